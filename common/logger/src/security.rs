@@ -48,6 +48,12 @@ pub enum SecurityEvent {
     /// A block being committed or executed is invalid
     InvalidBlock,
 
+    /// Consensus was asked to commit a block whose compute statuses do not match its transactions
+    InvalidComputeStatusConsensus,
+
+    /// Consensus received a transaction from mempool that it asked mempool to exclude
+    ExcludedTransactionConsensus,
+
     /// Network identified an invalid peer
     InvalidNetworkPeer,
 
@@ -8,6 +8,7 @@ use libra_config::{
     config::{
         BaseConfig, ConsensusConfig, NetworkConfig, NodeConfig, NodeConfigHelpers,
         PersistableConfig, RoleType, SafetyRulesBackend, SafetyRulesConfig, VMPublishingOption,
+        CURRENT_CONFIG_VERSION,
     },
     keys::{ConsensusKeyPair, NetworkKeyPairs},
     seed_peers::{SeedPeersConfig, SeedPeersConfigHelpers},
@@ -94,6 +95,7 @@ impl SwarmConfig {
             network_keypairs: NetworkKeyPairs::default(),
             network_peers: template_network.network_peers.clone(),
             seed_peers: template_network.seed_peers.clone(),
+            seed_peers_resolution: template_network.seed_peers_resolution,
         };
         let (mut private_keys, mut network_peers_config) =
             ConfigHelpers::gen_full_nodes(num_nodes, key_seed);
@@ -149,7 +151,8 @@ impl SwarmConfig {
         // Load contents of consensus peers file from upstream node.
         let consensus_peers_config = ConsensusPeersConfig::load_config(
             &upstream_config_dir.join(&upstream_peer_config.consensus.consensus_peers_file),
-        );
+        )
+        .expect("Unable to load the consensus peers of the upstream node");
         // NOTE: Need to restart upstream node with new configuration.
         let mut configs = Vec::new();
         // Generate configs for all nodes.
@@ -223,7 +226,7 @@ impl SwarmConfig {
             &faucet_key,
             &consensus_peers_config,
             &network_peers_config,
-        );
+        )?;
         // Extract peer addresses from seed peer config.
         let peer_addresses: BTreeMap<_, _> =
             seed_peers_config.seed_peers.clone().into_iter().collect();
@@ -350,6 +353,7 @@ impl SwarmConfig {
             safety_rules: safety_rules_config,
         };
         let mut config = NodeConfig {
+            version: CURRENT_CONFIG_VERSION,
             base: base_config,
             networks: vec![network_config],
             consensus: consensus_config,
@@ -492,7 +496,8 @@ impl SwarmConfigBuilder {
         }
 
         // read template
-        let mut template = NodeConfig::load_config(&self.template_path);
+        let mut template =
+            NodeConfig::load_config(&self.template_path).expect("Unable to load the template");
         // update everything in the template and then generate swarm config
         let listen_address = if self.is_ipv4 { "0.0.0.0" } else { "::1" };
         let listen_address = listen_address.to_string();
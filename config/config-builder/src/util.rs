@@ -1,8 +1,9 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use failure::prelude::*;
 use libra_config::{
-    config::{NodeConfig, NodeConfigHelpers},
+    config::NodeConfig,
     trusted_peers::{
         ConfigHelpers, ConsensusPeersConfig, ConsensusPrivateKey, NetworkPeersConfig,
         NetworkPrivateKeys,
@@ -19,27 +20,27 @@ pub fn gen_genesis_transaction(
     faucet_account_keypair: &KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
     consensus_peers_config: &ConsensusPeersConfig,
     network_peers_config: &NetworkPeersConfig,
-) -> SignatureCheckedTransaction {
-    encode_genesis_transaction_with_validator(
+) -> Result<SignatureCheckedTransaction> {
+    Ok(encode_genesis_transaction_with_validator(
         &faucet_account_keypair.private_key,
         faucet_account_keypair.public_key.clone(),
-        consensus_peers_config.get_validator_set(network_peers_config),
-    )
+        consensus_peers_config.get_validator_set(network_peers_config)?,
+    ))
 }
 
 pub fn gen_genesis_transaction_bytes(
     faucet_account_keypair: &KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
     consensus_peers_config: &ConsensusPeersConfig,
     network_peers_config: &NetworkPeersConfig,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
     let genesis_transaction = gen_genesis_transaction(
         faucet_account_keypair,
         consensus_peers_config,
         network_peers_config,
-    );
+    )?;
     let genesis_transaction: libra_types::proto::types::SignedTransaction =
         genesis_transaction.into();
-    genesis_transaction.to_vec().unwrap()
+    Ok(genesis_transaction.to_vec().unwrap())
 }
 
 /// Returns the validator keys, consensus peers, config, and the genesis keyapir
@@ -50,7 +51,7 @@ pub fn get_test_config_with_validators() -> (
     KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
 ) {
     // TODO: test config should be moved here instead of config crate
-    let config = NodeConfigHelpers::get_single_node_test_config(true);
+    let config = NodeConfig::default_for_test();
     // Those configs should be different on every call. We bypass the
     // costly StdRng initialization
     let mut seed_rng = rand::rngs::OsRng::new().expect("can't access OsRng");
@@ -61,7 +62,8 @@ pub fn get_test_config_with_validators() -> (
     let (validator_keys, test_consensus_peers, test_network_peers) =
         ConfigHelpers::gen_validator_nodes(1, None);
     let genesis_transaction =
-        gen_genesis_transaction_bytes(&keypair, &test_consensus_peers, &test_network_peers);
+        gen_genesis_transaction_bytes(&keypair, &test_consensus_peers, &test_network_peers)
+            .expect("[config] Failed to generate genesis txn");
     let mut genesis_transaction_file = File::create(config.get_genesis_transaction_file())
         .expect("[config] Failed to create file for storing genesis transaction");
     genesis_transaction_file
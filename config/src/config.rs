@@ -5,12 +5,16 @@ use crate::{
     keys::{ConsensusKeyPair, NetworkKeyPairs},
     seed_peers::SeedPeersConfigHelpers,
     trusted_peers::{ConfigHelpers, ConsensusPrivateKey, NetworkPrivateKeys},
-    utils::get_available_port,
+    utils::{check_bindable, get_available_port, multiaddr_to_socket_addr, write_file_atomic},
 };
 use failure::prelude::*;
+use libra_crypto::ed25519::compat;
+use libra_logger::prelude::*;
 use libra_tools::tempdir::TempPath;
 use libra_types::{
-    transaction::{SignedTransaction, Transaction},
+    account_config::association_address,
+    transaction::{RawTransaction, SignedTransaction, Transaction},
+    write_set::WriteSet,
     PeerId,
 };
 use prost::Message;
@@ -18,7 +22,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     convert::TryFrom,
     fs::File,
-    io::{Read, Write},
+    io::Read,
     path::{Path, PathBuf},
     str::FromStr,
     string::ToString,
@@ -27,10 +31,15 @@ use toml;
 
 mod admission_control_config;
 pub use admission_control_config::*;
+mod config_error;
+pub use config_error::*;
+mod config_source;
+pub use config_source::*;
 mod consensus_config;
 pub use consensus_config::*;
 mod debug_interface_config;
 pub use debug_interface_config::*;
+mod example_config;
 mod execution_config;
 pub use execution_config::*;
 mod logger_config;
@@ -39,8 +48,12 @@ mod metrics_config;
 pub use metrics_config::*;
 mod mempool_config;
 pub use mempool_config::*;
+mod mempool_proxy_config;
+pub use mempool_proxy_config::*;
 mod network_config;
 pub use network_config::*;
+mod reloadable_peers;
+pub use reloadable_peers::*;
 mod state_sync_config;
 pub use state_sync_config::*;
 mod storage_config;
@@ -54,6 +67,43 @@ pub use vm_config::*;
 #[path = "unit_tests/config_test.rs"]
 mod config_test;
 
+/// Version of the config format, bumped along with a migration in `MIGRATIONS` by every change
+/// that would break the loading of existing config files.
+pub const CURRENT_CONFIG_VERSION: u64 = 1;
+
+/// Migrations of the config format, the one at index `i` migrates raw configs from version `i`
+/// to version `i + 1`.
+const MIGRATIONS: &[fn(&mut toml::value::Table) -> Result<()>] = &[migrate_to_v1];
+
+// Version 1 introduces the version field.
+fn migrate_to_v1(_config: &mut toml::value::Table) -> Result<()> {
+    Ok(())
+}
+
+/// Environment variable that, when set, makes the loading of a config with unknown keys fail
+/// instead of only warning about them, e.g. for CI to catch typos.
+pub const DENY_UNKNOWN_KEYS_ENV: &str = "LIBRA_CONFIG_DENY_UNKNOWN_KEYS";
+
+/// How the keys of a config that no field of NodeConfig reads, e.g. typos, are treated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownKeys {
+    /// Every unknown key is logged as a warning and otherwise ignored.
+    Warn,
+    /// Unknown keys fail the loading of the config.
+    Deny,
+}
+
+impl UnknownKeys {
+    /// `Deny` if `DENY_UNKNOWN_KEYS_ENV` is set, `Warn` otherwise.
+    pub fn from_env() -> Self {
+        if std::env::var_os(DENY_UNKNOWN_KEYS_ENV).is_some() {
+            UnknownKeys::Deny
+        } else {
+            UnknownKeys::Warn
+        }
+    }
+}
+
 // path is relative to this file location
 static CONFIG_TEMPLATE: &[u8] = include_bytes!("../data/configs/node.config.toml");
 
@@ -64,6 +114,9 @@ static CONFIG_TEMPLATE: &[u8] = include_bytes!("../data/configs/node.config.toml
 #[cfg_attr(any(test, feature = "fuzzing"), derive(Clone))]
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct NodeConfig {
+    // Version of the config format, configs without it are assumed to be of version 0.
+    #[serde(default)]
+    pub version: u64,
     //TODO Add configuration for multiple chain's in a future diff
     #[serde(default)]
     pub base: BaseConfig,
@@ -98,6 +151,10 @@ pub struct BaseConfig {
     pub data_dir_path: PathBuf,
     #[serde(skip)]
     temp_data_dir: Option<TempPath>,
+    // Location of the config file the config was loaded from, which the files it references are
+    // loaded next to. Unset for configs that were not loaded.
+    #[serde(skip)]
+    config_path: Option<PathBuf>,
 }
 
 impl Default for BaseConfig {
@@ -105,6 +162,7 @@ impl Default for BaseConfig {
         BaseConfig {
             data_dir_path: PathBuf::from("."),
             temp_data_dir: None,
+            config_path: None,
         }
     }
 }
@@ -115,6 +173,7 @@ impl BaseConfig {
         BaseConfig {
             data_dir_path,
             temp_data_dir: None,
+            config_path: None,
         }
     }
 }
@@ -125,6 +184,7 @@ impl Clone for BaseConfig {
         Self {
             data_dir_path: self.data_dir_path.clone(),
             temp_data_dir: None,
+            config_path: self.config_path.clone(),
         }
     }
 }
@@ -133,10 +193,90 @@ impl NodeConfig {
     /// Reads the config file and returns the configuration object in addition to doing some
     /// post-processing of the config
     /// Paths used in the config are either absolute or relative to the config location
+    /// The environment overrides (see `apply_env_overrides`) are applied before the files
+    /// referenced by the config are loaded
+    /// Configs of older versions are migrated to the current one
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut config = Self::load_config(&path);
+        Self::load_from(&ConfigSource::File(path.as_ref().to_path_buf()))
+    }
+
+    /// Reads the config from a file, stdin or a URL and does the post-processing of `load`.
+    /// Paths used in a config read from stdin or a URL are either absolute or relative to the
+    /// working directory
+    pub fn load_from(source: &ConfigSource) -> Result<Self> {
+        Self::load_from_contents(source, &source.read()?)
+    }
+
+    fn load_from_contents(source: &ConfigSource, contents: &str) -> Result<Self> {
+        let value = toml::from_str(contents)
+            .map_err(|e| format_err!("Unable to parse config {}: {}", source, e))?;
+        Self::migrate(value)?.post_load(source.base_path())
+    }
+
+    /// Reads the base config file and deep merges the override files onto it, in order, before
+    /// doing the post-processing of `load`: tables are merged key by key, while any other value
+    /// of an override file, arrays included, replaces the value it overrides.
+    /// Paths used in the merged config are either absolute or relative to the base config location
+    pub fn load_with_overrides(base: &Path, overrides: &[&Path]) -> Result<Self> {
+        let mut value = read_toml(base)?;
+        for path in overrides {
+            merge_toml(&mut value, read_toml(path)?);
+        }
+        Self::migrate(value)?.post_load(base)
+    }
+
+    /// Parses a raw config after applying, in order, the migrations from its version to the
+    /// current one. Unknown keys are treated as told by `UnknownKeys::from_env`.
+    pub fn migrate(raw: toml::Value) -> Result<NodeConfig> {
+        Self::migrate_with(raw, UnknownKeys::from_env())
+    }
+
+    /// Same as `migrate`, with the keys of the migrated config that no field reads, listed with
+    /// their path, treated as told by `unknown_keys`.
+    pub fn migrate_with(mut raw: toml::Value, unknown_keys: UnknownKeys) -> Result<NodeConfig> {
+        let table = raw
+            .as_table_mut()
+            .ok_or_else(|| format_err!("Config is not a table"))?;
+        let version = match table.get("version") {
+            None => 0,
+            Some(version) => version
+                .as_integer()
+                .filter(|version| *version >= 0)
+                .ok_or_else(|| format_err!("Invalid config version {}", version))?
+                as u64,
+        };
+        ensure!(
+            version <= CURRENT_CONFIG_VERSION,
+            "Config version {} is newer than the supported version {}",
+            version,
+            CURRENT_CONFIG_VERSION
+        );
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            migration(table)?;
+            table.insert("version".to_string(), toml::Value::Integer(from as i64 + 1));
+            info!("Migrated config from version {} to {}", from, from + 1);
+        }
+        let config: NodeConfig = raw.clone().try_into()?;
+        // A key is unknown if it does not survive a round trip through NodeConfig.
+        let mut unknown = vec![];
+        find_unknown_keys(&raw, &toml::Value::try_from(&config)?, "", &mut unknown);
+        if !unknown.is_empty() {
+            match unknown_keys {
+                UnknownKeys::Warn => {
+                    for key in &unknown {
+                        warn!("Ignoring unknown config key {}", key);
+                    }
+                }
+                UnknownKeys::Deny => bail!("Unknown config keys: {}", unknown.join(", ")),
+            }
+        }
+        Ok(config)
+    }
+
+    fn post_load(mut self, path: &Path) -> Result<Self> {
+        self.apply_env_overrides()?;
         let mut validator_count = 0;
-        for network in &mut config.networks {
+        for network in &mut self.networks {
             // We use provided peer id for validator role. Otherwise peer id is generated using
             // network identity key.
             if network.role == RoleType::Validator {
@@ -144,14 +284,390 @@ impl NodeConfig {
                     validator_count, 0,
                     "At most 1 network config should be for a validator"
                 );
-                network.load(path.as_ref())?;
+                network.load(path)?;
                 validator_count += 1;
             } else {
-                network.load(path.as_ref())?;
+                network.load(path)?;
             }
         }
-        config.consensus.load(path.as_ref())?;
-        Ok(config)
+        self.consensus.load(path)?;
+        self.base.config_path = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Overwrites config fields with the values of the following environment variables, when
+    /// they are set:
+    /// - `LIBRA_DATA_DIR_PATH`: `base.data_dir_path`
+    /// - `LIBRA_GENESIS_FILE_LOCATION`: `execution.genesis_file_location`
+    /// - `LIBRA_STORAGE_DIR`: `storage.dir`
+    /// - `LIBRA_ADMISSION_CONTROL_SERVICE_PORT`: `admission_control.admission_control_service_port`
+    /// - `LIBRA_MEMPOOL_SERVICE_PORT`: `mempool.mempool_service_port`
+    /// - `LIBRA_CONSENSUS_KEYPAIR_FILE`: `consensus.consensus_keypair_file`
+    /// - `LIBRA_CONSENSUS_PEERS_FILE`: `consensus.consensus_peers_file`
+    /// - `LIBRA_NETWORK_LISTEN_ADDRESS`: `listen_address` of the first network
+    /// - `LIBRA_NETWORK_ADVERTISED_ADDRESS`: `advertised_address` of the first network
+    /// - `LIBRA_NETWORK_KEYPAIRS_FILE`: `network_keypairs_file` of the first network
+    /// - `LIBRA_NETWORK_PEERS_FILE`: `network_peers_file` of the first network
+    /// - `LIBRA_SEED_PEERS_FILE`: `seed_peers_file` of the first network
+    /// Other `LIBRA_*` variables are ignored. Fails on the first value that cannot be parsed into
+    /// the type of its field.
+    pub fn apply_env_overrides(&mut self) -> Result<()> {
+        env_override("LIBRA_DATA_DIR_PATH", &mut self.base.data_dir_path)?;
+        env_override(
+            "LIBRA_GENESIS_FILE_LOCATION",
+            &mut self.execution.genesis_file_location,
+        )?;
+        env_override("LIBRA_STORAGE_DIR", &mut self.storage.dir)?;
+        env_override(
+            "LIBRA_ADMISSION_CONTROL_SERVICE_PORT",
+            &mut self.admission_control.admission_control_service_port,
+        )?;
+        env_override(
+            "LIBRA_MEMPOOL_SERVICE_PORT",
+            &mut self.mempool.mempool_service_port,
+        )?;
+        env_override(
+            "LIBRA_CONSENSUS_KEYPAIR_FILE",
+            &mut self.consensus.consensus_keypair_file,
+        )?;
+        env_override(
+            "LIBRA_CONSENSUS_PEERS_FILE",
+            &mut self.consensus.consensus_peers_file,
+        )?;
+        if let Some(network) = self.networks.get_mut(0) {
+            env_override("LIBRA_NETWORK_LISTEN_ADDRESS", &mut network.listen_address)?;
+            env_override(
+                "LIBRA_NETWORK_ADVERTISED_ADDRESS",
+                &mut network.advertised_address,
+            )?;
+            env_override(
+                "LIBRA_NETWORK_KEYPAIRS_FILE",
+                &mut network.network_keypairs_file,
+            )?;
+            env_override("LIBRA_NETWORK_PEERS_FILE", &mut network.network_peers_file)?;
+            env_override("LIBRA_SEED_PEERS_FILE", &mut network.seed_peers_file)?;
+        }
+        Ok(())
+    }
+
+    /// Verifies that the data directory and every file referenced by the config exist and are
+    /// readable, reporting all the inaccessible paths at once. The files loaded next to the config
+    /// file are resolved as `load` does, see `resolve_config_file`.
+    pub fn validate(&self) -> std::result::Result<(), ConfigError> {
+        let mut inaccessible = vec![];
+        let mut check = |field: String, path: PathBuf, is_dir: bool| {
+            if let Err(reason) = Self::check_path(&path, is_dir) {
+                inaccessible.push(InaccessiblePath {
+                    field,
+                    path,
+                    reason,
+                });
+            }
+        };
+        check(
+            "base.data_dir_path".to_string(),
+            self.base.data_dir_path.clone(),
+            true,
+        );
+        check(
+            "execution.genesis_file_location".to_string(),
+            self.get_genesis_transaction_file(),
+            false,
+        );
+        let mut files = vec![
+            (
+                "consensus.consensus_keypair_file".to_string(),
+                &self.consensus.consensus_keypair_file,
+            ),
+            (
+                "consensus.consensus_peers_file".to_string(),
+                &self.consensus.consensus_peers_file,
+            ),
+        ];
+        for (index, network) in self.networks.iter().enumerate() {
+            files.push((
+                format!("networks[{}].network_keypairs_file", index),
+                &network.network_keypairs_file,
+            ));
+            files.push((
+                format!("networks[{}].network_peers_file", index),
+                &network.network_peers_file,
+            ));
+            files.push((
+                format!("networks[{}].seed_peers_file", index),
+                &network.seed_peers_file,
+            ));
+        }
+        for (field, path) in files {
+            // An empty path means that the file is not loaded.
+            if !path.as_os_str().is_empty() {
+                check(field, self.resolve_config_file(path), false);
+            }
+        }
+        if inaccessible.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::InaccessiblePaths(inaccessible))
+        }
+    }
+
+    /// Path of a file referenced by the config and loaded next to the config file, e.g. the
+    /// consensus keypair. For a config that was not loaded, the config file is assumed to be in
+    /// the data directory, which is where the config generation places both.
+    fn resolve_config_file(&self, file: &Path) -> PathBuf {
+        match &self.base.config_path {
+            Some(config_path) => config_path.with_file_name(file),
+            None => self.base.data_dir_path.join(file),
+        }
+    }
+
+    /// Checks that every listen address of the config can be bound, by binding to each of them
+    /// and releasing it right away, so that a port in use fails the startup before any service
+    /// is started. Lists every address that cannot be bound.
+    pub fn precheck_bindable(&self) -> std::result::Result<(), ConfigError> {
+        let mut unbindable = vec![];
+        let mut check = |field: &str, address: &str, port: u16| {
+            if let Err(e) = check_bindable((address, port)) {
+                unbindable.push(UnbindableAddress {
+                    field: field.to_string(),
+                    address: format!("{}:{}", address, port),
+                    reason: e.to_string(),
+                });
+            }
+        };
+        check(
+            "admission_control.admission_control_service_port",
+            &self.admission_control.address,
+            self.admission_control.admission_control_service_port,
+        );
+        let debug_interface = &self.debug_interface;
+        check(
+            "debug_interface.admission_control_node_debug_port",
+            &debug_interface.address,
+            debug_interface.admission_control_node_debug_port,
+        );
+        check(
+            "debug_interface.storage_node_debug_port",
+            &debug_interface.address,
+            debug_interface.storage_node_debug_port,
+        );
+        check(
+            "debug_interface.metrics_server_port",
+            &debug_interface.address,
+            debug_interface.metrics_server_port,
+        );
+        check(
+            "debug_interface.public_metrics_server_port",
+            &debug_interface.address,
+            debug_interface.public_metrics_server_port,
+        );
+        check(
+            "execution.port",
+            &self.execution.address,
+            self.execution.port,
+        );
+        check(
+            "mempool.mempool_service_port",
+            &self.mempool.address,
+            self.mempool.mempool_service_port,
+        );
+        check("storage.port", &self.storage.address, self.storage.port);
+        for (index, network) in self.networks.iter().enumerate() {
+            let result = match multiaddr_to_socket_addr(&network.listen_address) {
+                Some(addr) => check_bindable(addr).map_err(|e| e.to_string()),
+                None => Err("not an ip4 or ip6 address with a tcp port".to_string()),
+            };
+            if let Err(reason) = result {
+                unbindable.push(UnbindableAddress {
+                    field: format!("networks[{}].listen_address", index),
+                    address: network.listen_address.to_string(),
+                    reason,
+                });
+            }
+        }
+        if unbindable.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::UnbindableAddresses(unbindable))
+        }
+    }
+
+    /// Checks that the seed peers and the trusted peers of every permissioned network are the same
+    /// peers, the node itself aside: a seed peer that is not trusted is dialed only to be
+    /// rejected, and a trusted peer that is not a seed peer may never be dialed. Every peer in only
+    /// one of the sets is logged as a warning, or fails the check when `strict` is set.
+    pub fn check_peers_consistency(&self, strict: bool) -> std::result::Result<(), ConfigError> {
+        let mut inconsistent = vec![];
+        for (index, network) in self.networks.iter().enumerate() {
+            // Networks that are not permissioned accept peers that are not trusted.
+            if !network.is_permissioned {
+                continue;
+            }
+            let seed_peers = &network.seed_peers.seed_peers;
+            let trusted_peers = &network.network_peers.peers;
+            let mut report = |peer_id: &String, reason: &str| {
+                if *peer_id != network.peer_id {
+                    inconsistent.push(InconsistentPeer {
+                        network: format!("networks[{}]", index),
+                        peer_id: peer_id.clone(),
+                        reason: reason.to_string(),
+                    });
+                }
+            };
+            for peer_id in seed_peers.keys() {
+                if !trusted_peers.contains_key(peer_id) {
+                    report(peer_id, "seed peer missing from the trusted peers");
+                }
+            }
+            for peer_id in trusted_peers.keys() {
+                if !seed_peers.contains_key(peer_id) {
+                    report(peer_id, "trusted peer missing from the seed peers");
+                }
+            }
+        }
+        inconsistent.sort_by(|a, b| (&a.network, &a.peer_id).cmp(&(&b.network, &b.peer_id)));
+        if inconsistent.is_empty() {
+            Ok(())
+        } else if strict {
+            Err(ConfigError::InconsistentPeers(inconsistent))
+        } else {
+            for peer in inconsistent {
+                warn!("Peer {} of {}: {}", peer.peer_id, peer.network, peer.reason);
+            }
+            Ok(())
+        }
+    }
+
+    fn check_path(path: &Path, is_dir: bool) -> std::result::Result<(), String> {
+        let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+        if is_dir {
+            if !metadata.is_dir() {
+                return Err("not a directory".to_string());
+            }
+            std::fs::read_dir(path).map_err(|e| e.to_string())?;
+        } else {
+            if !metadata.is_file() {
+                return Err("not a file".to_string());
+            }
+            File::open(path).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Returns a single node test config with random ports and a temporary data dir, into which
+    /// the keys and peers referenced by the config are persisted, so that it passes `validate`.
+    /// The genesis transaction written along is an empty write set: nodes executing it need the
+    /// genesis of vm_genesis instead, which depends on this crate, to be written over it.
+    pub fn default_for_test() -> Self {
+        let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+        let dir = config.base.data_dir_path.clone();
+        let node_id = config.networks[0].peer_id.clone();
+        let consensus = &mut config.consensus;
+        consensus.consensus_keypair_file =
+            PathBuf::from(format!("{}.node.consensus.keys.toml", node_id));
+        consensus
+            .consensus_keypair
+            .save_config(dir.join(&consensus.consensus_keypair_file));
+        consensus.consensus_peers_file = PathBuf::from("consensus_peers.config.toml");
+        consensus
+            .consensus_peers
+            .save_config(dir.join(&consensus.consensus_peers_file));
+        for network in &mut config.networks {
+            let peer_id = network.peer_id.clone();
+            network.network_keypairs_file =
+                PathBuf::from(format!("{}.node.network.keys.toml", peer_id));
+            network
+                .network_keypairs
+                .save_config(dir.join(&network.network_keypairs_file));
+            network.network_peers_file =
+                PathBuf::from(format!("{}.network_peers.config.toml", peer_id));
+            network
+                .network_peers
+                .save_config(dir.join(&network.network_peers_file));
+            network.seed_peers_file = PathBuf::from(format!("{}.seed_peers.config.toml", peer_id));
+            network
+                .seed_peers
+                .save_config(dir.join(&network.seed_peers_file));
+        }
+        let (private_key, public_key) = compat::generate_keypair(None);
+        let genesis = RawTransaction::new_write_set(association_address(), 0, WriteSet::default())
+            .sign(&private_key, public_key)
+            .expect("Unable to sign the genesis transaction")
+            .into_inner();
+        let mut genesis_bytes = vec![];
+        libra_types::proto::types::SignedTransaction::from(genesis)
+            .encode(&mut genesis_bytes)
+            .expect("Unable to encode the genesis transaction");
+        write_file_atomic(config.get_genesis_transaction_file(), &genesis_bytes)
+            .expect("Unable to write the genesis transaction");
+        config
+    }
+
+    /// Returns the base config.
+    pub fn base(&self) -> &BaseConfig {
+        &self.base
+    }
+
+    /// Returns the metrics config.
+    pub fn metrics(&self) -> &MetricsConfig {
+        &self.metrics
+    }
+
+    /// Returns the execution config.
+    pub fn execution(&self) -> &ExecutionConfig {
+        &self.execution
+    }
+
+    /// Returns the admission control config.
+    pub fn admission_control(&self) -> &AdmissionControlConfig {
+        &self.admission_control
+    }
+
+    /// Returns the debug interface config.
+    pub fn debug_interface(&self) -> &DebugInterfaceConfig {
+        &self.debug_interface
+    }
+
+    /// Returns the storage config.
+    pub fn storage(&self) -> &StorageConfig {
+        &self.storage
+    }
+
+    /// Returns the configs of the networks the node is part of.
+    pub fn networks(&self) -> &[NetworkConfig] {
+        &self.networks
+    }
+
+    /// Returns the config of the network in which the node has the peer id `peer_id`.
+    pub fn network(&self, peer_id: &str) -> Option<&NetworkConfig> {
+        self.networks
+            .iter()
+            .find(|network| network.peer_id == peer_id)
+    }
+
+    /// Returns the consensus config.
+    pub fn consensus(&self) -> &ConsensusConfig {
+        &self.consensus
+    }
+
+    /// Returns the mempool config.
+    pub fn mempool(&self) -> &MempoolConfig {
+        &self.mempool
+    }
+
+    /// Returns the state sync config.
+    pub fn state_sync(&self) -> &StateSyncConfig {
+        &self.state_sync
+    }
+
+    /// Returns the logger config.
+    pub fn logger(&self) -> &LoggerConfig {
+        &self.logger
+    }
+
+    /// Returns the VM config.
+    pub fn vm_config(&self) -> &VMConfig {
+        &self.vm_config
     }
 
     /// Returns true if the node config is for a validator. Otherwise returns false.
@@ -178,6 +694,16 @@ impl NodeConfig {
             .last()
     }
 
+    /// Relocates the on-disk footprint of the node to `data_dir`, e.g. for tools spawning many
+    /// nodes from one config. The relative paths under the data directory, i.e. `storage.dir`,
+    /// `execution.genesis_file_location`, `metrics.dir` and `consensus.txn_log_file`, are resolved
+    /// against it by their accessors, so they all move with it, including on later calls. Absolute
+    /// paths, and the keypair and peers files resolved next to the config file, are left
+    /// untouched.
+    pub fn set_data_dir<P: Into<PathBuf>>(&mut self, data_dir: P) {
+        self.base.data_dir_path = data_dir.into();
+    }
+
     pub fn get_genesis_transaction_file(&self) -> PathBuf {
         let path = &self.execution.genesis_file_location;
         if path.is_relative() {
@@ -224,6 +750,16 @@ impl NodeConfig {
         }
     }
 
+    pub fn get_txn_log_file(&self) -> Option<PathBuf> {
+        self.consensus.txn_log_file.as_ref().map(|path| {
+            if path.is_relative() {
+                self.base.data_dir_path.join(path)
+            } else {
+                path.clone()
+            }
+        })
+    }
+
     /// Returns true if network_config is for an upstream network
     pub fn is_upstream_network(&self, network_config: &NetworkConfig) -> bool {
         self.state_sync
@@ -247,6 +783,82 @@ impl NodeConfig {
     }
 }
 
+fn read_toml(path: &Path) -> Result<toml::Value> {
+    let contents = ConfigSource::File(path.to_path_buf()).read()?;
+    toml::from_str(&contents).map_err(|e| format_err!("Unable to parse config {:?}: {}", path, e))
+}
+
+/// Merges `overlay` onto `base`: tables are merged key by key, any other value of `overlay`
+/// replaces the one of `base`.
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_toml(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Appends to `unknown` the path of every key of `raw` missing from `known`, the serialization of
+/// the config parsed from `raw`.
+fn find_unknown_keys(
+    raw: &toml::Value,
+    known: &toml::Value,
+    path: &str,
+    unknown: &mut Vec<String>,
+) {
+    match (raw, known) {
+        (toml::Value::Table(raw), toml::Value::Table(known)) => {
+            for (key, value) in raw {
+                let key_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match known.get(key) {
+                    Some(known_value) => find_unknown_keys(value, known_value, &key_path, unknown),
+                    None => unknown.push(key_path),
+                }
+            }
+        }
+        (toml::Value::Array(raw), toml::Value::Array(known)) => {
+            for (i, (value, known_value)) in raw.iter().zip(known).enumerate() {
+                find_unknown_keys(value, known_value, &format!("{}[{}]", path, i), unknown);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Overwrites `field` with the value of the environment variable `name`, if it is set.
+fn env_override<T>(name: &str, field: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = match std::env::var(name) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(()),
+        Err(e) => bail!("Invalid value of environment variable {}: {}", name, e),
+    };
+    *field = value.parse::<T>().map_err(|e| {
+        format_err!(
+            "Invalid value {:?} of environment variable {}: {}",
+            value,
+            name,
+            e
+        )
+    })?;
+    Ok(())
+}
+
 pub struct NodeConfigHelpers {}
 
 impl NodeConfigHelpers {
@@ -311,21 +923,19 @@ impl NodeConfigHelpers {
 }
 
 pub trait PersistableConfig: Serialize + DeserializeOwned {
-    // TODO: Return Result<Self> instead of panic.
-    fn load_config<P: AsRef<Path>>(path: P) -> Self {
+    fn load_config<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let mut file =
-            File::open(path).unwrap_or_else(|_| panic!("Cannot open config file {:?}", path));
+        let mut file = File::open(path)
+            .map_err(|e| format_err!("Cannot open config file {:?}: {}", path, e))?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)
-            .unwrap_or_else(|_| panic!("Error reading config file {:?}", path));
-        Self::parse(&contents).expect("Unable to parse config")
+            .map_err(|e| format_err!("Error reading config file {:?}: {}", path, e))?;
+        Self::parse(&contents).map_err(|e| format_err!("Unable to parse config {:?}: {}", path, e))
     }
 
     fn save_config<P: AsRef<Path>>(&self, output_file: P) {
         let contents = toml::to_vec(&self).expect("Error serializing");
-        let mut file = File::create(output_file).expect("Error opening file");
-        file.write_all(&contents).expect("Error writing file");
+        write_file_atomic(output_file, &contents).expect("Error writing file");
     }
 
     fn parse(serialized: &str) -> Result<Self> {
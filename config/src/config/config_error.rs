@@ -0,0 +1,54 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use failure::prelude::*;
+use std::path::PathBuf;
+
+/// Errors possible during the validation of a NodeConfig.
+#[derive(Debug, Fail, PartialEq)]
+pub enum ConfigError {
+    #[fail(display = "Inaccessible paths in config: {:?}", _0)]
+    /// Paths referenced by the config that do not exist or cannot be read, all of them are
+    /// reported at once.
+    InaccessiblePaths(Vec<InaccessiblePath>),
+    #[fail(display = "Unbindable listen addresses in config: {:?}", _0)]
+    /// Listen addresses of the config that cannot be bound, e.g. because their port is in use,
+    /// all of them are reported at once.
+    UnbindableAddresses(Vec<UnbindableAddress>),
+    #[fail(display = "Inconsistent seed and trusted peers in config: {:?}", _0)]
+    /// Peers of a network that are only in one of its seed peers and trusted peers, all of them
+    /// are reported at once.
+    InconsistentPeers(Vec<InconsistentPeer>),
+}
+
+/// A path referenced by the config that does not exist or cannot be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InaccessiblePath {
+    /// The config field referencing the path, e.g. `consensus.consensus_keypair_file`.
+    pub field: String,
+    /// The path, as resolved against the data directory.
+    pub path: PathBuf,
+    /// Why the path cannot be accessed.
+    pub reason: String,
+}
+
+/// A listen address of the config that cannot be bound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnbindableAddress {
+    /// The config field holding the port of the address, e.g. `storage.port`.
+    pub field: String,
+    /// The address, as `host:port` or as a multiaddr.
+    pub address: String,
+    /// Why the address cannot be bound.
+    pub reason: String,
+}
+
+/// A peer of a network that is only in one of its seed peers and trusted peers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InconsistentPeer {
+    /// The config of the network, e.g. `networks[0]`.
+    pub network: String,
+    pub peer_id: String,
+    /// Which set the peer is missing from.
+    pub reason: String,
+}
@@ -0,0 +1,116 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use failure::prelude::*;
+use std::{
+    convert::Infallible,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Upper bound on the time to fetch a config from a URL.
+pub const URL_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a config is read from: a file path, `-` for stdin, or an `http(s)://` URL.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigSource {
+    /// A config file, paths used in the config are relative to its location.
+    File(PathBuf),
+    /// The standard input, paths used in the config are relative to the working directory.
+    Stdin,
+    /// An `http://` or `https://` URL, paths used in the config are relative to the working
+    /// directory. Fetching requires the `url-config` feature.
+    Url(String),
+}
+
+impl ConfigSource {
+    /// The path the paths used in the config are relative to.
+    pub fn base_path(&self) -> &Path {
+        match self {
+            ConfigSource::File(path) => path,
+            ConfigSource::Stdin | ConfigSource::Url(_) => Path::new("./"),
+        }
+    }
+
+    /// Reads the whole config from the source.
+    pub fn read(&self) -> Result<String> {
+        self.read_from(io::stdin())
+    }
+
+    /// Reads the whole config from the source, with `stdin` standing for the standard input.
+    pub(crate) fn read_from<R: Read>(&self, mut stdin: R) -> Result<String> {
+        let mut contents = String::new();
+        match self {
+            ConfigSource::File(path) => {
+                File::open(path)
+                    .and_then(|mut file| file.read_to_string(&mut contents))
+                    .map_err(|e| format_err!("Error reading config file {:?}: {}", path, e))?;
+            }
+            ConfigSource::Stdin => {
+                stdin
+                    .read_to_string(&mut contents)
+                    .map_err(|e| format_err!("Error reading config from stdin: {}", e))?;
+            }
+            ConfigSource::Url(url) => contents = fetch(url)?,
+        }
+        Ok(contents)
+    }
+}
+
+impl FromStr for ConfigSource {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(if s == "-" {
+            ConfigSource::Stdin
+        } else if s.starts_with("http://") || s.starts_with("https://") {
+            ConfigSource::Url(s.to_string())
+        } else {
+            ConfigSource::File(PathBuf::from(s))
+        })
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "{:?}", path),
+            ConfigSource::Stdin => write!(f, "stdin"),
+            ConfigSource::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+#[cfg(feature = "url-config")]
+fn fetch(url: &str) -> Result<String> {
+    let client = reqwest::Client::builder()
+        .timeout(URL_FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format_err!("Cannot build a client to fetch config from {}: {}", url, e))?;
+    let mut response = client
+        .get(url)
+        .send()
+        .map_err(|e| format_err!("Cannot fetch config from {}: {}", url, e))?;
+    let status = response.status();
+    ensure!(
+        status.is_success(),
+        "Cannot fetch config from {}: HTTP {}",
+        url,
+        status
+    );
+    response
+        .text()
+        .map_err(|e| format_err!("Error reading config from {}: {}", url, e))
+}
+
+#[cfg(not(feature = "url-config"))]
+fn fetch(url: &str) -> Result<String> {
+    bail!(
+        "Cannot fetch config from {}: built without the url-config feature",
+        url
+    )
+}
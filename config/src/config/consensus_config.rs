@@ -2,9 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    config::{PersistableConfig, SafetyRulesBackend, SafetyRulesConfig},
+    config::{MempoolProxyConfig, PersistableConfig, SafetyRulesBackend, SafetyRulesConfig},
     keys::ConsensusKeyPair,
     trusted_peers::ConsensusPeersConfig,
+    units::deserialize_opt_millis,
 };
 use failure::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -18,6 +19,7 @@ pub struct ConsensusConfig {
     pub proposer_type: ConsensusProposerType,
     pub contiguous_rounds: u32,
     pub max_pruned_blocks_in_mem: Option<u64>,
+    #[serde(deserialize_with = "deserialize_opt_millis")]
     pub pacemaker_initial_timeout_ms: Option<u64>,
     // consensus_keypair contains the node's consensus keypair.
     // it is filled later on from consensus_keypair_file.
@@ -28,6 +30,10 @@ pub struct ConsensusConfig {
     pub consensus_peers: ConsensusPeersConfig,
     pub consensus_peers_file: PathBuf,
     pub safety_rules: SafetyRulesConfig,
+    pub mempool_proxy: MempoolProxyConfig,
+    // Log the pulled and committed payloads are recorded to for post-incident analysis, relative
+    // paths are relative to the data directory. Nothing is recorded when unset.
+    pub txn_log_file: Option<PathBuf>,
 }
 
 impl Default for ConsensusConfig {
@@ -43,6 +49,8 @@ impl Default for ConsensusConfig {
             consensus_peers: ConsensusPeersConfig::default(),
             consensus_peers_file: PathBuf::from("consensus_peers.config.toml"),
             safety_rules: SafetyRulesConfig::default(),
+            mempool_proxy: MempoolProxyConfig::default(),
+            txn_log_file: None,
         }
     }
 }
@@ -63,12 +71,12 @@ impl ConsensusConfig {
         if !self.consensus_keypair_file.as_os_str().is_empty() {
             self.consensus_keypair = ConsensusKeyPair::load_config(
                 path.as_ref().with_file_name(&self.consensus_keypair_file),
-            );
+            )?;
         }
         if !self.consensus_peers_file.as_os_str().is_empty() {
             self.consensus_peers = ConsensusPeersConfig::load_config(
                 path.as_ref().with_file_name(&self.consensus_peers_file),
-            );
+            )?;
         }
         if let SafetyRulesBackend::OnDiskStorage {
             default,
@@ -0,0 +1,484 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{NetworkConfig, NodeConfig, CURRENT_CONFIG_VERSION};
+use std::fmt::Write;
+
+/// Max width of the comment lines of the example config.
+const COMMENT_WIDTH: usize = 98;
+
+/// Description of every field of the example config, by path. The fields of the elements of an
+/// array of tables, e.g. `networks`, are described once for all the elements.
+pub(crate) const FIELD_DOCS: &[(&str, &str)] = &[
+    (
+        "version",
+        "Version of the config format, configs of older versions are migrated on load.",
+    ),
+    (
+        "base.data_dir_path",
+        "Directory the node persists its data to.",
+    ),
+    ("metrics.dir", "Directory the metrics are written to."),
+    (
+        "metrics.collection_interval_ms",
+        "Interval at which the metrics are collected.",
+    ),
+    (
+        "execution.address",
+        "Address the execution service listens on.",
+    ),
+    ("execution.port", "Port the execution service listens on."),
+    (
+        "execution.genesis_file_location",
+        "Genesis transaction file, relative to the config file.",
+    ),
+    (
+        "admission_control.address",
+        "Address admission control listens on.",
+    ),
+    (
+        "admission_control.admission_control_service_port",
+        "Port admission control listens on.",
+    ),
+    (
+        "admission_control.need_to_check_mempool_before_validation",
+        "Whether submitted transactions are rejected when mempool is full before being validated.",
+    ),
+    (
+        "admission_control.max_concurrent_inbound_syncs",
+        "Max number of transaction submissions an upstream proxy processes at once.",
+    ),
+    (
+        "admission_control.upstream_proxy_timeout.secs",
+        "Seconds of the timeout of the submissions forwarded by an upstream proxy.",
+    ),
+    (
+        "admission_control.upstream_proxy_timeout.nanos",
+        "Nanoseconds of the timeout of the submissions forwarded by an upstream proxy.",
+    ),
+    (
+        "debug_interface.admission_control_node_debug_port",
+        "Port of the debug interface of admission control.",
+    ),
+    (
+        "debug_interface.storage_node_debug_port",
+        "Port of the debug interface of storage.",
+    ),
+    (
+        "debug_interface.metrics_server_port",
+        "Port the metrics are served on.",
+    ),
+    (
+        "debug_interface.public_metrics_server_port",
+        "Port the metrics safe to publish are served on.",
+    ),
+    (
+        "debug_interface.address",
+        "Address the debug interfaces listen on.",
+    ),
+    ("storage.address", "Address the storage service listens on."),
+    ("storage.port", "Port the storage service listens on."),
+    (
+        "storage.dir",
+        "Directory of the database, relative to the data directory.",
+    ),
+    (
+        "storage.grpc_max_receive_len",
+        "Max size of the messages the storage client receives.",
+    ),
+    (
+        "networks.peer_id",
+        "Peer id of the node on the network, generated from the network identity key of full \
+         nodes when empty.",
+    ),
+    (
+        "networks.listen_address",
+        "Address the node listens on for new connections.",
+    ),
+    (
+        "networks.advertised_address",
+        "Address the node advertises to the other nodes for the discovery protocol.",
+    ),
+    (
+        "networks.discovery_interval_ms",
+        "Interval of the discovery protocol.",
+    ),
+    (
+        "networks.connectivity_check_interval_ms",
+        "Interval at which the connections to the known peers are checked.",
+    ),
+    (
+        "networks.enable_encryption_and_authentication",
+        "Whether Noise is used for encryption and authentication.",
+    ),
+    (
+        "networks.is_permissioned",
+        "Whether only trusted peers are allowed to connect, requires \
+         enable_encryption_and_authentication.",
+    ),
+    (
+        "networks.role",
+        "Role of the node in the network, one of \"validator\" and \"full_node\".",
+    ),
+    (
+        "networks.network_keypairs_file",
+        "Network key pairs file, relative to the config file.",
+    ),
+    (
+        "networks.network_peers_file",
+        "Trusted peers file, relative to the config file.",
+    ),
+    (
+        "networks.seed_peers_file",
+        "Seed peers file of the discovery protocol, relative to the config file.",
+    ),
+    (
+        "networks.seed_peers_resolution",
+        "When the DNS hosts of the seed peer addresses are resolved, one of \"eager\" and \
+         \"lazy\".",
+    ),
+    (
+        "consensus.max_block_size",
+        "Max number of transactions of a proposed block.",
+    ),
+    (
+        "consensus.proposer_type",
+        "How proposers are elected, one of \"fixed_proposer\", \"rotating_proposer\" and \
+         \"multiple_ordered_proposers\".",
+    ),
+    (
+        "consensus.contiguous_rounds",
+        "Number of contiguous rounds a proposer is elected for.",
+    ),
+    (
+        "consensus.consensus_keypair_file",
+        "Consensus key pair file, relative to the config file.",
+    ),
+    (
+        "consensus.consensus_peers_file",
+        "Consensus peers file, relative to the config file.",
+    ),
+    (
+        "consensus.safety_rules.backend.type",
+        "Storage of the safety rules state, one of \"InMemoryStorage\" and \"OnDiskStorage\", \
+         which also takes a path and a default flag.",
+    ),
+    (
+        "consensus.mempool_proxy.pull_timeout_ms",
+        "Upper bound on the time to wait for a block from mempool, an elapsed pull is treated \
+         as an empty block.",
+    ),
+    (
+        "consensus.mempool_proxy.pull_max_retries",
+        "Number of times a pull is retried when mempool is unavailable.",
+    ),
+    (
+        "consensus.mempool_proxy.pull_retry_base_delay_ms",
+        "Delay before the first pull retry, doubled on every subsequent retry.",
+    ),
+    (
+        "consensus.mempool_proxy.max_commit_batch",
+        "Max number of committed transactions sent to mempool in a single request.",
+    ),
+    (
+        "consensus.mempool_proxy.ping_timeout_ms",
+        "Deadline of the health check verifying that mempool is reachable on startup.",
+    ),
+    (
+        "consensus.mempool_proxy.sort_by_gas_price",
+        "Whether pulled transactions are sorted by gas unit price, highest first.",
+    ),
+    (
+        "consensus.mempool_proxy.num_prologue_statuses",
+        "Number of leading compute statuses of the transactions injected by the executor.",
+    ),
+    (
+        "consensus.mempool_proxy.exclude_by_hash",
+        "Whether pull requests also exclude pending transactions by hash, so that their \
+         replacements are not excluded.",
+    ),
+    (
+        "consensus.mempool_proxy.max_in_flight_requests",
+        "Max number of requests awaiting a response from mempool.",
+    ),
+    (
+        "consensus.mempool_proxy.max_block_size_limit",
+        "Upper bound on the size of the blocks pulled from mempool.",
+    ),
+    (
+        "consensus.mempool_proxy.min_commit_timestamp_usecs",
+        "Block timestamp below which commits are rejected, 0 disables the check.",
+    ),
+    (
+        "consensus.mempool_proxy.dry_run",
+        "Whether commit requests are built without being sent to mempool.",
+    ),
+    (
+        "consensus.mempool_proxy.bytes_per_block_sample_interval",
+        "One committed block out of every this many has its size observed, 0 disables the \
+         observation.",
+    ),
+    (
+        "consensus.mempool_proxy.sender_share_alert_percent",
+        "Share of a pulled block above which its top senders are logged, 0 disables the \
+         accounting.",
+    ),
+    (
+        "consensus.mempool_proxy.sender_share_top_senders",
+        "Number of senders logged when a pulled block crosses the alert share.",
+    ),
+    (
+        "consensus.mempool_proxy.invalid_txn_log_window_ms",
+        "Window within which the invalid or excluded transactions returned by mempool are \
+         logged once, 0 logs every one of them.",
+    ),
+    (
+        "consensus.mempool_proxy.max_txn_bytes",
+        "Max serialized size of a pulled transaction, larger ones are dropped undecoded.",
+    ),
+    (
+        "consensus.mempool_proxy.recently_pulled_window",
+        "Number of the last pulled transactions a pulled transaction is dropped for being among, \
+         0 disables the check.",
+    ),
+    (
+        "consensus.mempool_proxy.pull_all_max_txns",
+        "Max number of transactions drained from mempool by a pull of every remaining one.",
+    ),
+    (
+        "consensus.mempool_proxy.adaptive_pull.enabled",
+        "Whether the size of the pulled blocks adapts to the latency of mempool.",
+    ),
+    (
+        "consensus.mempool_proxy.adaptive_pull.slow_latency_ms",
+        "Pulls shrink while the average latency of the recent pulls exceeds it.",
+    ),
+    (
+        "consensus.mempool_proxy.adaptive_pull.min_size",
+        "Size pulls shrink down to at most.",
+    ),
+    (
+        "consensus.mempool_proxy.adaptive_pull.max_size",
+        "Size pulls grow back up to at most, also the size of the first pull.",
+    ),
+    (
+        "mempool.broadcast_transactions",
+        "Whether transactions are broadcast to the other nodes.",
+    ),
+    (
+        "mempool.shared_mempool_tick_interval_ms",
+        "Interval of the broadcasts to the other nodes.",
+    ),
+    (
+        "mempool.shared_mempool_batch_size",
+        "Max number of transactions broadcast at once.",
+    ),
+    (
+        "mempool.shared_mempool_max_concurrent_inbound_syncs",
+        "Max number of broadcasts from the other nodes processed at once.",
+    ),
+    ("mempool.capacity", "Max number of transactions of mempool."),
+    (
+        "mempool.capacity_per_user",
+        "Max number of transactions of mempool per sender.",
+    ),
+    (
+        "mempool.system_transaction_timeout_secs",
+        "Time after which a transaction is evicted from mempool.",
+    ),
+    (
+        "mempool.system_transaction_gc_interval_ms",
+        "Interval at which expired transactions are evicted.",
+    ),
+    (
+        "mempool.mempool_service_port",
+        "Port the mempool service listens on.",
+    ),
+    ("mempool.address", "Address the mempool service listens on."),
+    (
+        "state_sync.chunk_limit",
+        "Number of transactions requested at once from a peer.",
+    ),
+    (
+        "state_sync.tick_interval_ms",
+        "Interval at which the progress of state synchronization is checked.",
+    ),
+    (
+        "state_sync.long_poll_timeout_ms",
+        "Timeout of the long polls of a peer.",
+    ),
+    (
+        "state_sync.max_chunk_limit",
+        "Max number of transactions a peer may request at once.",
+    ),
+    (
+        "state_sync.max_timeout_ms",
+        "Max timeout of the long polls of a peer.",
+    ),
+    (
+        "state_sync.upstream_peers",
+        "Peer ids of the peers to synchronize from.",
+    ),
+    ("logger.is_async", "Whether logging is asynchronous."),
+    (
+        "logger.chan_size",
+        "Number of log records buffered by asynchronous logging.",
+    ),
+    (
+        "vm_config.publishing_options.type",
+        "Which scripts and modules may be run and published, one of \"Locked\", \
+         \"CustomScripts\" and \"Open\".",
+    ),
+    (
+        "vm_config.publishing_options.whitelist",
+        "Hashes of the scripts allowed to run, when locked.",
+    ),
+];
+
+/// Fields unset by default, by path, with their description and an example value. They are
+/// commented out in the example config.
+pub(crate) const UNSET_FIELDS: &[(&str, &str, &str)] = &[
+    (
+        "consensus.max_pruned_blocks_in_mem",
+        "Max number of pruned blocks kept in memory, 10000 when unset.",
+        "10000",
+    ),
+    (
+        "consensus.pacemaker_initial_timeout_ms",
+        "Timeout of the first round, 1000 when unset.",
+        "1000",
+    ),
+    (
+        "consensus.txn_log_file",
+        "Log the pulled and committed payloads are recorded to, relative to the data directory. \
+         Nothing is recorded when unset.",
+        "\"txn.log\"",
+    ),
+    (
+        "consensus.mempool_proxy.connection.concurrency",
+        "Number of completion queues polling the channel to mempool.",
+        "4",
+    ),
+    (
+        "consensus.mempool_proxy.connection.keepalive_time_ms",
+        "Interval of the keepalive pings sent on an idle channel to mempool.",
+        "10000",
+    ),
+    (
+        "consensus.mempool_proxy.connection.keepalive_timeout_ms",
+        "Time to wait for the acknowledgement of a keepalive ping before closing the channel.",
+        "5000",
+    ),
+    (
+        "consensus.mempool_proxy.connection.max_message_len",
+        "Max size of the messages sent to and received from mempool.",
+        "104857600",
+    ),
+];
+
+impl NodeConfig {
+    /// A config setting every field to its default, with a network, and documenting every field
+    /// in comments, for operators to discover the available keys. The fields unset by default
+    /// are included commented out. The example parses back into `example()`.
+    pub fn example_toml() -> String {
+        render_example().0
+    }
+
+    /// The config `example_toml` describes.
+    pub fn example() -> NodeConfig {
+        let mut config: NodeConfig = toml::from_str("").expect("Every field has a default");
+        config.version = CURRENT_CONFIG_VERSION;
+        config.networks.push(NetworkConfig::default());
+        config
+    }
+}
+
+/// The example config, along with the paths of the fields it sets.
+pub(crate) fn render_example() -> (String, Vec<String>) {
+    let value = toml::Value::try_from(&NodeConfig::example()).expect("Configs serialize to TOML");
+    let table = value.as_table().expect("Configs serialize to a table");
+    let mut out = String::from(
+        "# Example node config, every field is set to its default.\n\
+         # Fields unset by default are commented out.\n",
+    );
+    let mut paths = vec![];
+    render_table(&mut out, "", table, &mut paths);
+    (out, paths)
+}
+
+fn render_table(out: &mut String, path: &str, table: &toml::value::Table, paths: &mut Vec<String>) {
+    let (tables, values): (Vec<_>, Vec<_>) =
+        table.iter().partition(|(_, value)| is_table_like(value));
+    // The keys of a table come before its subtables.
+    for (key, value) in values {
+        let field_path = join(path, key);
+        render_comment(out, doc(&field_path).unwrap_or(""));
+        writeln!(out, "{} = {}", key, value).expect("Writing to a string cannot fail");
+        paths.push(field_path);
+    }
+    for (field_path, field_doc, example) in UNSET_FIELDS {
+        let (parent, key) = field_path.split_at(field_path.rfind('.').unwrap_or(0));
+        if parent == path {
+            render_comment(out, field_doc);
+            writeln!(out, "# {} = {}", key.trim_start_matches('.'), example)
+                .expect("Writing to a string cannot fail");
+        }
+    }
+    for (key, value) in tables {
+        let table_path = join(path, key);
+        match value {
+            toml::Value::Table(table) => {
+                writeln!(out, "\n[{}]", table_path).expect("Writing to a string cannot fail");
+                render_table(out, &table_path, table, paths);
+            }
+            toml::Value::Array(array) => {
+                for element in array.iter().filter_map(toml::Value::as_table) {
+                    writeln!(out, "\n[[{}]]", table_path).expect("Writing to a string cannot fail");
+                    render_table(out, &table_path, element, paths);
+                }
+            }
+            _ => unreachable!("Only tables and arrays of tables are rendered as sections"),
+        }
+    }
+}
+
+fn is_table_like(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Table(_) => true,
+        toml::Value::Array(array) => !array.is_empty() && array.iter().all(toml::Value::is_table),
+        _ => false,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn doc(path: &str) -> Option<&'static str> {
+    FIELD_DOCS
+        .iter()
+        .find(|(field_path, _)| *field_path == path)
+        .map(|(_, doc)| *doc)
+}
+
+/// Writes `text` as comment lines of at most `COMMENT_WIDTH` characters.
+fn render_comment(out: &mut String, text: &str) {
+    let mut line = String::from("#");
+    for word in text.split_whitespace() {
+        if line.len() > 1 && line.len() + 1 + word.len() > COMMENT_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line = String::from("#");
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    if line.len() > 1 {
+        out.push_str(&line);
+        out.push('\n');
+    }
+}
@@ -1,19 +1,23 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::units::{deserialize_millis, deserialize_secs};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default)]
 pub struct MempoolConfig {
     pub broadcast_transactions: bool,
+    #[serde(deserialize_with = "deserialize_millis")]
     pub shared_mempool_tick_interval_ms: u64,
     pub shared_mempool_batch_size: usize,
     pub shared_mempool_max_concurrent_inbound_syncs: usize,
     pub capacity: usize,
     // max number of transactions per user in Mempool
     pub capacity_per_user: usize,
+    #[serde(deserialize_with = "deserialize_secs")]
     pub system_transaction_timeout_secs: u64,
+    #[serde(deserialize_with = "deserialize_millis")]
     pub system_transaction_gc_interval_ms: u64,
     pub mempool_service_port: u16,
     pub address: String,
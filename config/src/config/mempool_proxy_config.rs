@@ -0,0 +1,150 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::units::{
+    deserialize_bytes, deserialize_millis, deserialize_opt_bytes, deserialize_opt_millis,
+};
+use serde::{Deserialize, Serialize};
+
+/// Configuration of the proxy consensus uses to talk to mempool.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MempoolProxyConfig {
+    // Upper bound on the time to wait for a block from mempool, an elapsed pull is treated
+    // as an empty block.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub pull_timeout_ms: u64,
+    // Number of times a pull is retried when mempool is unavailable.
+    pub pull_max_retries: u32,
+    // Delay before the first pull retry, doubled on every subsequent retry.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub pull_retry_base_delay_ms: u64,
+    // Max number of committed transactions sent to mempool in a single request, larger blocks
+    // are split into several requests.
+    pub max_commit_batch: usize,
+    // Deadline of the health check used to verify that mempool is reachable on startup.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub ping_timeout_ms: u64,
+    // Sort pulled transactions by gas unit price (highest first) instead of keeping the order
+    // mempool returned them in.
+    pub sort_by_gas_price: bool,
+    // Number of leading compute statuses that belong to transactions injected by the executor
+    // (e.g. the block prologue) rather than to transactions pulled from mempool.
+    pub num_prologue_statuses: usize,
+    // Attach the hash of every excluded transaction to pull requests, so that mempool does not
+    // exclude transactions replacing the pending ones.
+    pub exclude_by_hash: bool,
+    // Max number of pull and commit requests awaiting a response from mempool, further requests
+    // wait for one of them to complete.
+    pub max_in_flight_requests: usize,
+    // Upper bound on the size of the blocks pulled from mempool, larger pulls are clamped to it.
+    pub max_block_size_limit: u64,
+    // Commits with a block timestamp below it are rejected, so that a corrupted timestamp cannot
+    // make mempool GC every transaction, 0 disables the check. A plausible floor is the start of
+    // the network, e.g. 1546300800000000 for 2019-01-01.
+    pub min_commit_timestamp_usecs: u64,
+    // Build commit requests and record their metrics without sending them to mempool, e.g. for
+    // a shadow validator that must not mutate the state of mempool.
+    pub dry_run: bool,
+    // Observe the serialized size of one committed block out of every this many, 0 disables the
+    // observation. Serializing every transaction of large blocks may be too expensive to do for
+    // every block.
+    pub bytes_per_block_sample_interval: u64,
+    // Log the top senders of a pulled block when a single sender contributed more than this
+    // percentage of its transactions, 0 disables the accounting.
+    pub sender_share_alert_percent: u64,
+    // Number of senders listed when a pulled block crosses `sender_share_alert_percent`.
+    pub sender_share_top_senders: usize,
+    // Invalid or excluded transactions returned by mempool within this window of the last one
+    // logged are coalesced into the next security log line, 0 logs every one of them.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub invalid_txn_log_window_ms: u64,
+    // Max serialized size of a pulled transaction, larger transactions are dropped before being
+    // decoded so that a malicious mempool cannot make consensus allocate for them.
+    #[serde(deserialize_with = "deserialize_bytes")]
+    pub max_txn_bytes: usize,
+    // Number of the last pulled transactions remembered by sender and sequence number, a pulled
+    // transaction among them is dropped as it may already be proposed, 0 disables the check.
+    // Mempool exclusions may race with pulls and return a transaction pulled for the previous
+    // proposal.
+    pub recently_pulled_window: usize,
+    // Max number of transactions `pull_all` drains from mempool, e.g. at epoch boundaries, it
+    // stops pulling once it has accumulated that many.
+    pub pull_all_max_txns: u64,
+    pub adaptive_pull: AdaptivePullConfig,
+    pub connection: MempoolConnConfig,
+}
+
+impl Default for MempoolProxyConfig {
+    fn default() -> Self {
+        Self {
+            pull_timeout_ms: 1000,
+            pull_max_retries: 3,
+            pull_retry_base_delay_ms: 10,
+            max_commit_batch: 10_000,
+            ping_timeout_ms: 1000,
+            sort_by_gas_price: false,
+            num_prologue_statuses: 1,
+            exclude_by_hash: false,
+            max_in_flight_requests: 100,
+            max_block_size_limit: 10_000,
+            min_commit_timestamp_usecs: 0,
+            dry_run: false,
+            bytes_per_block_sample_interval: 1,
+            sender_share_alert_percent: 0,
+            sender_share_top_senders: 5,
+            invalid_txn_log_window_ms: 1000,
+            max_txn_bytes: 1 << 20,
+            recently_pulled_window: 0,
+            pull_all_max_txns: 100_000,
+            adaptive_pull: AdaptivePullConfig::default(),
+            connection: MempoolConnConfig::default(),
+        }
+    }
+}
+
+/// Adaptation of the size of the blocks pulled from mempool to its latency, so that blocks stay
+/// small while mempool is slow and grow back once it recovers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct AdaptivePullConfig {
+    // Whether the size of the pulled blocks adapts to the latency of mempool.
+    pub enabled: bool,
+    // Pulls shrink while the average latency of the recent pulls exceeds it, and grow back
+    // otherwise.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub slow_latency_ms: u64,
+    // Size pulls shrink down to at most.
+    pub min_size: u64,
+    // Size pulls grow back up to at most, also the size of the first pull.
+    pub max_size: u64,
+}
+
+impl Default for AdaptivePullConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            slow_latency_ms: 250,
+            min_size: 100,
+            max_size: 10_000,
+        }
+    }
+}
+
+/// Tuning of the grpc channel consensus connects to mempool with, unset values fall back to the
+/// grpc defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MempoolConnConfig {
+    // Number of completion queues polling the channel.
+    pub concurrency: Option<usize>,
+    // Interval of the keepalive pings sent on an idle channel.
+    #[serde(deserialize_with = "deserialize_opt_millis")]
+    pub keepalive_time_ms: Option<u64>,
+    // Time to wait for the acknowledgement of a keepalive ping before closing the channel.
+    #[serde(deserialize_with = "deserialize_opt_millis")]
+    pub keepalive_timeout_ms: Option<u64>,
+    // Max size of the messages sent to and received from mempool.
+    #[serde(deserialize_with = "deserialize_opt_bytes")]
+    pub max_message_len: Option<i32>,
+}
@@ -1,6 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::units::deserialize_millis;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -8,6 +9,7 @@ use std::path::PathBuf;
 #[serde(default)]
 pub struct MetricsConfig {
     pub dir: PathBuf,
+    #[serde(deserialize_with = "deserialize_millis")]
     pub collection_interval_ms: u64,
 }
 
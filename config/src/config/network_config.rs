@@ -2,8 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    config::PersistableConfig, keys::NetworkKeyPairs, seed_peers::SeedPeersConfig,
-    trusted_peers::NetworkPeersConfig, utils::get_local_ip,
+    config::PersistableConfig,
+    keys::NetworkKeyPairs,
+    seed_peers::SeedPeersConfig,
+    trusted_peers::NetworkPeersConfig,
+    units::deserialize_millis,
+    utils::{get_local_ip, ip_to_multiaddr},
 };
 use failure::prelude::*;
 use libra_crypto::ValidKey;
@@ -12,7 +16,7 @@ use parity_multiaddr::Multiaddr;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::TryFrom,
-    fmt,
+    fmt, fs,
     path::{Path, PathBuf},
     string::ToString,
 };
@@ -37,6 +41,16 @@ where
     }
 }
 
+/// When the DNS hosts of the seed peer addresses are resolved.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeedPeersResolution {
+    // While loading the config.
+    Eager,
+    // Right before the network starts dialing the seed peers.
+    Lazy,
+}
+
 impl fmt::Display for RoleType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -56,7 +70,9 @@ pub struct NetworkConfig {
     pub listen_address: Multiaddr,
     // The address that this node advertises to other nodes for the discovery protocol.
     pub advertised_address: Multiaddr,
+    #[serde(deserialize_with = "deserialize_millis")]
     pub discovery_interval_ms: u64,
+    #[serde(deserialize_with = "deserialize_millis")]
     pub connectivity_check_interval_ms: u64,
     // Flag to toggle if Noise is used for encryption and authentication.
     pub enable_encryption_and_authentication: bool,
@@ -80,6 +96,8 @@ pub struct NetworkConfig {
     #[serde(skip)]
     pub seed_peers: SeedPeersConfig,
     pub seed_peers_file: PathBuf,
+    // When the DNS hosts of the seed peer addresses are resolved. One of: {"eager", "lazy"}.
+    pub seed_peers_resolution: SeedPeersResolution,
 }
 
 impl Default for NetworkConfig {
@@ -99,6 +117,7 @@ impl Default for NetworkConfig {
             network_peers: NetworkPeersConfig::default(),
             seed_peers_file: PathBuf::from("seed_peers.config.toml"),
             seed_peers: SeedPeersConfig::default(),
+            seed_peers_resolution: SeedPeersResolution::Eager,
         }
     }
 }
@@ -108,24 +127,33 @@ impl NetworkConfig {
         if !self.network_peers_file.as_os_str().is_empty() {
             self.network_peers = NetworkPeersConfig::load_config(
                 path.as_ref().with_file_name(&self.network_peers_file),
-            );
+            )?;
         }
         if !self.network_keypairs_file.as_os_str().is_empty() {
             self.network_keypairs = NetworkKeyPairs::load_config(
                 path.as_ref().with_file_name(&self.network_keypairs_file),
-            );
+            )?;
         }
         if !self.seed_peers_file.as_os_str().is_empty() {
-            self.seed_peers =
-                SeedPeersConfig::load_config(path.as_ref().with_file_name(&self.seed_peers_file));
+            let seed_peers_file = path.as_ref().with_file_name(&self.seed_peers_file);
+            let contents = fs::read_to_string(&seed_peers_file).map_err(|e| {
+                format_err!("Cannot read seed peers file {:?}: {}", seed_peers_file, e)
+            })?;
+            self.seed_peers = SeedPeersConfig::parse_validated(&contents)
+                .map_err(|e| format_err!("Invalid seed peers file {:?}: {}", seed_peers_file, e))?;
+        }
+        if self.seed_peers_resolution == SeedPeersResolution::Eager {
+            self.seed_peers.resolve();
         }
         if self.advertised_address.to_string().is_empty() {
-            self.advertised_address =
-                get_local_ip().ok_or_else(|| ::failure::err_msg("No local IP"))?;
+            self.advertised_address = get_local_ip()
+                .map(ip_to_multiaddr)
+                .ok_or_else(|| ::failure::err_msg("No local IP"))?;
         }
         if self.listen_address.to_string().is_empty() {
-            self.listen_address =
-                get_local_ip().ok_or_else(|| ::failure::err_msg("No local IP"))?;
+            self.listen_address = get_local_ip()
+                .map(ip_to_multiaddr)
+                .ok_or_else(|| ::failure::err_msg("No local IP"))?;
         }
         // If PeerId is not set, it is derived from NetworkIdentityKey.
         if self.peer_id == "" {
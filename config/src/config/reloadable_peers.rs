@@ -0,0 +1,183 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    config::{NodeConfig, SeedPeersResolution},
+    seed_peers::{SeedPeersConfig, SharedSeedPeers},
+    trusted_peers::{
+        ConsensusPeersConfig, FilePeersSource, NetworkPeersConfig, TrustedPeersSource,
+    },
+};
+use failure::prelude::*;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// The peer sets of a node, shared with the components using them and reloadable from their
+/// sources without restarting the node, e.g. by a SIGHUP handler. The trusted peer sets are read
+/// from the peer files of the config unless another source is set, e.g. the sets published
+/// on-chain.
+#[derive(Clone, Debug)]
+pub struct ReloadablePeers {
+    consensus_peers_source: Option<Arc<dyn TrustedPeersSource<ConsensusPeersConfig>>>,
+    consensus_peers: Arc<RwLock<ConsensusPeersConfig>>,
+    networks: Vec<ReloadableNetworkPeers>,
+}
+
+#[derive(Clone, Debug)]
+struct ReloadableNetworkPeers {
+    peer_id: String,
+    network_peers_source: Option<Arc<dyn TrustedPeersSource<NetworkPeersConfig>>>,
+    network_peers: Arc<RwLock<NetworkPeersConfig>>,
+    seed_peers_file: Option<PathBuf>,
+    seed_peers_resolution: SeedPeersResolution,
+    seed_peers: SharedSeedPeers,
+}
+
+impl NodeConfig {
+    /// Returns the peer sets of this config, reloadable from the peer files of the config loaded
+    /// from `path`.
+    pub fn reloadable_peers<P: AsRef<Path>>(&self, path: P) -> ReloadablePeers {
+        let path = path.as_ref();
+        let peers_file = |file: &Path| {
+            if file.as_os_str().is_empty() {
+                None
+            } else {
+                Some(path.with_file_name(file))
+            }
+        };
+        ReloadablePeers {
+            consensus_peers_source: peers_file(&self.consensus.consensus_peers_file).map(
+                |file| -> Arc<dyn TrustedPeersSource<_>> {
+                    Arc::new(FilePeersSource::consensus_peers(file))
+                },
+            ),
+            consensus_peers: Arc::new(RwLock::new(self.consensus.consensus_peers.clone())),
+            networks: self
+                .networks
+                .iter()
+                .map(|network| ReloadableNetworkPeers {
+                    peer_id: network.peer_id.clone(),
+                    network_peers_source: peers_file(&network.network_peers_file).map(
+                        |file| -> Arc<dyn TrustedPeersSource<_>> {
+                            Arc::new(FilePeersSource::network_peers(file))
+                        },
+                    ),
+                    network_peers: Arc::new(RwLock::new(network.network_peers.clone())),
+                    seed_peers_file: peers_file(&network.seed_peers_file),
+                    seed_peers_resolution: network.seed_peers_resolution,
+                    seed_peers: SharedSeedPeers::new(network.seed_peers.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ReloadablePeers {
+    /// Reloads the consensus peer set from `source` rather than from the consensus peers file.
+    pub fn set_consensus_peers_source(
+        &mut self,
+        source: Arc<dyn TrustedPeersSource<ConsensusPeersConfig>>,
+    ) {
+        self.consensus_peers_source = Some(source);
+    }
+
+    /// Reloads the network peer set of the network of `peer_id` from `source` rather than from
+    /// its network peers file.
+    pub fn set_network_peers_source(
+        &mut self,
+        peer_id: &str,
+        source: Arc<dyn TrustedPeersSource<NetworkPeersConfig>>,
+    ) -> Result<()> {
+        let network = self
+            .networks
+            .iter_mut()
+            .find(|network| network.peer_id == peer_id)
+            .ok_or_else(|| format_err!("No network of peer id {}", peer_id))?;
+        network.network_peers_source = Some(source);
+        Ok(())
+    }
+
+    /// Fetches the peer sets from their sources and swaps them in. Every set is fetched before
+    /// any is swapped, so that if one of them cannot be fetched all the sets are left intact.
+    pub fn reload(&self) -> Result<()> {
+        let consensus_peers = match &self.consensus_peers_source {
+            Some(source) => Some(source.fetch()?),
+            None => None,
+        };
+        let mut networks = Vec::with_capacity(self.networks.len());
+        for network in &self.networks {
+            let network_peers = match &network.network_peers_source {
+                Some(source) => Some(source.fetch()?),
+                None => None,
+            };
+            let seed_peers = match &network.seed_peers_file {
+                Some(file) => {
+                    let mut seed_peers = FilePeersSource::new(
+                        file.clone(),
+                        "seed peers",
+                        SeedPeersConfig::parse_validated,
+                    )
+                    .fetch()?;
+                    if network.seed_peers_resolution == SeedPeersResolution::Eager {
+                        seed_peers.resolve();
+                    }
+                    Some(seed_peers)
+                }
+                None => None,
+            };
+            networks.push((network_peers, seed_peers));
+        }
+
+        if let Some(consensus_peers) = consensus_peers {
+            *self
+                .consensus_peers
+                .write()
+                .expect("Consensus peers lock is poisoned") = consensus_peers;
+        }
+        for (network, (network_peers, seed_peers)) in self.networks.iter().zip(networks) {
+            if let Some(network_peers) = network_peers {
+                *network
+                    .network_peers
+                    .write()
+                    .expect("Network peers lock is poisoned") = network_peers;
+            }
+            if let Some(seed_peers) = seed_peers {
+                network.seed_peers.set(seed_peers);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the current consensus peer set.
+    pub fn consensus_peers(&self) -> ConsensusPeersConfig {
+        self.consensus_peers
+            .read()
+            .expect("Consensus peers lock is poisoned")
+            .clone()
+    }
+
+    /// Returns a copy of the current network peer set of the network of `peer_id`.
+    pub fn network_peers(&self, peer_id: &str) -> Option<NetworkPeersConfig> {
+        self.network(peer_id).map(|network| {
+            network
+                .network_peers
+                .read()
+                .expect("Network peers lock is poisoned")
+                .clone()
+        })
+    }
+
+    /// Returns the seed peer set of the network of `peer_id`.
+    pub fn seed_peers(&self, peer_id: &str) -> Option<SharedSeedPeers> {
+        self.network(peer_id)
+            .map(|network| network.seed_peers.clone())
+    }
+
+    fn network(&self, peer_id: &str) -> Option<&ReloadableNetworkPeers> {
+        self.networks
+            .iter()
+            .find(|network| network.peer_id == peer_id)
+    }
+}
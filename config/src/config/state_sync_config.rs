@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::trusted_peers::UpstreamPeersConfig;
+use crate::{trusted_peers::UpstreamPeersConfig, units::deserialize_millis};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -10,12 +10,15 @@ pub struct StateSyncConfig {
     // Size of chunk to request for state synchronization
     pub chunk_limit: u64,
     // interval used for checking state synchronization progress
+    #[serde(deserialize_with = "deserialize_millis")]
     pub tick_interval_ms: u64,
     // default timeout used for long polling to remote peer
+    #[serde(deserialize_with = "deserialize_millis")]
     pub long_poll_timeout_ms: u64,
     // valid maximum chunk limit for sanity check
     pub max_chunk_limit: u64,
     // valid maximum timeout limit for sanity check
+    #[serde(deserialize_with = "deserialize_millis")]
     pub max_timeout_ms: u64,
     // List of peers to use as upstream in state sync protocols.
     #[serde(flatten)]
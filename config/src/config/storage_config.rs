@@ -1,6 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::units::deserialize_opt_bytes;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -10,6 +11,7 @@ pub struct StorageConfig {
     pub address: String,
     pub port: u16,
     pub dir: PathBuf,
+    #[serde(deserialize_with = "deserialize_opt_bytes")]
     pub grpc_max_receive_len: Option<i32>,
 }
 
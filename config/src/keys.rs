@@ -1,18 +1,90 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::trusted_peers::{deserialize_key, serialize_key};
+use crate::{
+    config::PersistableConfig,
+    trusted_peers::{deserialize_key, serialize_key},
+    utils::write_file_atomic,
+};
+use clear_on_drop::clear::Clear;
+use failure::{format_err, Fail};
 use libra_crypto::{
     ed25519::*,
+    slip0010::Slip0010,
     test_utils::TEST_SEED,
+    traits::CryptoMaterialError,
     x25519::{self, X25519StaticPrivateKey, X25519StaticPublicKey},
-    PrivateKey, ValidKeyStringExt,
+    HashValue, PrivateKey, PublicKey, Signature, ValidKey, ValidKeyStringExt,
 };
 use mirai_annotations::verify_unreachable;
 use rand::{rngs::StdRng, SeedableRng};
+use rayon::prelude::*;
+use ring::{
+    aead::{self, Aad, LessSafeKey, Nonce, UnboundKey},
+    constant_time, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    convert::TryFrom,
+    fmt, fs, io,
+    num::NonZeroU32,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
 
-#[derive(Debug, PartialEq)]
+#[cfg(test)]
+#[path = "unit_tests/keys_test.rs"]
+mod keys_test;
+
+/// Printed in place of private keys, so that formatting a config never leaks them into logs.
+struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+/// Temporary buffer of private key material, e.g. the bytes a key is encoded to or decoded from,
+/// overwritten with zeros when dropped so that the copy does not linger in freed memory. The
+/// parsed keys, e.g. the private key of a ConsensusKeyPair, are held by the types of libra_crypto
+/// and are not covered.
+pub struct SecretBytes<T: AsRef<[u8]> + AsMut<[u8]>>(T);
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> SecretBytes<T> {
+    pub fn new(bytes: T) -> Self {
+        SecretBytes(bytes)
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Deref for SecretBytes<T> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> DerefMut for SecretBytes<T> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.0.as_mut()
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Drop for SecretBytes<T> {
+    fn drop(&mut self) {
+        self.0.as_mut().clear();
+    }
+}
+
+#[derive(PartialEq)]
 #[cfg_attr(any(test, feature = "fuzzing"), derive(Clone))]
 enum PrivateKeyContainer<T> {
     Present(T),
@@ -20,6 +92,16 @@ enum PrivateKeyContainer<T> {
     Absent,
 }
 
+impl<T> fmt::Debug for PrivateKeyContainer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrivateKeyContainer::Present(_) => fmt::Debug::fmt(&Redacted, f),
+            PrivateKeyContainer::Removed => write!(f, "Removed"),
+            PrivateKeyContainer::Absent => write!(f, "Absent"),
+        }
+    }
+}
+
 impl<T> PrivateKeyContainer<T>
 where
     T: PrivateKey,
@@ -72,7 +154,7 @@ where
 
 // NetworkKeyPairs is used to store a node's Network specific keypairs.
 // It is filled via a config file at the moment.
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Deserialize, PartialEq, Serialize)]
 #[cfg_attr(any(test, feature = "fuzzing"), derive(Clone))]
 pub struct NetworkKeyPairs {
     network_signing_private_key: PrivateKeyContainer<Ed25519PrivateKey>,
@@ -103,6 +185,26 @@ impl Default for NetworkKeyPairs {
     }
 }
 
+impl fmt::Debug for NetworkKeyPairs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NetworkKeyPairs")
+            .field(
+                "network_signing_private_key",
+                &self.network_signing_private_key,
+            )
+            .field(
+                "network_signing_public_key",
+                &self.network_signing_public_key,
+            )
+            .field("network_identity_private_key", &Redacted)
+            .field(
+                "network_identity_public_key",
+                &self.network_identity_public_key,
+            )
+            .finish()
+    }
+}
+
 impl NetworkKeyPairs {
     // used in testing to fill the structure with test keypairs
     pub fn load(
@@ -220,3 +322,834 @@ where
         .map_err(<D::Error as serde::de::Error>::custom)
         .map(Some)
 }
+
+/// Loads a private key from the environment variable `var_name`, hex encoded as in the key files,
+/// e.g. to inject keys from a secret manager rather than writing them to disk.
+pub fn load_from_env<K: ValidKeyStringExt>(var_name: &str) -> failure::Result<K> {
+    let encoded_key = std::env::var(var_name).map_err(|e| {
+        format_err!(
+            "Unable to read key from environment variable {}: {}",
+            var_name,
+            e
+        )
+    })?;
+    let bytes = hex::decode(encoded_key.trim())
+        .map(SecretBytes::new)
+        .map_err(|e| {
+            format_err!(
+                "Key in environment variable {} is not hex encoded: {}",
+                var_name,
+                e
+            )
+        })?;
+    K::try_from(&bytes[..]).map_err(|e| {
+        format_err!(
+            "Invalid key of {} bytes in environment variable {}: {}",
+            bytes.len(),
+            var_name,
+            e
+        )
+    })
+}
+
+/// Default upper bound on the time a key file operation may take, see `set_io_timeout`.
+pub const DEFAULT_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Timeout of the key file operations in milliseconds, 0 for none.
+static IO_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_IO_TIMEOUT.as_millis() as u64);
+
+/// Sets the upper bound on the time the key files of this module may take to be read or
+/// written, after which the operation fails with `KeyFileTimeout` instead of blocking the caller,
+/// e.g. the node boot, on a hung network file system. `None` waits forever.
+pub fn set_io_timeout(timeout: Option<Duration>) {
+    let timeout_ms = timeout.map_or(0, |timeout| std::cmp::max(timeout.as_millis() as u64, 1));
+    IO_TIMEOUT_MS.store(timeout_ms, Ordering::Relaxed);
+}
+
+/// The upper bound set by `set_io_timeout`.
+pub fn io_timeout() -> Option<Duration> {
+    match IO_TIMEOUT_MS.load(Ordering::Relaxed) {
+        0 => None,
+        timeout_ms => Some(Duration::from_millis(timeout_ms)),
+    }
+}
+
+/// A key file operation that did not complete within the timeout set by `set_io_timeout`.
+#[derive(Clone, Debug, Fail, PartialEq)]
+#[fail(
+    display = "Timed out after {:?} accessing key file {:?}",
+    timeout, path
+)]
+pub struct KeyFileTimeout {
+    pub path: PathBuf,
+    pub timeout: Duration,
+}
+
+/// Runs the file operation `op` on `path` on a dedicated thread, giving up on it after
+/// `timeout`. A blocked system call cannot be interrupted: the thread of an operation that timed
+/// out is left behind, and its result discarded whenever it completes.
+fn run_with_timeout<T, F>(
+    path: &Path,
+    timeout: Option<Duration>,
+    op: F,
+) -> Result<io::Result<T>, KeyFileTimeout>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(op()),
+    };
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let spawned = thread::Builder::new()
+        .name("key-file-io".to_string())
+        .spawn(move || {
+            // The receiver is gone if the operation timed out.
+            let _ = sender.send(op());
+        });
+    if let Err(e) = spawned {
+        return Ok(Err(e));
+    }
+    receiver.recv_timeout(timeout).map_err(|_| KeyFileTimeout {
+        path: path.to_path_buf(),
+        timeout,
+    })
+}
+
+fn read_key_file(path: &Path) -> failure::Result<String> {
+    let owned_path = path.to_path_buf();
+    Ok(run_with_timeout(path, io_timeout(), move || {
+        fs::read_to_string(owned_path)
+    })??)
+}
+
+fn write_key_file(path: &Path, contents: Vec<u8>) -> failure::Result<()> {
+    let owned_path = path.to_path_buf();
+    Ok(run_with_timeout(path, io_timeout(), move || {
+        write_file_atomic(owned_path, &contents)
+    })??)
+}
+
+/// Errors possible when saving or loading a passphrase encrypted key file.
+#[derive(Debug, Fail, PartialEq)]
+pub enum EncryptedKeyError {
+    #[fail(display = "Wrong passphrase, or the key file was tampered with")]
+    /// The key file cannot be decrypted with the passphrase.
+    WrongPassphrase,
+    #[fail(display = "Malformed key file: {}", _0)]
+    /// The key file is not an encrypted key file.
+    MalformedFile(String),
+    #[fail(display = "Invalid key: {}", _0)]
+    /// The decrypted key is not a valid key of the requested type.
+    InvalidKey(CryptoMaterialError),
+    #[fail(display = "Unable to access key file: {}", _0)]
+    /// The key file cannot be read or written.
+    Io(String),
+    #[fail(display = "{}", _0)]
+    /// The key file was not read or written in time.
+    Timeout(KeyFileTimeout),
+}
+
+impl From<failure::Error> for EncryptedKeyError {
+    fn from(error: failure::Error) -> Self {
+        match error.downcast::<KeyFileTimeout>() {
+            Ok(timeout) => EncryptedKeyError::Timeout(timeout),
+            Err(error) => EncryptedKeyError::Io(error.to_string()),
+        }
+    }
+}
+
+// Number of PBKDF2 iterations deriving the encryption key of new key files from the passphrase.
+const KDF_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// On disk format of a passphrase encrypted key: the key is sealed with ChaCha20-Poly1305, under
+/// a key derived from the passphrase and the salt with PBKDF2-HMAC-SHA256. Binary fields are hex
+/// encoded.
+#[derive(Deserialize, Serialize)]
+struct EncryptedKeyFile {
+    kdf_iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_sealing_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key_bytes = SecretBytes::new([0u8; 32]);
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key_bytes,
+    );
+    let key = UnboundKey::new(&aead::CHACHA20_POLY1305, &key_bytes)
+        .expect("ChaCha20-Poly1305 keys are 32 bytes long");
+    LessSafeKey::new(key)
+}
+
+/// Saves `key` to `path`, encrypted with `passphrase` so that the file is useless without it.
+pub fn save_encrypted<K: ValidKey>(
+    key: &K,
+    path: &Path,
+    passphrase: &str,
+) -> Result<(), EncryptedKeyError> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| EncryptedKeyError::Io("Unable to generate randomness".to_string()))?;
+    let iterations = NonZeroU32::new(KDF_ITERATIONS).expect("Iterations are not zero");
+    let sealing_key = derive_sealing_key(passphrase, &salt, iterations);
+    // The key is sealed in place, in a buffer large enough to never reallocate and leave a copy
+    // of the key behind.
+    let key_bytes = SecretBytes::new(key.to_bytes());
+    let mut ciphertext = Vec::with_capacity(key_bytes.len() + aead::CHACHA20_POLY1305.tag_len());
+    ciphertext.extend_from_slice(&key_bytes);
+    sealing_key
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut ciphertext,
+        )
+        .expect("Keys are short enough to be sealed");
+    let file = EncryptedKeyFile {
+        kdf_iterations: KDF_ITERATIONS,
+        salt: hex::encode(&salt),
+        nonce: hex::encode(&nonce),
+        ciphertext: hex::encode(&ciphertext),
+    };
+    let contents = toml::to_vec(&file).map_err(|e| EncryptedKeyError::Io(e.to_string()))?;
+    Ok(write_key_file(path, contents)?)
+}
+
+/// Loads a key saved by `save_encrypted` from `path`, decrypting it with `passphrase`.
+pub fn load_encrypted<K: ValidKey>(path: &Path, passphrase: &str) -> Result<K, EncryptedKeyError> {
+    let contents = read_key_file(path)?;
+    let file: EncryptedKeyFile = EncryptedKeyFile::parse(&contents)
+        .map_err(|e| EncryptedKeyError::MalformedFile(e.to_string()))?;
+    let decode = |field: &str, value: &str| {
+        hex::decode(value)
+            .map_err(|e| EncryptedKeyError::MalformedFile(format!("{}: {}", field, e)))
+    };
+    let salt = decode("salt", &file.salt)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    let nonce_bytes = decode("nonce", &file.nonce)?;
+    if nonce_bytes.len() != NONCE_LEN {
+        return Err(EncryptedKeyError::MalformedFile(format!(
+            "nonce of {} bytes",
+            nonce_bytes.len()
+        )));
+    }
+    nonce.copy_from_slice(&nonce_bytes);
+    let mut ciphertext = SecretBytes::new(decode("ciphertext", &file.ciphertext)?);
+    let iterations = NonZeroU32::new(file.kdf_iterations)
+        .ok_or_else(|| EncryptedKeyError::MalformedFile("zero kdf iterations".to_string()))?;
+    let opening_key = derive_sealing_key(passphrase, &salt, iterations);
+    let key_bytes = opening_key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut ciphertext,
+        )
+        .map_err(|_| EncryptedKeyError::WrongPassphrase)?;
+    K::try_from(&key_bytes[..]).map_err(EncryptedKeyError::InvalidKey)
+}
+
+/// Saves `key` to `path` hex encoded and unencrypted, for backward compatibility with tooling
+/// reading plain key files. Prefer `save_encrypted`.
+pub fn save_plaintext<K: ValidKeyStringExt>(key: &K, path: &Path) -> failure::Result<()> {
+    write_key_file(path, key.to_encoded_string()?.into_bytes())
+}
+
+/// Loads a key saved by `save_plaintext` from `path`.
+pub fn load_plaintext<K: ValidKeyStringExt>(path: &Path) -> failure::Result<K> {
+    let encoded_key = read_key_file(path)?;
+    K::from_encoded_string(encoded_key.trim())
+        .map_err(|e| format_err!("Invalid key in {:?}: {}", path, e))
+}
+
+/// Compares the encodings of two keys in constant time, see `constant_time_eq_bytes`.
+pub fn constant_time_eq<K: ValidKey>(a: &K, b: &K) -> bool {
+    constant_time_eq_bytes(&a.to_bytes(), &b.to_bytes())
+}
+
+/// Compares two byte strings in constant time: the time taken depends on their lengths only, not
+/// on the position of their first difference, so that matching a presented key against a trusted
+/// one does not leak the trusted key through timing.
+pub fn constant_time_eq_bytes(a: &[u8], b: &[u8]) -> bool {
+    constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
+/// Number of bytes of the hash of a public key kept in its fingerprint.
+const FINGERPRINT_BYTES: usize = 8;
+
+/// Short fingerprint of a public key, for operators to tell keys apart at a glance: the first
+/// bytes of the SHA3-256 hash of the key encoding, as colon separated hex pairs, e.g.
+/// `05:4f:34:1a:2f:a5:84:bb`. It is the same on every platform.
+/// Being truncated, a fingerprint only identifies a key for humans: it must not be relied upon to
+/// authenticate a key, compare the keys themselves (see `constant_time_eq`) for that.
+pub fn fingerprint<K: PublicKey + ValidKey>(public_key: &K) -> String {
+    HashValue::from_sha3_256(&public_key.to_bytes()).to_vec()[..FINGERPRINT_BYTES]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Key types with a PEM encoding, for exchanging keys with OpenSSL based tooling, e.g. HSMs and
+/// signing tools. Private keys are encoded as unencrypted PKCS#8 `PRIVATE KEY`s and public keys as
+/// `PUBLIC KEY` SubjectPublicKeyInfos, with the algorithm identifiers of RFC 8410, as written by
+/// `openssl genpkey -algorithm ed25519` and `openssl pkey -pubout`.
+pub trait PemKey: ValidKey {
+    /// Name of the key type, in errors.
+    const KEY_TYPE: &'static str;
+    /// Label of the PEM boundaries.
+    const PEM_LABEL: &'static str;
+    /// DER encoding of the key up to the key bytes, which end it.
+    const DER_PREFIX: &'static [u8];
+}
+
+const PRIVATE_KEY_PEM_LABEL: &str = "PRIVATE KEY";
+const PUBLIC_KEY_PEM_LABEL: &str = "PUBLIC KEY";
+
+impl PemKey for Ed25519PrivateKey {
+    const KEY_TYPE: &'static str = "Ed25519 private key";
+    const PEM_LABEL: &'static str = PRIVATE_KEY_PEM_LABEL;
+    const DER_PREFIX: &'static [u8] = &[
+        0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04,
+        0x20,
+    ];
+}
+
+impl PemKey for Ed25519PublicKey {
+    const KEY_TYPE: &'static str = "Ed25519 public key";
+    const PEM_LABEL: &'static str = PUBLIC_KEY_PEM_LABEL;
+    const DER_PREFIX: &'static [u8] = &[
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+    ];
+}
+
+impl PemKey for X25519StaticPrivateKey {
+    const KEY_TYPE: &'static str = "X25519 private key";
+    const PEM_LABEL: &'static str = PRIVATE_KEY_PEM_LABEL;
+    const DER_PREFIX: &'static [u8] = &[
+        0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x6e, 0x04, 0x22, 0x04,
+        0x20,
+    ];
+}
+
+impl PemKey for X25519StaticPublicKey {
+    const KEY_TYPE: &'static str = "X25519 public key";
+    const PEM_LABEL: &'static str = PUBLIC_KEY_PEM_LABEL;
+    const DER_PREFIX: &'static [u8] = &[
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x6e, 0x03, 0x21, 0x00,
+    ];
+}
+
+/// Label, DER prefix and name of every PemKey type, to name the type of a mismatched key.
+const PEM_KEY_TYPES: &[(&str, &[u8], &str)] = &[
+    (
+        Ed25519PrivateKey::PEM_LABEL,
+        Ed25519PrivateKey::DER_PREFIX,
+        Ed25519PrivateKey::KEY_TYPE,
+    ),
+    (
+        Ed25519PublicKey::PEM_LABEL,
+        Ed25519PublicKey::DER_PREFIX,
+        Ed25519PublicKey::KEY_TYPE,
+    ),
+    (
+        X25519StaticPrivateKey::PEM_LABEL,
+        X25519StaticPrivateKey::DER_PREFIX,
+        X25519StaticPrivateKey::KEY_TYPE,
+    ),
+    (
+        X25519StaticPublicKey::PEM_LABEL,
+        X25519StaticPublicKey::DER_PREFIX,
+        X25519StaticPublicKey::KEY_TYPE,
+    ),
+];
+
+/// Max length of the base64 lines of a PEM, as per RFC 7468.
+const PEM_LINE_LEN: usize = 64;
+
+/// Errors possible when decoding a PEM encoded key.
+#[derive(Debug, Fail, PartialEq)]
+pub enum PemError {
+    #[fail(display = "Malformed PEM: {}", _0)]
+    /// The input is not PEM encoded.
+    Malformed(String),
+    #[fail(display = "Expected {}, found {}", expected, found)]
+    /// The PEM holds a key of another type than the requested one.
+    KeyTypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[fail(display = "Unsupported {} in PEM, expected {}", label, expected)]
+    /// The PEM holds a key of an algorithm or an encoding not supported.
+    Unsupported {
+        expected: &'static str,
+        label: String,
+    },
+    #[fail(display = "Invalid key: {}", _0)]
+    /// The PEM holds invalid key bytes.
+    InvalidKey(CryptoMaterialError),
+}
+
+/// Encodes `key` in PEM, see `PemKey`.
+pub fn to_pem<K: PemKey>(key: &K) -> String {
+    let mut der = K::DER_PREFIX.to_vec();
+    der.extend(key.to_bytes());
+    let der = SecretBytes::new(der);
+    let encoded = base64::encode(&*der);
+    let mut pem = format!("-----BEGIN {}-----\n", K::PEM_LABEL);
+    for line in encoded.as_bytes().chunks(PEM_LINE_LEN) {
+        // Base64 is ASCII.
+        pem.push_str(std::str::from_utf8(line).expect("Invalid base64"));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", K::PEM_LABEL));
+    pem
+}
+
+/// Decodes a key encoded in PEM, see `PemKey`. Text around the PEM boundaries is ignored, as
+/// OpenSSL does.
+pub fn from_pem<K: PemKey>(pem: &str) -> Result<K, PemError> {
+    let (label, der) = decode_pem(pem)?;
+    let der = SecretBytes::new(der);
+    if label == K::PEM_LABEL && der.len() > K::DER_PREFIX.len() && der.starts_with(K::DER_PREFIX) {
+        return K::try_from(&der[K::DER_PREFIX.len()..]).map_err(PemError::InvalidKey);
+    }
+    match PEM_KEY_TYPES
+        .iter()
+        .find(|(other_label, prefix, _)| *other_label == label && der.starts_with(prefix))
+    {
+        Some(&(_, _, found)) => Err(PemError::KeyTypeMismatch {
+            expected: K::KEY_TYPE,
+            found,
+        }),
+        None => Err(PemError::Unsupported {
+            expected: K::KEY_TYPE,
+            label,
+        }),
+    }
+}
+
+/// Returns the label and the decoded contents of the first PEM block of `pem`.
+fn decode_pem(pem: &str) -> Result<(String, Vec<u8>), PemError> {
+    let mut lines = pem.lines().map(str::trim);
+    let label = lines
+        .find_map(|line| {
+            if line.starts_with("-----BEGIN ") && line.ends_with("-----") {
+                Some(line["-----BEGIN ".len()..line.len() - "-----".len()].to_string())
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| PemError::Malformed("no BEGIN line".to_string()))?;
+    let end = format!("-----END {}-----", label);
+    let mut encoded = String::new();
+    loop {
+        match lines.next() {
+            Some(line) if line == end => break,
+            Some(line) => encoded.push_str(line),
+            None => return Err(PemError::Malformed(format!("no END line for {}", label))),
+        }
+    }
+    let der = base64::decode(&encoded).map_err(|e| PemError::Malformed(e.to_string()))?;
+    Ok((label, der))
+}
+
+/// Max number of keys of an aggregate public key, the number of bits of the signer bitmap of an
+/// aggregate signature.
+pub const MAX_AGGREGATED_KEYS: usize = 32;
+const SIGNER_BITMAP_LEN: usize = MAX_AGGREGATED_KEYS / 8;
+
+/// Errors possible when aggregating keys and signatures, or verifying an aggregate signature.
+#[derive(Debug, Fail, PartialEq)]
+pub enum AggregateKeyError {
+    #[fail(display = "No key to aggregate")]
+    /// No public key was given.
+    Empty,
+    #[fail(
+        display = "{} keys aggregated, at most {} supported",
+        _0, MAX_AGGREGATED_KEYS
+    )]
+    /// More than MAX_AGGREGATED_KEYS public keys were given.
+    TooManyKeys(usize),
+    #[fail(display = "Key {} is aggregated more than once", _0)]
+    /// A public key is given twice, it would let its owner sign on behalf of two signers.
+    DuplicateKey(usize),
+    #[fail(display = "Threshold {} is invalid for {} keys", threshold, num_keys)]
+    /// The threshold is either zero or greater than the number of keys.
+    InvalidThreshold { threshold: u8, num_keys: usize },
+    #[fail(display = "Signer {} is out of range", _0)]
+    /// A signature is attributed to a key the aggregate key does not have.
+    InvalidSignerIndex(usize),
+    #[fail(display = "Found {} signatures, {} required", found, threshold)]
+    /// Fewer signatures than the threshold of the aggregate key.
+    InsufficientSignatures { found: usize, threshold: u8 },
+    #[fail(display = "Invalid signature of signer {}", _0)]
+    /// The signature of a signer does not verify under its key.
+    InvalidSignature(usize),
+    #[fail(display = "Malformed aggregate: {}", _0)]
+    /// The encoding of an aggregate key or signature is invalid.
+    Malformed(String),
+}
+
+/// The ed25519 public keys of a group of signers, `threshold` of whom must sign for a signature
+/// of the group to verify, see `verify_aggregate_signature`.
+/// Summing ed25519 public keys into a single point is not secure without an interactive signing
+/// protocol: a signer choosing its key after the others can cancel them out. The aggregate key is
+/// instead the ordered list of the keys of the signers, and every signature is checked against the
+/// key of its signer. It is encoded as the concatenation of the 32 bytes encodings of the keys,
+/// followed by the threshold as a single byte.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregatePublicKey {
+    public_keys: Vec<Ed25519PublicKey>,
+    threshold: u8,
+}
+
+impl AggregatePublicKey {
+    /// Aggregates `public_keys` into a key that signatures of at least `threshold` of them
+    /// verify under. The order of the keys matters, signers are referred to by their index.
+    pub fn new(public_keys: &[Ed25519PublicKey], threshold: u8) -> Result<Self, AggregateKeyError> {
+        if public_keys.is_empty() {
+            return Err(AggregateKeyError::Empty);
+        }
+        if public_keys.len() > MAX_AGGREGATED_KEYS {
+            return Err(AggregateKeyError::TooManyKeys(public_keys.len()));
+        }
+        if let Some(duplicate) =
+            (1..public_keys.len()).find(|&index| public_keys[..index].contains(&public_keys[index]))
+        {
+            return Err(AggregateKeyError::DuplicateKey(duplicate));
+        }
+        if threshold == 0 || threshold as usize > public_keys.len() {
+            return Err(AggregateKeyError::InvalidThreshold {
+                threshold,
+                num_keys: public_keys.len(),
+            });
+        }
+        Ok(Self {
+            public_keys: public_keys.to_vec(),
+            threshold,
+        })
+    }
+
+    pub fn public_keys(&self) -> &[Ed25519PublicKey] {
+        &self.public_keys
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<_> = self
+            .public_keys
+            .iter()
+            .flat_map(|public_key| public_key.to_bytes().to_vec())
+            .collect();
+        bytes.push(self.threshold);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for AggregatePublicKey {
+    type Error = AggregateKeyError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, AggregateKeyError> {
+        let (&threshold, encoded_keys) = bytes
+            .split_last()
+            .ok_or_else(|| AggregateKeyError::Malformed("no threshold".to_string()))?;
+        if encoded_keys.len() % ED25519_PUBLIC_KEY_LENGTH != 0 {
+            return Err(AggregateKeyError::Malformed(format!(
+                "{} bytes of keys",
+                encoded_keys.len()
+            )));
+        }
+        let public_keys = encoded_keys
+            .chunks(ED25519_PUBLIC_KEY_LENGTH)
+            .map(Ed25519PublicKey::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| AggregateKeyError::Malformed(e.to_string()))?;
+        Self::new(&public_keys, threshold)
+    }
+}
+
+/// Aggregates `public_keys` into a key that only the signatures of all of them verify under, see
+/// `AggregatePublicKey`.
+pub fn aggregate_public_keys(
+    public_keys: &[Ed25519PublicKey],
+) -> Result<AggregatePublicKey, AggregateKeyError> {
+    if public_keys.len() > MAX_AGGREGATED_KEYS {
+        return Err(AggregateKeyError::TooManyKeys(public_keys.len()));
+    }
+    AggregatePublicKey::new(public_keys, public_keys.len() as u8)
+}
+
+/// The signatures of some of the signers of an aggregate key, with the index of their signer.
+/// It is encoded as the concatenation of the 64 bytes encodings of the signatures, ordered by
+/// signer, followed by a 4 bytes bitmap of the signers: the bit of signer `i` is the bit of weight
+/// `0x80 >> (i % 8)` of byte `i / 8`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateSignature {
+    signatures: Vec<(usize, Ed25519Signature)>,
+}
+
+impl AggregateSignature {
+    /// Aggregates the signatures of the signers at the given indices of an aggregate key.
+    pub fn new(mut signatures: Vec<(usize, Ed25519Signature)>) -> Result<Self, AggregateKeyError> {
+        signatures.sort_by_key(|(index, _)| *index);
+        for (position, (index, _)) in signatures.iter().enumerate() {
+            if *index >= MAX_AGGREGATED_KEYS
+                || (position > 0 && signatures[position - 1].0 == *index)
+            {
+                return Err(AggregateKeyError::InvalidSignerIndex(*index));
+            }
+        }
+        Ok(Self { signatures })
+    }
+
+    /// The signatures, with the index of their signer, by increasing index.
+    pub fn signatures(&self) -> &[(usize, Ed25519Signature)] {
+        &self.signatures
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bitmap = [0u8; SIGNER_BITMAP_LEN];
+        let mut bytes = Vec::with_capacity(
+            self.signatures.len() * ED25519_SIGNATURE_LENGTH + SIGNER_BITMAP_LEN,
+        );
+        for (index, signature) in &self.signatures {
+            bitmap[index / 8] |= 0x80 >> (index % 8);
+            bytes.extend_from_slice(&signature.to_bytes());
+        }
+        bytes.extend_from_slice(&bitmap);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for AggregateSignature {
+    type Error = AggregateKeyError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, AggregateKeyError> {
+        if bytes.len() < SIGNER_BITMAP_LEN
+            || (bytes.len() - SIGNER_BITMAP_LEN) % ED25519_SIGNATURE_LENGTH != 0
+        {
+            return Err(AggregateKeyError::Malformed(format!(
+                "{} bytes of signature",
+                bytes.len()
+            )));
+        }
+        let (encoded_signatures, bitmap) = bytes.split_at(bytes.len() - SIGNER_BITMAP_LEN);
+        let signers: Vec<_> = (0..MAX_AGGREGATED_KEYS)
+            .filter(|index| bitmap[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect();
+        if signers.len() * ED25519_SIGNATURE_LENGTH != encoded_signatures.len() {
+            return Err(AggregateKeyError::Malformed(format!(
+                "{} signers for {} bytes of signatures",
+                signers.len(),
+                encoded_signatures.len()
+            )));
+        }
+        let signatures = signers
+            .into_iter()
+            .zip(encoded_signatures.chunks(ED25519_SIGNATURE_LENGTH))
+            .map(|(index, encoded)| {
+                Ed25519Signature::try_from(encoded)
+                    .map(|signature| (index, signature))
+                    .map_err(|e| AggregateKeyError::Malformed(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { signatures })
+    }
+}
+
+/// Checks that `signature` holds valid signatures of `message` by at least the threshold of the
+/// signers of `public_key`.
+pub fn verify_aggregate_signature(
+    message: &HashValue,
+    signature: &AggregateSignature,
+    public_key: &AggregatePublicKey,
+) -> Result<(), AggregateKeyError> {
+    if signature.signatures.len() < public_key.threshold as usize {
+        return Err(AggregateKeyError::InsufficientSignatures {
+            found: signature.signatures.len(),
+            threshold: public_key.threshold,
+        });
+    }
+    for (index, signer_signature) in &signature.signatures {
+        let signer_key = public_key
+            .public_keys
+            .get(*index)
+            .ok_or(AggregateKeyError::InvalidSignerIndex(*index))?;
+        signer_signature
+            .verify(message, signer_key)
+            .map_err(|_| AggregateKeyError::InvalidSignature(*index))?;
+    }
+    Ok(())
+}
+
+/// Name of the consensus key pair file written by `ValidatorKeys::save_to_dir`.
+pub const CONSENSUS_KEYPAIR_FILE: &str = "node.consensus.keys.toml";
+/// Name of the network key pairs file written by `ValidatorKeys::save_to_dir`.
+pub const NETWORK_KEYPAIRS_FILE: &str = "node.network.keys.toml";
+
+/// Every key pair a validator needs.
+#[derive(Debug, PartialEq)]
+pub struct ValidatorKeys {
+    pub consensus_keypair: ConsensusKeyPair,
+    pub network_keypairs: NetworkKeyPairs,
+}
+
+impl ValidatorKeys {
+    /// Writes each key pair to its conventionally named file of `dir`.
+    pub fn save_to_dir(&self, dir: &Path) -> failure::Result<()> {
+        write_key_file(
+            &dir.join(CONSENSUS_KEYPAIR_FILE),
+            toml::to_vec(&self.consensus_keypair)?,
+        )?;
+        write_key_file(
+            &dir.join(NETWORK_KEYPAIRS_FILE),
+            toml::to_vec(&self.network_keypairs)?,
+        )
+    }
+
+    /// Reads the key pairs written to `dir` by `save_to_dir`.
+    pub fn load_from_dir(dir: &Path) -> failure::Result<Self> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            read_key_file(&path).map_err(|e| format_err!("Cannot read {:?}: {}", path, e))
+        };
+        Ok(Self {
+            consensus_keypair: ConsensusKeyPair::parse(&read(CONSENSUS_KEYPAIR_FILE)?)?,
+            network_keypairs: NetworkKeyPairs::parse(&read(NETWORK_KEYPAIRS_FILE)?)?,
+        })
+    }
+}
+
+/// Generates fresh random key pairs for a validator.
+pub fn generate_validator_keys() -> ValidatorKeys {
+    let mut rng = StdRng::from_entropy();
+    let (consensus_private_key, _) = compat::generate_keypair(&mut rng);
+    let (network_signing_private_key, _) = compat::generate_keypair(&mut rng);
+    let (network_identity_private_key, _) = x25519::compat::generate_keypair(&mut rng);
+    ValidatorKeys {
+        consensus_keypair: ConsensusKeyPair::load(Some(consensus_private_key)),
+        network_keypairs: NetworkKeyPairs::load(
+            network_signing_private_key,
+            network_identity_private_key,
+        ),
+    }
+}
+
+/// Deterministically derives the `index`th ed25519 keypair of `seed`: the private key of the
+/// SLIP-0010 hardened path `m/index'`, so keys can also be derived by any SLIP-0010 compliant
+/// wallet. Fails if `index` is not lower than 2^31, the first index of the hardened keys.
+pub fn derive_from_seed(
+    seed: &[u8],
+    index: u32,
+) -> failure::Result<(Ed25519PrivateKey, Ed25519PublicKey)> {
+    if index >= 1 << 31 {
+        return Err(format_err!(
+            "Cannot derive key {} from seed, indices must be lower than 2^31",
+            index
+        ));
+    }
+    let extended_key = Slip0010::derive_from_path(&format!("m/{}", index), seed)
+        .map_err(|e| format_err!("Cannot derive key {} from seed: {}", index, e))?;
+    let key_bytes = SecretBytes::new(extended_key.get_private().to_bytes());
+    let private_key = Ed25519PrivateKey::try_from(&key_bytes[..])
+        .map_err(|e| format_err!("Cannot derive key {} from seed: {}", index, e))?;
+    let public_key = (&private_key).into();
+    Ok((private_key, public_key))
+}
+
+/// Number of SLIP-0010 indices `generate_n` derives the keys of a validator from.
+const KEYS_PER_VALIDATOR: u64 = 3;
+
+/// Generates the key bundles of `n` validators in parallel and writes the bundle of the `i`th one
+/// to `out_dir/<i>` with `ValidatorKeys::save_to_dir`. Returns the consensus public keys of the
+/// validators in order.
+///
+/// Without `seed` the keys are random. With a `seed` they are derived with `derive_from_seed`, so
+/// the same seed always generates the same bundles: the consensus and network signing keys of the
+/// `i`th validator are the keys of the indices `3i` and `3i + 1`, and its network identity key is
+/// derived with HKDF from the key of the index `3i + 2`.
+pub fn generate_n(
+    n: usize,
+    out_dir: &Path,
+    seed: Option<&[u8]>,
+) -> failure::Result<Vec<Ed25519PublicKey>> {
+    if seed.is_some() && (n as u64).saturating_mul(KEYS_PER_VALIDATOR) > 1 << 31 {
+        return Err(format_err!(
+            "Cannot derive the keys of {} validators from a seed",
+            n
+        ));
+    }
+    (0..n)
+        .into_par_iter()
+        .map(|index| {
+            let mut keys = match seed {
+                Some(seed) => derive_validator_keys(seed, index as u32)?,
+                None => generate_validator_keys(),
+            };
+            let dir = out_dir.join(index.to_string());
+            fs::create_dir_all(&dir)
+                .map_err(|e| format_err!("Cannot create key directory {:?}: {}", dir, e))?;
+            keys.save_to_dir(&dir)?;
+            keys.consensus_keypair
+                .consensus_public_key
+                .take()
+                .ok_or_else(|| format_err!("Generated keys have no consensus key"))
+        })
+        .collect()
+}
+
+fn derive_validator_keys(seed: &[u8], index: u32) -> failure::Result<ValidatorKeys> {
+    let key_index = |offset| index * KEYS_PER_VALIDATOR as u32 + offset;
+    let (consensus_private_key, _) = derive_from_seed(seed, key_index(0))?;
+    let (network_signing_private_key, _) = derive_from_seed(seed, key_index(1))?;
+    let identity_seed = SecretBytes::new(derive_from_seed(seed, key_index(2))?.0.to_bytes());
+    let (network_identity_private_key, _) =
+        X25519StaticPrivateKey::derive_keypair_from_seed(None, &identity_seed[..], None);
+    Ok(ValidatorKeys {
+        consensus_keypair: ConsensusKeyPair::load(Some(consensus_private_key)),
+        network_keypairs: NetworkKeyPairs::load(
+            network_signing_private_key,
+            network_identity_private_key,
+        ),
+    })
+}
+
+/// Rotates the consensus key pair file at `path`: a new key pair replaces the current one, which
+/// is archived to the sibling file `<name>.prev` so that artifacts it recently signed can still
+/// be verified during the transition. Returns the previous and the new public keys.
+pub fn rotate(path: &Path) -> failure::Result<(Ed25519PublicKey, Ed25519PublicKey)> {
+    let contents = read_key_file(path)?;
+    let previous_public_key = ConsensusKeyPair::parse(&contents)?
+        .consensus_public_key
+        .ok_or_else(|| format_err!("No consensus key to rotate in {:?}", path))?;
+    let mut rng = StdRng::from_entropy();
+    let (private_key, public_key) = compat::generate_keypair(&mut rng);
+    // The previous key is archived before being replaced, so that it is never lost.
+    write_key_file(&previous_key_path(path)?, contents.into_bytes())?;
+    write_key_file(
+        path,
+        toml::to_vec(&ConsensusKeyPair::load(Some(private_key)))?,
+    )?;
+    Ok((previous_public_key, public_key))
+}
+
+fn previous_key_path(path: &Path) -> failure::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format_err!("{:?} is not a file path", path))?;
+    Ok(path.with_file_name(format!("{}.prev", file_name.to_string_lossy())))
+}
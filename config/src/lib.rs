@@ -7,4 +7,5 @@ pub mod config;
 pub mod keys;
 pub mod seed_peers;
 pub mod trusted_peers;
+pub mod units;
 pub mod utils;
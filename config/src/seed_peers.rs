@@ -2,9 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{trusted_peers::NetworkPeersConfig, utils::get_available_port};
+use failure::prelude::*;
+use libra_logger::prelude::*;
 use parity_multiaddr::{Multiaddr, Protocol};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
 
 #[cfg(test)]
 #[path = "unit_tests/seed_peers_test.rs"]
@@ -14,6 +22,316 @@ mod seed_peers_test;
 pub struct SeedPeersConfig {
     // All peers config. Key:a unique peer id, will be PK in future, Value: peer discovery info
     pub seed_peers: HashMap<String, Vec<Multiaddr>>,
+    // Dialing priority of the seed peers, e.g. to dial the validators of the same region first.
+    // Peers with a lower priority are dialed first, peers without a priority are dialed last.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub priorities: HashMap<String, u32>,
+}
+
+/// A seed peers file, before its addresses are parsed into multiaddrs.
+#[derive(Deserialize)]
+struct RawSeedPeersConfig {
+    #[serde(default)]
+    seed_peers: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    priorities: HashMap<String, u32>,
+}
+
+/// Errors possible during the validation of a SeedPeersConfig.
+#[derive(Debug, Fail, PartialEq)]
+pub enum SeedPeersError {
+    #[fail(display = "Invalid seed peer addresses: {:?}", _0)]
+    /// Addresses that are malformed or cannot be dialed, all of them are reported at once.
+    InvalidAddresses(Vec<InvalidSeedPeerAddr>),
+}
+
+/// A seed peer address that is malformed or cannot be dialed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidSeedPeerAddr {
+    pub peer_id: String,
+    pub addr: String,
+    /// What is wrong with the address.
+    pub reason: String,
+}
+
+impl SeedPeersConfig {
+    /// Parses a seed peers file, reporting every address that fails to parse into a multiaddr
+    /// or fails `validate`, rather than only the first syntax error.
+    pub fn parse_validated(serialized: &str) -> Result<Self> {
+        let raw: RawSeedPeersConfig = toml::from_str(serialized)?;
+        let mut seed_peers = HashMap::new();
+        let mut invalid_addrs = vec![];
+        for (peer_id, addrs) in raw.seed_peers {
+            let mut parsed_addrs = vec![];
+            for addr in addrs {
+                match addr.parse::<Multiaddr>() {
+                    Ok(parsed_addr) => parsed_addrs.push(parsed_addr),
+                    Err(e) => invalid_addrs.push(InvalidSeedPeerAddr {
+                        peer_id: peer_id.clone(),
+                        addr,
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+            seed_peers.insert(peer_id, parsed_addrs);
+        }
+        let config = SeedPeersConfig {
+            seed_peers,
+            priorities: raw.priorities,
+        };
+        if let Err(SeedPeersError::InvalidAddresses(invalid)) = config.validate() {
+            invalid_addrs.extend(invalid);
+        }
+        if invalid_addrs.is_empty() {
+            Ok(config)
+        } else {
+            invalid_addrs.sort_by(|a, b| (&a.peer_id, &a.addr).cmp(&(&b.peer_id, &b.addr)));
+            Err(SeedPeersError::InvalidAddresses(invalid_addrs).into())
+        }
+    }
+
+    /// Verifies that every seed peer address can be dialed: it is an ip4, ip6, dns4 or dns6 host
+    /// followed by a tcp port.
+    pub fn validate(&self) -> std::result::Result<(), SeedPeersError> {
+        let mut invalid_addrs = vec![];
+        for (peer_id, addrs) in &self.seed_peers {
+            for addr in addrs {
+                if let Err(reason) = check_addr(addr) {
+                    invalid_addrs.push(InvalidSeedPeerAddr {
+                        peer_id: peer_id.clone(),
+                        addr: addr.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+        if invalid_addrs.is_empty() {
+            Ok(())
+        } else {
+            Err(SeedPeersError::InvalidAddresses(invalid_addrs))
+        }
+    }
+
+    /// Sets the addresses of `peer_id`, returns whether the peer set changed.
+    pub fn add_peer(&mut self, peer_id: String, addrs: Vec<Multiaddr>) -> bool {
+        if self.seed_peers.get(&peer_id) == Some(&addrs) {
+            return false;
+        }
+        self.seed_peers.insert(peer_id, addrs);
+        true
+    }
+
+    /// Merges the peers of `other` into this set. The addresses of a peer of both sets are the
+    /// union of its addresses, in order and without duplicates, and its priority is the lowest
+    /// of its priorities.
+    pub fn merge(&mut self, other: SeedPeersConfig) {
+        for (peer_id, addrs) in other.seed_peers {
+            let merged_addrs = self.seed_peers.entry(peer_id).or_insert_with(Vec::new);
+            for addr in addrs {
+                if !merged_addrs.contains(&addr) {
+                    merged_addrs.push(addr);
+                }
+            }
+        }
+        for (peer_id, priority) in other.priorities {
+            let merged_priority = self.priorities.entry(peer_id).or_insert(priority);
+            *merged_priority = std::cmp::min(*merged_priority, priority);
+        }
+    }
+
+    /// Removes `peer_id`, returns whether the peer set changed.
+    pub fn remove_peer(&mut self, peer_id: &str) -> bool {
+        self.priorities.remove(peer_id);
+        self.seed_peers.remove(peer_id).is_some()
+    }
+
+    /// Iterates over the seed peers in dialing order: by increasing priority, then the peers
+    /// without a priority. Peers of equal priority are ordered by peer id, as the order of the
+    /// peers in the file is not kept.
+    pub fn iter_by_priority(&self) -> impl Iterator<Item = (&String, &Vec<Multiaddr>)> {
+        let mut peers: Vec<_> = self.seed_peers.iter().collect();
+        peers.sort_by(|(a, _), (b, _)| {
+            let priority = |peer_id: &String| {
+                let priority = self.priorities.get(peer_id).copied();
+                (priority.is_none(), priority)
+            };
+            (priority(a), a).cmp(&(priority(b), b))
+        });
+        peers.into_iter()
+    }
+
+    /// Replaces the seed peer addresses starting with a `/dns4` or `/dns6` host with one address
+    /// per ip the host resolves to, as the transports only dial ip addresses. Addresses that fail
+    /// to resolve are logged and skipped, and so are the peers left without any address.
+    pub fn resolve(&mut self) {
+        for (peer_id, addrs) in self.seed_peers.iter_mut() {
+            *addrs = addrs
+                .iter()
+                .flat_map(|addr| {
+                    resolve_addr(addr).unwrap_or_else(|e| {
+                        warn!("Skipping address {} of seed peer {}: {}", addr, peer_id, e);
+                        vec![]
+                    })
+                })
+                .collect();
+        }
+        self.seed_peers.retain(|peer_id, addrs| {
+            if addrs.is_empty() {
+                warn!(
+                    "Skipping seed peer {} without any resolved address",
+                    peer_id
+                );
+            }
+            !addrs.is_empty()
+        });
+    }
+}
+
+/// A seed peer set shared between threads, e.g. between the network and an orchestrator adding
+/// and removing validators, which updates it without reloading the seed peers file.
+#[derive(Clone, Debug, Default)]
+pub struct SharedSeedPeers(Arc<RwLock<SeedPeersConfig>>);
+
+impl SharedSeedPeers {
+    pub fn new(config: SeedPeersConfig) -> Self {
+        SharedSeedPeers(Arc::new(RwLock::new(config)))
+    }
+
+    /// See `SeedPeersConfig::add_peer`.
+    pub fn add_peer(&self, peer_id: String, addrs: Vec<Multiaddr>) -> bool {
+        self.0
+            .write()
+            .expect("Seed peers lock is poisoned")
+            .add_peer(peer_id, addrs)
+    }
+
+    /// See `SeedPeersConfig::remove_peer`.
+    pub fn remove_peer(&self, peer_id: &str) -> bool {
+        self.0
+            .write()
+            .expect("Seed peers lock is poisoned")
+            .remove_peer(peer_id)
+    }
+
+    /// Replaces the whole peer set, e.g. with the one of a reloaded seed peers file.
+    pub fn set(&self, config: SeedPeersConfig) {
+        *self.0.write().expect("Seed peers lock is poisoned") = config;
+    }
+
+    /// Returns a copy of the current peer set.
+    pub fn get(&self) -> SeedPeersConfig {
+        self.0.read().expect("Seed peers lock is poisoned").clone()
+    }
+}
+
+/// Reads the seed peers files at `paths`, e.g. one per region, and merges them into one set as
+/// `SeedPeersConfig::merge` does, in the order of `paths`.
+pub fn load_many(paths: &[&Path]) -> Result<SeedPeersConfig> {
+    let mut config = SeedPeersConfig::default();
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format_err!("Cannot read seed peers file {:?}: {}", path, e))?;
+        let file_config = SeedPeersConfig::parse_validated(&contents)
+            .map_err(|e| format_err!("Invalid seed peers file {:?}: {}", path, e))?;
+        config.merge(file_config);
+    }
+    Ok(config)
+}
+
+/// Reads and merges, as `load_many` does, every `.toml` file of `dir`, in the order of their
+/// names. Subdirectories are not read.
+pub fn load_dir(dir: &Path) -> Result<SeedPeersConfig> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)
+        .map_err(|e| format_err!("Cannot read seed peers directory {:?}: {}", dir, e))?
+    {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    load_many(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>())
+}
+
+fn check_addr(addr: &Multiaddr) -> std::result::Result<(), String> {
+    let to_string = |protocol| Multiaddr::empty().with(protocol).to_string();
+    let mut protocols = addr.iter();
+    match protocols.next() {
+        Some(Protocol::Ip4(_))
+        | Some(Protocol::Ip6(_))
+        | Some(Protocol::Dns4(_))
+        | Some(Protocol::Dns6(_)) => (),
+        Some(protocol) => {
+            return Err(format!(
+                "expected an ip4, ip6, dns4 or dns6 host, found {}",
+                to_string(protocol)
+            ))
+        }
+        None => return Err("empty address".to_string()),
+    }
+    match protocols.next() {
+        Some(Protocol::Tcp(_)) => (),
+        Some(protocol) => {
+            return Err(format!(
+                "expected a tcp port after the host, found {}",
+                to_string(protocol)
+            ))
+        }
+        None => return Err("missing tcp port".to_string()),
+    }
+    match protocols.next() {
+        Some(protocol) => Err(format!(
+            "unexpected {} after the tcp port",
+            to_string(protocol)
+        )),
+        None => Ok(()),
+    }
+}
+
+fn resolve_addr(addr: &Multiaddr) -> io::Result<Vec<Multiaddr>> {
+    let mut protocols = addr.iter();
+    let (host, ipv4) = match protocols.next() {
+        Some(Protocol::Dns4(host)) => (host, true),
+        Some(Protocol::Dns6(host)) => (host, false),
+        _ => return Ok(vec![addr.clone()]),
+    };
+    let rest: Vec<_> = protocols.collect();
+    let port = match rest.first() {
+        Some(Protocol::Tcp(port)) => *port,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the host is not followed by a tcp port",
+            ))
+        }
+    };
+    let resolved: Vec<_> = (host.as_ref(), port)
+        .to_socket_addrs()?
+        .filter(|socket_addr| socket_addr.is_ipv4() == ipv4)
+        .map(|socket_addr| {
+            let mut resolved = Multiaddr::empty();
+            resolved.push(match socket_addr.ip() {
+                IpAddr::V4(ip) => Protocol::Ip4(ip),
+                IpAddr::V6(ip) => Protocol::Ip6(ip),
+            });
+            for protocol in &rest {
+                resolved.push(protocol.clone());
+            }
+            resolved
+        })
+        .collect();
+    if resolved.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} has no {} address",
+                host,
+                if ipv4 { "ipv4" } else { "ipv6" }
+            ),
+        ));
+    }
+    Ok(resolved)
 }
 
 pub struct SeedPeersConfigHelpers {}
@@ -54,6 +372,9 @@ impl SeedPeersConfigHelpers {
             addr.push(Protocol::Tcp(port.unwrap_or_else(get_available_port)));
             seed_peers.insert(peer_id.clone(), vec![addr]);
         }
-        SeedPeersConfig { seed_peers }
+        SeedPeersConfig {
+            seed_peers,
+            priorities: HashMap::new(),
+        }
     }
 }
@@ -1,10 +1,14 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::config::PersistableConfig;
+use failure::{format_err, Fail};
 use libra_crypto::{
+    bls12381::{BLS12381PublicKey, BLS12381Signature},
     ed25519::{compat, *},
-    traits::{ValidKey, ValidKeyStringExt},
+    traits::{Signature, ValidKey, ValidKeyStringExt},
     x25519::{self, X25519StaticPrivateKey, X25519StaticPublicKey},
+    HashValue,
 };
 use libra_types::{
     account_address::AccountAddress,
@@ -15,12 +19,17 @@ use libra_types::{
 };
 use mirai_annotations::*;
 use rand::{rngs::StdRng, SeedableRng};
-use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{
+    de::{self, DeserializeOwned, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
-    fmt,
+    fmt, fs,
     hash::BuildHasher,
+    marker::PhantomData,
+    path::PathBuf,
     str::FromStr,
 };
 
@@ -29,15 +38,156 @@ use std::{
 mod trusted_peers_test;
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct NetworkPeerInfo {
-    #[serde(serialize_with = "serialize_key")]
-    #[serde(deserialize_with = "deserialize_key")]
+    /// Network signing key of the peer, tagged with its signature scheme so that peers may use
+    /// different schemes while the network migrates from one to another.
     #[serde(rename = "ns")]
-    pub network_signing_pubkey: Ed25519PublicKey,
+    pub network_signing_pubkey: TaggedPublicKey,
     #[serde(serialize_with = "serialize_key")]
     #[serde(deserialize_with = "deserialize_key")]
     #[serde(rename = "ni")]
     pub network_identity_pubkey: X25519StaticPublicKey,
+    /// Network signing key the peer is rotating to. Until the rotation completes, signatures by
+    /// either key are accepted, so that peers do not need to switch keys at the same time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "nns")]
+    pub next_network_signing_pubkey: Option<TaggedPublicKey>,
+}
+
+/// Tag of the ed25519 keys of the trusted peers.
+pub const ED25519_ALGORITHM: &str = "ed25519";
+/// Tag of the BLS12-381 keys of the trusted peers.
+pub const BLS12381_ALGORITHM: &str = "bls12381";
+
+/// The bytes of a public key, tagged with the signature scheme they are a key of. Tags of schemes
+/// this node does not support are kept, the keys are only rejected when verifying a signature.
+/// Written as `<tag>:<hex encoded key>`, or as the hex encoded key alone for ed25519 keys, as in
+/// the peers files predating the tags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaggedPublicKey {
+    pub algorithm: String,
+    pub key: Vec<u8>,
+}
+
+impl TaggedPublicKey {
+    pub fn ed25519(public_key: &Ed25519PublicKey) -> Self {
+        Self {
+            algorithm: ED25519_ALGORITHM.to_string(),
+            key: public_key.to_bytes().to_vec(),
+        }
+    }
+
+    pub fn bls12381(public_key: &BLS12381PublicKey) -> Self {
+        Self {
+            algorithm: BLS12381_ALGORITHM.to_string(),
+            key: public_key.to_bytes().to_vec(),
+        }
+    }
+
+    /// The key as an ed25519 key, if it is one.
+    pub fn to_ed25519(&self) -> Option<Ed25519PublicKey> {
+        if self.algorithm == ED25519_ALGORITHM {
+            Ed25519PublicKey::try_from(self.key.as_slice()).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Verifies that `signature` is the signature of `message` by this key, with the verification
+    /// routine of its signature scheme.
+    pub fn verify(&self, message: &HashValue, signature: &[u8]) -> Result<(), VerifyError> {
+        match self.algorithm.as_str() {
+            ED25519_ALGORITHM => {
+                verify_signature::<Ed25519Signature>(message, signature, &self.key)
+            }
+            BLS12381_ALGORITHM => {
+                verify_signature::<BLS12381Signature>(message, signature, &self.key)
+            }
+            algorithm => Err(VerifyError::UnsupportedAlgorithm(algorithm.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for TaggedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.algorithm == ED25519_ALGORITHM {
+            write!(f, "{}", hex::encode(&self.key))
+        } else {
+            write!(f, "{}:{}", self.algorithm, hex::encode(&self.key))
+        }
+    }
+}
+
+impl FromStr for TaggedPublicKey {
+    type Err = failure::Error;
+
+    /// Parses a key written by `Display`. The keys of the supported schemes must be valid keys
+    /// of their scheme.
+    fn from_str(s: &str) -> failure::Result<Self> {
+        let (algorithm, encoded) = match s.find(':') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => (ED25519_ALGORITHM, s),
+        };
+        let key = Self {
+            algorithm: algorithm.to_string(),
+            key: hex::decode(encoded)?,
+        };
+        match algorithm {
+            ED25519_ALGORITHM => {
+                Ed25519PublicKey::try_from(key.key.as_slice())
+                    .map_err(|e| format_err!("Invalid ed25519 key {}: {}", s, e))?;
+            }
+            BLS12381_ALGORITHM => {
+                BLS12381PublicKey::try_from(key.key.as_slice())
+                    .map_err(|e| format_err!("Invalid bls12381 key {}: {}", s, e))?;
+            }
+            _ => (),
+        }
+        Ok(key)
+    }
+}
+
+impl Serialize for TaggedPublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TaggedPublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded: String = Deserialize::deserialize(deserializer)?;
+        encoded.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Verifies a signature of the scheme `S`, a malformed signature or key being a bad signature.
+fn verify_signature<S: Signature>(
+    message: &HashValue,
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<(), VerifyError> {
+    let signature = S::try_from(signature).map_err(|_| VerifyError::BadSignature)?;
+    let public_key =
+        S::VerifyingKeyMaterial::try_from(public_key).map_err(|_| VerifyError::BadSignature)?;
+    signature
+        .verify(message, &public_key)
+        .map_err(|_| VerifyError::BadSignature)
+}
+
+impl NetworkPeerInfo {
+    /// Completes the rotation of the network signing key: the next key becomes the current one,
+    /// signatures by the previous key are no longer accepted. Returns whether a rotation was
+    /// pending.
+    pub fn complete_key_rotation(&mut self) -> bool {
+        match self.next_network_signing_pubkey.take() {
+            Some(next) => {
+                self.network_signing_pubkey = next;
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 pub struct NetworkPrivateKeys {
@@ -49,9 +199,109 @@ pub struct NetworkPrivateKeys {
 pub struct NetworkPeersConfig {
     #[serde(flatten)]
     #[serde(serialize_with = "serialize_ordered_map")]
+    #[serde(deserialize_with = "deserialize_unique_map")]
     pub peers: HashMap<String, NetworkPeerInfo>,
 }
 
+/// Errors possible when verifying a signature against the trusted peers.
+#[derive(Debug, Fail, PartialEq)]
+pub enum VerifyError {
+    #[fail(display = "Peer {} is not trusted", _0)]
+    /// The peer is not one of the trusted peers.
+    UnknownPeer(String),
+    #[fail(display = "Signature is invalid")]
+    /// The signature does not match the message and the key of the peer.
+    BadSignature,
+    #[fail(display = "Unsupported key algorithm {}", _0)]
+    /// The key of the peer is a key of a signature scheme this node does not support.
+    UnsupportedAlgorithm(String),
+}
+
+/// Changes from a set of trusted peers to another, by peer id, e.g. for operators to review a new
+/// set before applying it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerSetDiff<T> {
+    /// Peers only in the new set.
+    pub added: BTreeMap<String, T>,
+    /// Peers only in the old set.
+    pub removed: BTreeMap<String, T>,
+    /// Peers in both sets whose keys changed, with their old and new keys.
+    pub changed: BTreeMap<String, (T, T)>,
+}
+
+impl<T: Clone + PartialEq> PeerSetDiff<T> {
+    fn new(old: &HashMap<String, T>, new: &HashMap<String, T>) -> Self {
+        let mut diff = Self {
+            added: BTreeMap::new(),
+            removed: BTreeMap::new(),
+            changed: BTreeMap::new(),
+        };
+        for (peer_id, old_info) in old {
+            match new.get(peer_id) {
+                Some(new_info) if new_info != old_info => {
+                    diff.changed
+                        .insert(peer_id.clone(), (old_info.clone(), new_info.clone()));
+                }
+                Some(_) => (),
+                None => {
+                    diff.removed.insert(peer_id.clone(), old_info.clone());
+                }
+            }
+        }
+        for (peer_id, new_info) in new {
+            if !old.contains_key(peer_id) {
+                diff.added.insert(peer_id.clone(), new_info.clone());
+            }
+        }
+        diff
+    }
+
+    /// Whether both sets are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl NetworkPeersConfig {
+    /// Serializes the peers to JSON, with the field names and the hex encoded keys of the peers
+    /// files.
+    pub fn to_json(&self) -> failure::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserializes peers serialized by `to_json`.
+    pub fn from_json(json: &str) -> failure::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Verifies that `signature` is the signature of `message` by the network signing key of the
+    /// trusted peer `peer_id`, or by its next network signing key while it rotates keys, each
+    /// verified with the routine of the signature scheme it is tagged with. A signature matching
+    /// neither fails with the error of the current key.
+    pub fn verify(
+        &self,
+        peer_id: &str,
+        message: &HashValue,
+        signature: &[u8],
+    ) -> Result<(), VerifyError> {
+        let peer_info = self
+            .peers
+            .get(peer_id)
+            .ok_or_else(|| VerifyError::UnknownPeer(peer_id.to_string()))?;
+        let result = peer_info.network_signing_pubkey.verify(message, signature);
+        match &peer_info.next_network_signing_pubkey {
+            Some(next) if result.is_err() && next.verify(message, signature).is_ok() => Ok(()),
+            _ => result,
+        }
+    }
+
+    /// The changes from these peers to `other`. A peer rotating its network signing key counts as
+    /// changed.
+    pub fn diff(&self, other: &Self) -> PeerSetDiff<NetworkPeerInfo> {
+        PeerSetDiff::new(&self.peers, &other.peers)
+    }
+}
+
 impl fmt::Debug for NetworkPeersConfig {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "<{} keys>", self.peers.len())
@@ -59,6 +309,7 @@ impl fmt::Debug for NetworkPeersConfig {
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct ConsensusPeerInfo {
     #[serde(serialize_with = "serialize_key")]
     #[serde(deserialize_with = "deserialize_key")]
@@ -74,6 +325,7 @@ pub struct ConsensusPrivateKey {
 pub struct ConsensusPeersConfig {
     #[serde(flatten)]
     #[serde(serialize_with = "serialize_ordered_map")]
+    #[serde(deserialize_with = "deserialize_unique_map")]
     pub peers: HashMap<String, ConsensusPeerInfo>,
 }
 
@@ -85,36 +337,57 @@ pub struct UpstreamPeersConfig {
 }
 
 impl ConsensusPeersConfig {
-    /// Return a sorted vector of ValidatorPublicKey's
-    pub fn get_validator_set(&self, network_peers_config: &NetworkPeersConfig) -> ValidatorSet {
-        let mut keys: Vec<ValidatorPublicKeys> = self
+    /// Serializes the peers to JSON, with the field names and the hex encoded keys of the peers
+    /// files.
+    pub fn to_json(&self) -> failure::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Deserializes peers serialized by `to_json`.
+    pub fn from_json(json: &str) -> failure::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The changes from these peers to `other`.
+    pub fn diff(&self, other: &Self) -> PeerSetDiff<ConsensusPeerInfo> {
+        PeerSetDiff::new(&self.peers, &other.peers)
+    }
+
+    /// Return a sorted vector of ValidatorPublicKey's. Fails if the network signing key of a peer
+    /// is not an ed25519 key, the only scheme of the validator set.
+    pub fn get_validator_set(
+        &self,
+        network_peers_config: &NetworkPeersConfig,
+    ) -> failure::Result<ValidatorSet> {
+        let mut keys = self
             .peers
             .iter()
             .map(|(peer_id_str, peer_info)| {
-                ValidatorPublicKeys::new(
+                let network_peer_info = network_peers_config.peers.get(peer_id_str).unwrap();
+                let network_signing_pubkey = network_peer_info
+                    .network_signing_pubkey
+                    .to_ed25519()
+                    .ok_or_else(|| {
+                        format_err!(
+                            "Network signing key of validator {} is a {} key, not an ed25519 one",
+                            peer_id_str,
+                            network_peer_info.network_signing_pubkey.algorithm
+                        )
+                    })?;
+                Ok(ValidatorPublicKeys::new(
                     AccountAddress::from_str(peer_id_str).expect("[config] invalid peer_id"),
                     peer_info.consensus_pubkey.clone(),
                     // TODO: Add support for dynamic voting weights in config
                     1,
-                    network_peers_config
-                        .peers
-                        .get(peer_id_str)
-                        .unwrap()
-                        .network_signing_pubkey
-                        .clone(),
-                    network_peers_config
-                        .peers
-                        .get(peer_id_str)
-                        .unwrap()
-                        .network_identity_pubkey
-                        .clone(),
-                )
+                    network_signing_pubkey,
+                    network_peer_info.network_identity_pubkey.clone(),
+                ))
             })
-            .collect();
+            .collect::<failure::Result<Vec<_>>>()?;
         // self.peers is a HashMap, so iterating over it produces a differently ordered vector each
         // time. Sort by account address to produce a canonical ordering
         keys.sort_by(|k1, k2| k1.account_address().cmp(k2.account_address()));
-        ValidatorSet::new(keys)
+        Ok(ValidatorSet::new(keys))
     }
 
     pub fn get_validator_verifier(&self) -> ValidatorVerifier {
@@ -138,6 +411,55 @@ impl ConsensusPeersConfig {
     }
 }
 
+/// A source of a peer set, e.g. the peers file of the node or the set published on-chain, so that
+/// the consumers of the set, e.g. `ReloadablePeers`, do not depend on where it is kept.
+pub trait TrustedPeersSource<T>: fmt::Debug + Send + Sync {
+    /// Fetches the current peer set.
+    fn fetch(&self) -> failure::Result<T>;
+}
+
+/// Peer set read from a file, e.g. the peers files written by `PersistableConfig::save_config`.
+pub struct FilePeersSource<T> {
+    path: PathBuf,
+    kind: &'static str,
+    parse: fn(&str) -> failure::Result<T>,
+}
+
+impl<T> FilePeersSource<T> {
+    /// Source of the peer set held by the file at `path`, parsed with `parse`. `kind` names the
+    /// set in the errors.
+    pub fn new(path: PathBuf, kind: &'static str, parse: fn(&str) -> failure::Result<T>) -> Self {
+        Self { path, kind, parse }
+    }
+}
+
+impl FilePeersSource<NetworkPeersConfig> {
+    pub fn network_peers(path: PathBuf) -> Self {
+        Self::new(path, "network peers", NetworkPeersConfig::parse)
+    }
+}
+
+impl FilePeersSource<ConsensusPeersConfig> {
+    pub fn consensus_peers(path: PathBuf) -> Self {
+        Self::new(path, "consensus peers", ConsensusPeersConfig::parse)
+    }
+}
+
+impl<T> fmt::Debug for FilePeersSource<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FilePeersSource({} at {:?})", self.kind, self.path)
+    }
+}
+
+impl<T> TrustedPeersSource<T> for FilePeersSource<T> {
+    fn fetch(&self) -> failure::Result<T> {
+        let contents = fs::read_to_string(&self.path)
+            .map_err(|e| format_err!("Cannot read {} file {:?}: {}", self.kind, self.path, e))?;
+        (self.parse)(&contents)
+            .map_err(|e| format_err!("Invalid {} file {:?}: {}", self.kind, self.path, e))
+    }
+}
+
 // TODO: move to mod utils.
 pub struct ConfigHelpers {}
 
@@ -187,8 +509,9 @@ impl ConfigHelpers {
             network_peers.insert(
                 peer_id.to_string(),
                 NetworkPeerInfo {
-                    network_signing_pubkey: public0,
+                    network_signing_pubkey: TaggedPublicKey::ed25519(&public0),
                     network_identity_pubkey: public1,
+                    next_network_signing_pubkey: None,
                 },
             );
             // save the private keys in a different hashmap
@@ -235,8 +558,9 @@ impl ConfigHelpers {
             network_peers.insert(
                 peer_id.to_string(),
                 NetworkPeerInfo {
-                    network_signing_pubkey: public0,
+                    network_signing_pubkey: TaggedPublicKey::ed25519(&public0),
                     network_identity_pubkey: public1,
+                    next_network_signing_pubkey: None,
                 },
             );
             // save the private keys in a different hashmap
@@ -279,6 +603,34 @@ where
         .map_err(<D::Error as serde::de::Error>::custom)
 }
 
+pub fn serialize_opt_key<S, K>(key: &Option<K>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Serialize + ValidKeyStringExt,
+{
+    match key {
+        Some(key) => key
+            .to_encoded_string()
+            .map_err(<S::Error as serde::ser::Error>::custom)
+            .and_then(|str| serializer.serialize_some(&str)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_opt_key<'de, D, K>(deserializer: D) -> Result<Option<K>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: ValidKeyStringExt + DeserializeOwned + 'static,
+{
+    let encoded_key: Option<String> = Deserialize::deserialize(deserializer)?;
+    encoded_key
+        .map(|encoded_key| {
+            ValidKeyStringExt::from_encoded_string(&encoded_key)
+                .map_err(<D::Error as serde::de::Error>::custom)
+        })
+        .transpose()
+}
+
 pub fn serialize_ordered_map<S, V, H>(
     value: &HashMap<String, V, H>,
     serializer: S,
@@ -291,3 +643,44 @@ where
     let ordered: BTreeMap<_, _> = value.iter().collect();
     ordered.serialize(serializer)
 }
+
+/// Deserializes a map keyed by peer id, failing with every duplicated peer id instead of keeping
+/// the last entry of each, so that a peer listed twice cannot silently be trusted with the wrong
+/// keys.
+pub fn deserialize_unique_map<'de, D, V>(deserializer: D) -> Result<HashMap<String, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    struct UniqueMapVisitor<V>(PhantomData<V>);
+
+    impl<'de, V: Deserialize<'de>> Visitor<'de> for UniqueMapVisitor<V> {
+        type Value = HashMap<String, V>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map of peer ids")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            let mut map = HashMap::new();
+            let mut duplicates = BTreeSet::new();
+            while let Some((peer_id, value)) = access.next_entry::<String, V>()? {
+                if map.contains_key(&peer_id) {
+                    duplicates.insert(peer_id);
+                } else {
+                    map.insert(peer_id, value);
+                }
+            }
+            if !duplicates.is_empty() {
+                let duplicates: Vec<_> = duplicates.into_iter().collect();
+                return Err(de::Error::custom(format!(
+                    "duplicate peer ids: {}",
+                    duplicates.join(", ")
+                )));
+            }
+            Ok(map)
+        }
+    }
+
+    deserializer.deserialize_map(UniqueMapVisitor(PhantomData))
+}
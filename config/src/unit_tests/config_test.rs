@@ -2,7 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use std::fs;
+use crate::trusted_peers::{ConsensusPeersConfig, NetworkPeersConfig, TrustedPeersSource};
+use std::{
+    fmt, fs,
+    sync::{Arc, Mutex},
+};
 
 static EXPECTED_SINGLE_NODE_CONFIG: &[u8] =
     include_bytes!("../../data/configs/single.node.config.toml");
@@ -65,3 +69,572 @@ fn verify_all_configs() {
         }
     }
 }
+
+#[test]
+fn verify_validate_reports_all_missing_paths() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(false);
+    config.consensus.consensus_keypair_file = PathBuf::from("consensus_keypair.config.toml");
+    {
+        let network = &mut config.networks[0];
+        network.network_keypairs_file = PathBuf::from("network_keypairs.config.toml");
+        network.network_peers_file = PathBuf::from("network_peers.config.toml");
+        network.seed_peers_file = PathBuf::from("seed_peers.config.toml");
+    }
+    let error = config
+        .validate()
+        .expect_err("Files were never written to the data dir");
+    let paths = match error {
+        ConfigError::InaccessiblePaths(paths) => paths,
+        error => panic!("Unexpected error: {}", error),
+    };
+    let fields: Vec<_> = paths.iter().map(|path| path.field.as_str()).collect();
+    assert_eq!(
+        fields,
+        vec![
+            "execution.genesis_file_location",
+            "consensus.consensus_keypair_file",
+            "networks[0].network_keypairs_file",
+            "networks[0].network_peers_file",
+            "networks[0].seed_peers_file",
+        ]
+    );
+
+    // Once the files exist the config is valid.
+    for path in &paths {
+        fs::write(&path.path, b"").expect("Unable to write file");
+    }
+    config.validate().expect("All paths exist");
+}
+
+#[test]
+fn verify_validate_resolves_files_next_to_config() {
+    let config = NodeConfig::default_for_test();
+    let config_file = config.base.data_dir_path.join("node.config.toml");
+    config.save_config(&config_file);
+    let mut loaded = NodeConfig::load(&config_file).expect("NodeConfig");
+    loaded.validate().expect("All paths exist");
+
+    // The keys and peers are still checked next to the config file, as they are loaded.
+    let data_dir = TempPath::new();
+    data_dir.create_as_dir().expect("error creating tempdir");
+    loaded.base.data_dir_path = data_dir.path().to_path_buf();
+    fs::write(loaded.get_genesis_transaction_file(), b"").expect("Unable to write file");
+    loaded.validate().expect("All paths exist");
+
+    fs::remove_file(config_file.with_file_name(&loaded.consensus.consensus_keypair_file))
+        .expect("Unable to remove file");
+    match loaded.validate() {
+        Err(ConfigError::InaccessiblePaths(paths)) => {
+            assert_eq!(paths.len(), 1);
+            assert_eq!(paths[0].field, "consensus.consensus_keypair_file");
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn verify_merge_toml() {
+    let mut base: toml::Value = toml::from_str(
+        r#"
+        [base]
+        data_dir_path = "/opt/libra"
+        [consensus]
+        max_block_size = 100
+        proposer_type = "rotating_proposer"
+        [[networks]]
+        role = "validator"
+        [[networks]]
+        role = "full_node"
+        "#,
+    )
+    .unwrap();
+    let overlay: toml::Value = toml::from_str(
+        r#"
+        [consensus]
+        max_block_size = 200
+        contiguous_rounds = 2
+        [[networks]]
+        role = "full_node"
+        "#,
+    )
+    .unwrap();
+    let expected: toml::Value = toml::from_str(
+        r#"
+        [base]
+        data_dir_path = "/opt/libra"
+        [consensus]
+        max_block_size = 200
+        proposer_type = "rotating_proposer"
+        contiguous_rounds = 2
+        [[networks]]
+        role = "full_node"
+        "#,
+    )
+    .unwrap();
+    merge_toml(&mut base, overlay);
+    assert_eq!(base, expected);
+}
+
+#[test]
+fn verify_load_with_overrides() {
+    let dir = TempPath::new();
+    dir.create_as_dir().expect("error creating tempdir");
+    let base = dir.path().join("node.config.toml");
+    fs::write(&base, CONFIG_TEMPLATE).expect("Unable to write file");
+    let first = dir.path().join("first.config.override.toml");
+    fs::write(
+        &first,
+        "[mempool]\ncapacity = 10\ncapacity_per_user = 5\n[storage]\ndir = \"first\"\n",
+    )
+    .expect("Unable to write file");
+    let second = dir.path().join("second.config.override.toml");
+    fs::write(&second, "[mempool]\ncapacity = 20\n").expect("Unable to write file");
+
+    let config = NodeConfig::load_with_overrides(&base, &[&first, &second]).expect("NodeConfig");
+    assert_eq!(config.mempool.capacity, 20);
+    assert_eq!(config.mempool.capacity_per_user, 5);
+    assert_eq!(config.storage.dir, PathBuf::from("first"));
+    assert_eq!(config.networks.len(), 1);
+}
+
+#[test]
+fn verify_default_for_test() {
+    let config = NodeConfig::default_for_test();
+    config.validate().expect("All paths exist");
+    let genesis = config
+        .get_genesis_transaction()
+        .expect("The genesis transaction is written");
+    assert!(genesis.as_signed_user_txn().is_ok());
+
+    // The config reloads into the same keys and peers once saved to the data dir.
+    let config_file = config.base.data_dir_path.join("node.config.toml");
+    config.save_config(&config_file);
+    let loaded = NodeConfig::load(&config_file).expect("NodeConfig");
+    assert_eq!(loaded.consensus, config.consensus);
+    assert_eq!(loaded.networks, config.networks);
+}
+
+#[test]
+fn verify_migrate() {
+    let legacy: toml::Value = toml::from_str("[storage]\ndir = \"legacy\"\n").unwrap();
+    let config = NodeConfig::migrate(legacy).expect("Legacy config");
+    assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+    assert_eq!(config.storage.dir, PathBuf::from("legacy"));
+
+    let current: toml::Value =
+        toml::from_str(&format!("version = {}\n", CURRENT_CONFIG_VERSION)).unwrap();
+    let config = NodeConfig::migrate(current).expect("Current config");
+    assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+
+    let future: toml::Value =
+        toml::from_str(&format!("version = {}\n", CURRENT_CONFIG_VERSION + 1)).unwrap();
+    NodeConfig::migrate(future).expect_err("Config from the future");
+    let invalid: toml::Value = toml::from_str("version = -1\n").unwrap();
+    NodeConfig::migrate(invalid).expect_err("Negative version");
+}
+
+#[test]
+fn verify_reload_peers() {
+    let config = NodeConfig::default_for_test();
+    let config_file = config.base.data_dir_path.join("node.config.toml");
+    let peers = config.reloadable_peers(&config_file);
+    let peer_id = config.networks[0].peer_id.clone();
+    assert_eq!(peers.consensus_peers(), config.consensus.consensus_peers);
+    assert_eq!(
+        peers.network_peers(&peer_id),
+        Some(config.networks[0].network_peers.clone())
+    );
+
+    let (_, consensus_peers, network_peers) = ConfigHelpers::gen_validator_nodes(2, None);
+    let data_dir = &config.base.data_dir_path;
+    consensus_peers.save_config(data_dir.join(&config.consensus.consensus_peers_file));
+    network_peers.save_config(data_dir.join(&config.networks[0].network_peers_file));
+    peers.reload().expect("Unable to reload the peers");
+    assert_eq!(peers.consensus_peers(), consensus_peers);
+    assert_eq!(peers.network_peers(&peer_id), Some(network_peers.clone()));
+    assert_eq!(
+        peers.seed_peers(&peer_id).unwrap().get(),
+        config.networks[0].seed_peers
+    );
+
+    // A malformed file leaves every peer set intact, including the ones of valid files.
+    let (_, new_consensus_peers, _) = ConfigHelpers::gen_validator_nodes(1, None);
+    new_consensus_peers.save_config(data_dir.join(&config.consensus.consensus_peers_file));
+    fs::write(
+        data_dir.join(&config.networks[0].network_peers_file),
+        b"not a peers file",
+    )
+    .expect("Unable to write file");
+    peers
+        .reload()
+        .expect_err("The network peers file is malformed");
+    assert_eq!(peers.consensus_peers(), consensus_peers);
+    assert_eq!(peers.network_peers(&peer_id), Some(network_peers));
+}
+
+/// Peer set source standing for the on-chain set, unavailable when `peers` is unset.
+#[derive(Debug)]
+struct FakePeersSource<T> {
+    peers: Mutex<Option<T>>,
+}
+
+impl<T> FakePeersSource<T> {
+    fn new() -> Self {
+        Self {
+            peers: Mutex::new(None),
+        }
+    }
+}
+
+impl<T: Clone + fmt::Debug + Send> TrustedPeersSource<T> for FakePeersSource<T> {
+    fn fetch(&self) -> Result<T> {
+        self.peers
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| format_err!("The peer set is unavailable"))
+    }
+}
+
+#[test]
+fn verify_reload_peers_from_source() {
+    let config = NodeConfig::default_for_test();
+    let mut peers = config.reloadable_peers(config.base.data_dir_path.join("node.config.toml"));
+    let peer_id = config.networks[0].peer_id.clone();
+    let consensus_source = Arc::new(FakePeersSource::<ConsensusPeersConfig>::new());
+    let network_source = Arc::new(FakePeersSource::<NetworkPeersConfig>::new());
+    peers.set_consensus_peers_source(consensus_source.clone());
+    peers
+        .set_network_peers_source(&peer_id, network_source.clone())
+        .expect("Unknown network");
+    peers
+        .set_network_peers_source("unknown", network_source.clone())
+        .expect_err("No network of this peer id");
+
+    let (_, consensus_peers, network_peers) = ConfigHelpers::gen_validator_nodes(2, None);
+    *consensus_source.peers.lock().unwrap() = Some(consensus_peers.clone());
+    *network_source.peers.lock().unwrap() = Some(network_peers.clone());
+    // The peer files are not read anymore.
+    fs::write(
+        config
+            .base
+            .data_dir_path
+            .join(&config.consensus.consensus_peers_file),
+        b"not a peers file",
+    )
+    .expect("Unable to write file");
+    peers.reload().expect("Unable to reload the peers");
+    assert_eq!(peers.consensus_peers(), consensus_peers);
+    assert_eq!(peers.network_peers(&peer_id), Some(network_peers.clone()));
+
+    // A source failing to fetch its set leaves every set intact.
+    *consensus_source.peers.lock().unwrap() = Some(config.consensus.consensus_peers.clone());
+    *network_source.peers.lock().unwrap() = None;
+    peers
+        .reload()
+        .expect_err("The network peer set is unavailable");
+    assert_eq!(peers.consensus_peers(), consensus_peers);
+    assert_eq!(peers.network_peers(&peer_id), Some(network_peers));
+}
+
+#[test]
+fn verify_precheck_bindable() {
+    let config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.precheck_bindable().expect("Every port is available");
+
+    let storage = &config.storage;
+    let _busy = std::net::TcpListener::bind((storage.address.as_str(), storage.port))
+        .expect("Unable to bind the storage port");
+    let error = config
+        .precheck_bindable()
+        .expect_err("The storage port is in use");
+    let addresses = match error {
+        ConfigError::UnbindableAddresses(addresses) => addresses,
+        error => panic!("Unexpected error: {}", error),
+    };
+    assert_eq!(addresses.len(), 1);
+    assert_eq!(addresses[0].field, "storage.port");
+    assert_eq!(
+        addresses[0].address,
+        format!("{}:{}", storage.address, storage.port)
+    );
+}
+
+#[test]
+fn verify_check_peers_consistency() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config
+        .check_peers_consistency(true)
+        .expect("The test config peers are consistent");
+
+    let network = &mut config.networks[0];
+    let mut trusted_only = PeerId::random().to_string();
+    let mut seed_only = PeerId::random().to_string();
+    // Both peers are reported under the same network, in order of their ids.
+    if seed_only < trusted_only {
+        std::mem::swap(&mut seed_only, &mut trusted_only);
+    }
+    let peer_info = network.network_peers.peers[&network.peer_id].clone();
+    network
+        .network_peers
+        .peers
+        .insert(trusted_only.clone(), peer_info);
+    let addrs = network.seed_peers.seed_peers[&network.peer_id].clone();
+    network
+        .seed_peers
+        .seed_peers
+        .insert(seed_only.clone(), addrs);
+    // The node itself does not need to be a seed peer.
+    let own_peer_id = network.peer_id.clone();
+    network.seed_peers.seed_peers.remove(&own_peer_id);
+
+    config
+        .check_peers_consistency(false)
+        .expect("Inconsistent peers are only logged");
+    match config.check_peers_consistency(true) {
+        Err(ConfigError::InconsistentPeers(peers)) => assert_eq!(
+            peers,
+            vec![
+                InconsistentPeer {
+                    network: "networks[0]".to_string(),
+                    peer_id: trusted_only,
+                    reason: "trusted peer missing from the seed peers".to_string(),
+                },
+                InconsistentPeer {
+                    network: "networks[0]".to_string(),
+                    peer_id: seed_only,
+                    reason: "seed peer missing from the trusted peers".to_string(),
+                },
+            ]
+        ),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    // Networks that are not permissioned are not checked.
+    config.networks[0].is_permissioned = false;
+    config
+        .check_peers_consistency(true)
+        .expect("The network is not permissioned");
+}
+
+#[test]
+fn verify_config_source_parse() {
+    assert_eq!("-".parse(), Ok(ConfigSource::Stdin));
+    assert_eq!(
+        "https://example.com/node.config.toml".parse(),
+        Ok(ConfigSource::Url(
+            "https://example.com/node.config.toml".to_string()
+        ))
+    );
+    assert_eq!(
+        "configs/node.config.toml".parse(),
+        Ok(ConfigSource::File(PathBuf::from(
+            "configs/node.config.toml"
+        )))
+    );
+}
+
+#[test]
+fn verify_load_from_file() {
+    let config = NodeConfig::default_for_test();
+    let config_file = config.base.data_dir_path.join("node.config.toml");
+    config.save_config(&config_file);
+
+    let loaded = NodeConfig::load_from(&ConfigSource::File(config_file)).expect("NodeConfig");
+    assert_eq!(loaded.consensus, config.consensus);
+    assert_eq!(loaded.networks, config.networks);
+    NodeConfig::load_from(&ConfigSource::File(
+        config.base.data_dir_path.join("missing.config.toml"),
+    ))
+    .expect_err("The config file does not exist");
+}
+
+#[test]
+fn verify_load_from_stdin() {
+    let mut config = NodeConfig::default_for_test();
+    // Paths are relative to the working directory for stdin, make them absolute.
+    let data_dir = config.base.data_dir_path.clone();
+    config.consensus.consensus_keypair_file =
+        data_dir.join(&config.consensus.consensus_keypair_file);
+    config.consensus.consensus_peers_file = data_dir.join(&config.consensus.consensus_peers_file);
+    for network in &mut config.networks {
+        network.network_keypairs_file = data_dir.join(&network.network_keypairs_file);
+        network.network_peers_file = data_dir.join(&network.network_peers_file);
+        network.seed_peers_file = data_dir.join(&network.seed_peers_file);
+    }
+    let contents = toml::to_string(&config).expect("Error serializing");
+
+    let source = ConfigSource::Stdin;
+    let read = source
+        .read_from(contents.as_bytes())
+        .expect("Unable to read stdin");
+    let loaded = NodeConfig::load_from_contents(&source, &read).expect("NodeConfig");
+    assert_eq!(loaded.consensus, config.consensus);
+    assert_eq!(loaded.networks, config.networks);
+    NodeConfig::load_from_contents(&source, "not a config").expect_err("Malformed config");
+}
+
+#[cfg(not(feature = "url-config"))]
+#[test]
+fn verify_load_from_url_disabled() {
+    let source = ConfigSource::Url("http://localhost/node.config.toml".to_string());
+    NodeConfig::load_from(&source).expect_err("URL sources require the url-config feature");
+}
+
+#[test]
+fn verify_set_data_dir() {
+    let mut config = NodeConfig::default();
+    config.storage.dir = PathBuf::from("libradb/db");
+    config.metrics.dir = PathBuf::from("/var/metrics");
+    config.execution.genesis_file_location = PathBuf::from("genesis.blob");
+    config.consensus.txn_log_file = Some(PathBuf::from("txns.log"));
+    config.set_data_dir("node1");
+
+    assert_eq!(config.base.data_dir_path, PathBuf::from("node1"));
+    assert_eq!(config.get_storage_dir(), PathBuf::from("node1/libradb/db"));
+    assert_eq!(
+        config.get_genesis_transaction_file(),
+        PathBuf::from("node1/genesis.blob")
+    );
+    assert_eq!(
+        config.get_metrics_dir(),
+        Some(PathBuf::from("/var/metrics"))
+    );
+    assert_eq!(
+        config.get_txn_log_file(),
+        Some(PathBuf::from("node1/txns.log"))
+    );
+    // The fields themselves are left relative, and the config-relative files are not moved.
+    assert_eq!(config.storage.dir, PathBuf::from("libradb/db"));
+    assert_eq!(
+        config.consensus.consensus_keypair_file,
+        PathBuf::from("consensus_keypair.config.toml")
+    );
+
+    // Relocating the node again moves every relative path to the new data directory.
+    config.set_data_dir("/opt/libra");
+    assert_eq!(
+        config.get_storage_dir(),
+        PathBuf::from("/opt/libra/libradb/db")
+    );
+    assert_eq!(
+        config.get_genesis_transaction_file(),
+        PathBuf::from("/opt/libra/genesis.blob")
+    );
+    assert_eq!(
+        config.get_txn_log_file(),
+        Some(PathBuf::from("/opt/libra/txns.log"))
+    );
+}
+
+#[test]
+fn verify_set_data_dir_validates_files() {
+    let mut config = NodeConfig::default();
+    config.set_data_dir("/nonexistent/libra");
+    match config.validate() {
+        Err(ConfigError::InaccessiblePaths(paths)) => {
+            assert!(paths
+                .iter()
+                .all(|inaccessible| inaccessible.path.starts_with("/nonexistent/libra")));
+            assert!(paths
+                .iter()
+                .any(|inaccessible| inaccessible.field == "consensus.consensus_keypair_file"));
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn verify_accessors() {
+    let config = NodeConfig::default_for_test();
+    assert_eq!(config.base(), &config.base);
+    assert_eq!(config.metrics(), &config.metrics);
+    assert_eq!(config.execution(), &config.execution);
+    assert_eq!(config.admission_control(), &config.admission_control);
+    assert_eq!(config.debug_interface(), &config.debug_interface);
+    assert_eq!(config.storage(), &config.storage);
+    assert_eq!(config.networks(), &config.networks[..]);
+    assert_eq!(config.consensus(), &config.consensus);
+    assert_eq!(config.mempool(), &config.mempool);
+    assert_eq!(config.state_sync(), &config.state_sync);
+    assert_eq!(config.logger(), &config.logger);
+    assert_eq!(config.vm_config(), &config.vm_config);
+
+    let peer_id = &config.networks[0].peer_id;
+    assert_eq!(config.network(peer_id), Some(&config.networks[0]));
+    assert_eq!(config.network("unknown"), None);
+}
+
+#[test]
+fn verify_unknown_keys() {
+    let raw: toml::Value =
+        toml::from_str("[storage]\ndirr = \"typo\"\n\n[mempool]\nunknown = 1\n").unwrap();
+    let config = NodeConfig::migrate_with(raw.clone(), UnknownKeys::Warn).expect("Lenient config");
+    assert_eq!(config.storage, StorageConfig::default());
+    let error = NodeConfig::migrate_with(raw, UnknownKeys::Deny).expect_err("Strict config");
+    assert_eq!(
+        error.to_string(),
+        "Unknown config keys: mempool.unknown, storage.dirr"
+    );
+
+    // A complete config has no unknown keys, including in its arrays of tables.
+    let config = NodeConfig::default_for_test();
+    let mut raw = toml::Value::try_from(&config).unwrap();
+    NodeConfig::migrate_with(raw.clone(), UnknownKeys::Deny).expect("Complete config");
+    raw.get_mut("networks")
+        .and_then(|networks| networks.get_mut(0))
+        .and_then(toml::Value::as_table_mut)
+        .unwrap()
+        .insert("listen_adress".to_string(), toml::Value::Boolean(true));
+    let error = NodeConfig::migrate_with(raw, UnknownKeys::Deny).expect_err("Strict config");
+    assert_eq!(
+        error.to_string(),
+        "Unknown config keys: networks[0].listen_adress"
+    );
+}
+
+#[test]
+fn verify_example_toml() {
+    let example = NodeConfig::example_toml();
+    let raw: toml::Value = toml::from_str(&example).expect("Example is valid TOML");
+    let config = NodeConfig::migrate_with(raw, UnknownKeys::Deny).expect("Example is valid");
+    assert_eq!(config, NodeConfig::example());
+
+    // Every field is documented, and every documented field exists.
+    let (_, paths) = example_config::render_example();
+    for path in &paths {
+        assert!(
+            example_config::FIELD_DOCS
+                .iter()
+                .any(|(documented, _)| documented == path),
+            "Field {} is not documented",
+            path
+        );
+    }
+    for (documented, _) in example_config::FIELD_DOCS {
+        assert!(
+            paths.iter().any(|path| path == documented),
+            "Documented field {} does not exist",
+            documented
+        );
+    }
+
+    // The fields unset by default are valid once uncommented.
+    let mut uncommented = example.clone();
+    for (path, _, value) in example_config::UNSET_FIELDS {
+        let key = path.rsplit('.').next().unwrap();
+        let line = format!("# {} = {}", key, value);
+        assert!(example.contains(&line), "Field {} is missing", path);
+        uncommented = uncommented.replace(&line, &line[2..]);
+    }
+    let raw: toml::Value = toml::from_str(&uncommented).expect("Example is valid TOML");
+    let config = NodeConfig::migrate_with(raw, UnknownKeys::Deny).expect("Example is valid");
+    assert_eq!(
+        config.consensus.txn_log_file,
+        Some(PathBuf::from("txn.log"))
+    );
+    assert_eq!(
+        config.consensus.mempool_proxy.connection.max_message_len,
+        Some(104_857_600)
+    );
+}
@@ -1,11 +1,222 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use super::SeedPeersConfigHelpers;
+use super::*;
 use crate::trusted_peers::ConfigHelpers;
+use libra_tools::tempdir::TempPath;
 
 #[test]
 fn generate_test_config() {
     let (_, _, network_peers_config) = ConfigHelpers::gen_validator_nodes(10, None);
     let _ = SeedPeersConfigHelpers::get_test_config(&network_peers_config, None);
 }
+
+#[test]
+fn resolve_dns_hosts() {
+    let parse = |addr: &str| addr.parse::<Multiaddr>().unwrap();
+    let mut config = SeedPeersConfig::default();
+    config.seed_peers.insert(
+        "dns".to_string(),
+        vec![
+            parse("/dns4/localhost/tcp/6180"),
+            parse("/dns4/unresolvable.invalid/tcp/6180"),
+        ],
+    );
+    config
+        .seed_peers
+        .insert("ip".to_string(), vec![parse("/ip4/10.0.0.1/tcp/6180")]);
+    config.seed_peers.insert(
+        "unresolvable".to_string(),
+        vec![parse("/dns4/unresolvable.invalid/tcp/6180")],
+    );
+
+    config.resolve();
+    assert_eq!(config.seed_peers.len(), 2);
+    assert!(config.seed_peers["dns"].contains(&parse("/ip4/127.0.0.1/tcp/6180")));
+    assert_eq!(
+        config.seed_peers["ip"],
+        vec![parse("/ip4/10.0.0.1/tcp/6180")]
+    );
+}
+
+#[test]
+fn validate_reports_all_malformed_addresses() {
+    let serialized = r#"
+        [seed_peers]
+        valid = ["/ip4/10.0.0.1/tcp/6180", "/dns4/validator.example.com/tcp/6180"]
+        malformed = ["/ip4/10.0.0.300/tcp/6180", "/ipx/10.0.0.1/tcp/6180"]
+        unusable = [
+            "/ip4/10.0.0.1",
+            "/tcp/6180",
+            "/ip4/10.0.0.1/udp/6180",
+            "/ip4/10.0.0.1/tcp/6180/ws",
+        ]
+    "#;
+    let error = SeedPeersConfig::parse_validated(serialized).unwrap_err();
+    let SeedPeersError::InvalidAddresses(invalid_addrs) = error
+        .downcast::<SeedPeersError>()
+        .expect("Addresses are invalid");
+    let invalid: Vec<_> = invalid_addrs
+        .iter()
+        .map(|invalid| (invalid.peer_id.as_str(), invalid.addr.as_str()))
+        .collect();
+    assert_eq!(
+        invalid,
+        vec![
+            ("malformed", "/ip4/10.0.0.300/tcp/6180"),
+            ("malformed", "/ipx/10.0.0.1/tcp/6180"),
+            ("unusable", "/ip4/10.0.0.1"),
+            ("unusable", "/ip4/10.0.0.1/tcp/6180/ws"),
+            ("unusable", "/ip4/10.0.0.1/udp/6180"),
+            ("unusable", "/tcp/6180"),
+        ]
+    );
+    let reasons: Vec<_> = invalid_addrs[2..]
+        .iter()
+        .map(|invalid| invalid.reason.as_str())
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "missing tcp port",
+            "unexpected /ws after the tcp port",
+            "expected a tcp port after the host, found /udp/6180",
+            "expected an ip4, ip6, dns4 or dns6 host, found /tcp/6180",
+        ]
+    );
+
+    let valid = r#"
+        [seed_peers]
+        valid = ["/ip4/10.0.0.1/tcp/6180", "/dns4/validator.example.com/tcp/6180"]
+    "#;
+    let config = SeedPeersConfig::parse_validated(valid).unwrap();
+    assert_eq!(config.seed_peers["valid"].len(), 2);
+    config.validate().unwrap();
+}
+
+#[test]
+fn add_and_remove_peers() {
+    let addrs = vec!["/ip4/10.0.0.1/tcp/6180".parse::<Multiaddr>().unwrap()];
+    let other_addrs = vec!["/ip4/10.0.0.2/tcp/6180".parse::<Multiaddr>().unwrap()];
+    let mut config = SeedPeersConfig::default();
+
+    assert!(config.add_peer("peer".to_string(), addrs.clone()));
+    assert!(!config.add_peer("peer".to_string(), addrs.clone()));
+    assert!(config.remove_peer("peer"));
+    assert!(!config.remove_peer("peer"));
+    assert!(config.seed_peers.is_empty());
+    assert!(config.add_peer("peer".to_string(), addrs.clone()));
+    assert!(config.add_peer("peer".to_string(), other_addrs.clone()));
+    assert_eq!(config.seed_peers.len(), 1);
+    assert_eq!(config.seed_peers["peer"], other_addrs);
+}
+
+#[test]
+fn update_shared_peers_from_several_threads() {
+    let shared = SharedSeedPeers::default();
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let addr = format!("/ip4/10.0.0.{}/tcp/6180", i);
+                assert!(shared.add_peer(i.to_string(), vec![addr.parse().unwrap()]));
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(shared.get().seed_peers.len(), 4);
+    assert!(shared.remove_peer("0"));
+    assert!(!shared.remove_peer("0"));
+    assert_eq!(shared.get().seed_peers.len(), 3);
+}
+
+#[test]
+fn iter_by_priority() {
+    let config = SeedPeersConfig::parse_validated(
+        r#"
+        [seed_peers]
+        a = ["/ip4/10.0.0.1/tcp/6180"]
+        b = ["/ip4/10.0.0.2/tcp/6180"]
+        c = ["/ip4/10.0.0.3/tcp/6180"]
+        d = ["/ip4/10.0.0.4/tcp/6180"]
+        e = ["/ip4/10.0.0.5/tcp/6180"]
+
+        [priorities]
+        d = 1
+        b = 2
+        e = 1
+        "#,
+    )
+    .unwrap();
+    let order: Vec<_> = config
+        .iter_by_priority()
+        .map(|(peer_id, _)| peer_id.as_str())
+        .collect();
+    assert_eq!(order, vec!["d", "e", "b", "a", "c"]);
+
+    // Configs without priorities are dialed in peer id order, and serialize as before.
+    let mut config = config;
+    config.priorities.clear();
+    let order: Vec<_> = config
+        .iter_by_priority()
+        .map(|(peer_id, _)| peer_id.as_str())
+        .collect();
+    assert_eq!(order, vec!["a", "b", "c", "d", "e"]);
+    assert!(!toml::to_string(&config).unwrap().contains("priorities"));
+}
+
+#[test]
+fn load_and_merge_many_files() {
+    let dir = TempPath::new();
+    dir.create_as_dir().expect("error creating tempdir");
+    let us_east = r#"
+        [seed_peers]
+        shared = ["/ip4/10.0.0.1/tcp/6180", "/ip4/10.0.0.2/tcp/6180"]
+        us_east = ["/ip4/10.0.1.1/tcp/6180"]
+        [priorities]
+        shared = 1
+    "#;
+    let eu_west = r#"
+        [seed_peers]
+        shared = ["/ip4/10.0.0.2/tcp/6180", "/ip4/10.0.0.3/tcp/6180"]
+        eu_west = ["/ip4/10.0.2.1/tcp/6180"]
+        [priorities]
+        shared = 0
+    "#;
+    std::fs::write(dir.path().join("us_east.toml"), us_east).unwrap();
+    std::fs::write(dir.path().join("eu_west.toml"), eu_west).unwrap();
+    std::fs::write(dir.path().join("README"), "not a seed peers file").unwrap();
+
+    let parse = |addr: &str| addr.parse::<Multiaddr>().unwrap();
+    let config = load_many(&[
+        &dir.path().join("us_east.toml"),
+        &dir.path().join("eu_west.toml"),
+    ])
+    .unwrap();
+    assert_eq!(config.seed_peers.len(), 3);
+    assert_eq!(
+        config.seed_peers["shared"],
+        vec![
+            parse("/ip4/10.0.0.1/tcp/6180"),
+            parse("/ip4/10.0.0.2/tcp/6180"),
+            parse("/ip4/10.0.0.3/tcp/6180"),
+        ]
+    );
+    assert_eq!(config.priorities["shared"], 0);
+
+    // The files of a directory are merged in the order of their names.
+    let dir_config = load_dir(dir.path()).unwrap();
+    assert_eq!(dir_config.seed_peers.len(), 3);
+    assert_eq!(
+        dir_config.seed_peers["shared"],
+        vec![
+            parse("/ip4/10.0.0.2/tcp/6180"),
+            parse("/ip4/10.0.0.3/tcp/6180"),
+            parse("/ip4/10.0.0.1/tcp/6180"),
+        ]
+    );
+
+    assert!(load_many(&[&dir.path().join("missing.toml")]).is_err());
+}
@@ -1,7 +1,12 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ConfigHelpers;
+use super::*;
+use crate::config::PersistableConfig;
+use libra_crypto::{
+    bls12381::BLS12381PrivateKey,
+    traits::{SigningKey, Uniform},
+};
 
 #[test]
 fn generate_test_config() {
@@ -9,3 +14,358 @@ fn generate_test_config() {
         ConfigHelpers::gen_validator_nodes(10, None);
     let (_keys, _network_peers_config) = ConfigHelpers::gen_full_nodes(10, None);
 }
+
+#[test]
+fn validator_set_requires_ed25519_network_keys() {
+    let (_, consensus_peers_config, mut network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(2, None);
+    let validator_set = consensus_peers_config
+        .get_validator_set(&network_peers_config)
+        .expect("Generated validators have ed25519 keys");
+    assert_eq!(validator_set.payload().len(), 2);
+
+    let mut rng = StdRng::from_seed([3u8; 32]);
+    let bls_private_key = BLS12381PrivateKey::generate_for_testing(&mut rng);
+    let peer_id = network_peers_config.peers.keys().next().unwrap().clone();
+    network_peers_config
+        .peers
+        .get_mut(&peer_id)
+        .unwrap()
+        .network_signing_pubkey = TaggedPublicKey::bls12381(&(&bls_private_key).into());
+    let error = consensus_peers_config
+        .get_validator_set(&network_peers_config)
+        .expect_err("A bls12381 network signing key is not a validator key");
+    assert!(error.to_string().contains(&peer_id));
+}
+
+#[test]
+fn verify_signature_of_trusted_peer() {
+    let (mut private_keys, _, network_peers_config) = ConfigHelpers::gen_validator_nodes(2, None);
+    let mut peer_ids: Vec<_> = private_keys.keys().cloned().collect();
+    peer_ids.sort();
+    let signer = peer_ids[0].to_string();
+    let other = peer_ids[1].to_string();
+    let (_, network_private_keys) = private_keys.remove(&peer_ids[0]).unwrap();
+    let message = HashValue::from_sha3_256(b"handshake");
+    let signature = network_private_keys
+        .network_signing_private_key
+        .sign_message(&message);
+
+    network_peers_config
+        .verify(&signer, &message, &signature.to_bytes())
+        .unwrap();
+    assert_eq!(
+        network_peers_config.verify(&other, &message, &signature.to_bytes()),
+        Err(VerifyError::BadSignature)
+    );
+    assert_eq!(
+        network_peers_config.verify(&signer, &HashValue::zero(), &signature.to_bytes()),
+        Err(VerifyError::BadSignature)
+    );
+    let unknown = AccountAddress::random().to_string();
+    assert_eq!(
+        network_peers_config.verify(&unknown, &message, &signature.to_bytes()),
+        Err(VerifyError::UnknownPeer(unknown.clone()))
+    );
+}
+
+#[test]
+fn verify_signature_during_key_rotation() {
+    let (mut private_keys, _, mut network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(1, None);
+    let (peer_id, (_, current_keys)) = private_keys.drain().next().unwrap();
+    let peer_id = peer_id.to_string();
+    let mut rng = StdRng::from_seed([2u8; 32]);
+    let (next_private_key, next_public_key) = compat::generate_keypair(&mut rng);
+    let (stale_private_key, _) = compat::generate_keypair(&mut rng);
+    let message = HashValue::from_sha3_256(b"handshake");
+    let current_signature = current_keys
+        .network_signing_private_key
+        .sign_message(&message);
+    let next_signature = next_private_key.sign_message(&message);
+    let stale_signature = stale_private_key.sign_message(&message);
+
+    network_peers_config
+        .peers
+        .get_mut(&peer_id)
+        .unwrap()
+        .next_network_signing_pubkey = Some(TaggedPublicKey::ed25519(&next_public_key));
+    let round_tripped = NetworkPeersConfig::parse(&toml::to_string(&network_peers_config).unwrap())
+        .expect("Unable to parse the rotating peers");
+    assert_eq!(round_tripped, network_peers_config);
+    // Both keys are accepted during the rotation window.
+    network_peers_config
+        .verify(&peer_id, &message, &current_signature.to_bytes())
+        .unwrap();
+    network_peers_config
+        .verify(&peer_id, &message, &next_signature.to_bytes())
+        .unwrap();
+    assert_eq!(
+        network_peers_config.verify(&peer_id, &message, &stale_signature.to_bytes()),
+        Err(VerifyError::BadSignature)
+    );
+
+    // Once the rotation completes, the previous key is stale.
+    let peer_info = network_peers_config.peers.get_mut(&peer_id).unwrap();
+    assert!(peer_info.complete_key_rotation());
+    assert!(!peer_info.complete_key_rotation());
+    assert_eq!(
+        peer_info.network_signing_pubkey,
+        TaggedPublicKey::ed25519(&next_public_key)
+    );
+    network_peers_config
+        .verify(&peer_id, &message, &next_signature.to_bytes())
+        .unwrap();
+    assert_eq!(
+        network_peers_config.verify(&peer_id, &message, &current_signature.to_bytes()),
+        Err(VerifyError::BadSignature)
+    );
+}
+
+#[test]
+fn verify_signature_per_key_algorithm() {
+    let (mut private_keys, _, mut network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(3, None);
+    let mut peer_ids: Vec<_> = private_keys.keys().cloned().collect();
+    peer_ids.sort();
+    let (ed25519_peer, bls_peer, unknown_peer) = (
+        peer_ids[0].to_string(),
+        peer_ids[1].to_string(),
+        peer_ids[2].to_string(),
+    );
+    let (_, ed25519_keys) = private_keys.remove(&peer_ids[0]).unwrap();
+    let (_, unknown_peer_keys) = private_keys.remove(&peer_ids[2]).unwrap();
+    let mut rng = StdRng::from_seed([3u8; 32]);
+    let bls_private_key = BLS12381PrivateKey::generate_for_testing(&mut rng);
+    let bls_key = TaggedPublicKey::bls12381(&(&bls_private_key).into());
+    let message = HashValue::from_sha3_256(b"handshake");
+    let sign_ed25519 = |keys: &NetworkPrivateKeys| {
+        keys.network_signing_private_key
+            .sign_message(&message)
+            .to_bytes()
+    };
+    let ed25519_signature = sign_ed25519(&ed25519_keys);
+    let bls_signature = bls_private_key.sign_message(&message).to_bytes();
+
+    network_peers_config
+        .peers
+        .get_mut(&bls_peer)
+        .unwrap()
+        .network_signing_pubkey = bls_key.clone();
+    let unknown_key = TaggedPublicKey {
+        algorithm: "dilithium2".to_string(),
+        key: vec![7u8; 32],
+    };
+    network_peers_config
+        .peers
+        .get_mut(&unknown_peer)
+        .unwrap()
+        .network_signing_pubkey = unknown_key.clone();
+    let round_tripped = NetworkPeersConfig::parse(&toml::to_string(&network_peers_config).unwrap())
+        .expect("Unable to parse the tagged peers");
+    assert_eq!(round_tripped, network_peers_config);
+    // The ed25519 keys are written untagged, as in the peers files predating the tags.
+    let ed25519_key = network_peers_config.peers[&ed25519_peer]
+        .network_signing_pubkey
+        .clone();
+    assert!(!ed25519_key.to_string().contains(':'));
+    assert_eq!(
+        format!("ed25519:{}", ed25519_key)
+            .parse::<TaggedPublicKey>()
+            .unwrap(),
+        ed25519_key
+    );
+    assert!(bls_key.to_string().starts_with("bls12381:"));
+    assert_eq!(
+        unknown_key.to_string().parse::<TaggedPublicKey>().unwrap(),
+        unknown_key
+    );
+    assert!("bls12381:0707".parse::<TaggedPublicKey>().is_err());
+    assert!(ed25519_key.to_ed25519().is_some());
+    assert!(bls_key.to_ed25519().is_none());
+
+    // Each signature is verified by the routine of the algorithm of the key of its signer.
+    network_peers_config
+        .verify(&ed25519_peer, &message, &ed25519_signature)
+        .unwrap();
+    network_peers_config
+        .verify(&bls_peer, &message, &bls_signature)
+        .unwrap();
+    assert_eq!(
+        network_peers_config.verify(&ed25519_peer, &message, &bls_signature),
+        Err(VerifyError::BadSignature)
+    );
+    assert_eq!(
+        network_peers_config.verify(&bls_peer, &message, &ed25519_signature),
+        Err(VerifyError::BadSignature)
+    );
+    assert_eq!(
+        network_peers_config.verify(&unknown_peer, &message, &bls_signature),
+        Err(VerifyError::UnsupportedAlgorithm("dilithium2".to_string()))
+    );
+
+    // A peer migrating to a new algorithm rotates to a key of it, both keys are accepted until
+    // the rotation completes.
+    network_peers_config
+        .peers
+        .get_mut(&ed25519_peer)
+        .unwrap()
+        .next_network_signing_pubkey = Some(bls_key.clone());
+    network_peers_config
+        .verify(&ed25519_peer, &message, &ed25519_signature)
+        .unwrap();
+    network_peers_config
+        .verify(&ed25519_peer, &message, &bls_signature)
+        .unwrap();
+    // A peer migrating from an unsupported algorithm is verified with its supported next key,
+    // a signature matching neither reports the error of its current key.
+    network_peers_config
+        .peers
+        .get_mut(&unknown_peer)
+        .unwrap()
+        .next_network_signing_pubkey = Some(TaggedPublicKey::ed25519(
+        &(&unknown_peer_keys.network_signing_private_key).into(),
+    ));
+    network_peers_config
+        .verify(&unknown_peer, &message, &sign_ed25519(&unknown_peer_keys))
+        .unwrap();
+    assert_eq!(
+        network_peers_config.verify(&unknown_peer, &message, &ed25519_signature),
+        Err(VerifyError::UnsupportedAlgorithm("dilithium2".to_string()))
+    );
+}
+
+#[test]
+fn json_round_trip() {
+    let (_, consensus_peers_config, network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(3, None);
+
+    let toml = toml::to_string(&network_peers_config).unwrap();
+    let json = NetworkPeersConfig::parse(&toml).unwrap().to_json().unwrap();
+    let from_json = NetworkPeersConfig::from_json(&json).unwrap();
+    assert_eq!(from_json, network_peers_config);
+    assert_eq!(toml::to_string(&from_json).unwrap(), toml);
+
+    let json = consensus_peers_config.to_json().unwrap();
+    assert_eq!(
+        ConsensusPeersConfig::from_json(&json).unwrap(),
+        consensus_peers_config
+    );
+}
+
+#[test]
+fn json_with_unknown_field() {
+    let (_, consensus_peers_config, _) = ConfigHelpers::gen_validator_nodes(1, None);
+    let (peer_id, peer_info) = consensus_peers_config.peers.iter().next().unwrap();
+    let json = format!(
+        r#"{{"{}": {{"c": "{}", "weight": 1}}}}"#,
+        peer_id,
+        peer_info.consensus_pubkey.to_encoded_string().unwrap()
+    );
+    let error = ConsensusPeersConfig::from_json(&json).unwrap_err();
+    assert!(error.to_string().contains("unknown field `weight`"));
+}
+
+#[test]
+fn duplicate_peer_ids() {
+    let (_, consensus_peers_config, _) = ConfigHelpers::gen_validator_nodes(3, None);
+    let mut entries: Vec<_> = consensus_peers_config
+        .peers
+        .iter()
+        .map(|(peer_id, peer_info)| {
+            (
+                peer_id.clone(),
+                peer_info.consensus_pubkey.to_encoded_string().unwrap(),
+            )
+        })
+        .collect();
+    entries.sort();
+    let (first, second, third) = (&entries[0], &entries[1], &entries[2]);
+    // The first two peers are listed twice, the second time with the key of the third peer.
+    let json = format!(
+        r#"{{"{}": {{"c": "{}"}}, "{}": {{"c": "{}"}}, "{}": {{"c": "{}"}}, "{}": {{"c": "{}"}}, "{}": {{"c": "{}"}}}}"#,
+        first.0, first.1, second.0, second.1, third.0, third.1, first.0, third.1, second.0, third.1
+    );
+    let error = ConsensusPeersConfig::from_json(&json).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains(&format!("duplicate peer ids: {}, {}", first.0, second.0)));
+
+    let toml = format!(
+        "[{}]\nc = \"{}\"\n[{}]\nc = \"{}\"\n",
+        first.0, first.1, first.0, third.1
+    );
+    assert!(ConsensusPeersConfig::parse(&toml).is_err());
+}
+
+#[test]
+fn diff_peer_sets() {
+    let (_, consensus_peers_config, network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(3, None);
+    let (_, other_consensus_peers_config, other_network_peers_config) =
+        ConfigHelpers::gen_validator_nodes(1, Some([1u8; 32]));
+    let mut peer_ids: Vec<_> = consensus_peers_config.peers.keys().cloned().collect();
+    peer_ids.sort();
+    let (removed, changed) = (&peer_ids[0], &peer_ids[1]);
+    let added = other_consensus_peers_config.peers.keys().next().unwrap();
+    assert!(consensus_peers_config
+        .diff(&consensus_peers_config)
+        .is_empty());
+
+    // One peer leaves, one joins and one changes its key.
+    let mut new_consensus_peers_config = consensus_peers_config.clone();
+    new_consensus_peers_config.peers.remove(removed);
+    new_consensus_peers_config.peers.insert(
+        added.clone(),
+        other_consensus_peers_config.peers[added].clone(),
+    );
+    let new_key = other_consensus_peers_config.peers[added].clone();
+    new_consensus_peers_config
+        .peers
+        .insert(changed.clone(), new_key.clone());
+    let diff = consensus_peers_config.diff(&new_consensus_peers_config);
+    assert!(!diff.is_empty());
+    assert_eq!(
+        diff.added.into_iter().collect::<Vec<_>>(),
+        vec![(
+            added.clone(),
+            other_consensus_peers_config.peers[added].clone()
+        )]
+    );
+    assert_eq!(
+        diff.removed.into_iter().collect::<Vec<_>>(),
+        vec![(
+            removed.clone(),
+            consensus_peers_config.peers[removed].clone()
+        )]
+    );
+    assert_eq!(
+        diff.changed.into_iter().collect::<Vec<_>>(),
+        vec![(
+            changed.clone(),
+            (consensus_peers_config.peers[changed].clone(), new_key)
+        )]
+    );
+    // The diff the other way around undoes the changes.
+    let diff = new_consensus_peers_config.diff(&consensus_peers_config);
+    assert_eq!(diff.added.keys().collect::<Vec<_>>(), vec![removed]);
+    assert_eq!(diff.removed.keys().collect::<Vec<_>>(), vec![added]);
+    assert_eq!(diff.changed.keys().collect::<Vec<_>>(), vec![changed]);
+
+    // Starting a key rotation changes the network keys of the peer.
+    let mut new_network_peers_config = network_peers_config.clone();
+    let next_key = other_network_peers_config.peers[added]
+        .network_signing_pubkey
+        .clone();
+    new_network_peers_config
+        .peers
+        .get_mut(changed)
+        .unwrap()
+        .next_network_signing_pubkey = Some(next_key);
+    let diff = network_peers_config.diff(&new_network_peers_config);
+    assert!(diff.added.is_empty());
+    assert!(diff.removed.is_empty());
+    let (old_info, new_info) = &diff.changed[changed];
+    assert_eq!(old_info, &network_peers_config.peers[changed]);
+    assert_eq!(new_info, &new_network_peers_config.peers[changed]);
+}
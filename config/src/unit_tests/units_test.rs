@@ -0,0 +1,122 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::config::MempoolProxyConfig;
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Fields {
+    #[serde(deserialize_with = "deserialize_millis")]
+    timeout_ms: u64,
+    #[serde(default, deserialize_with = "deserialize_opt_millis")]
+    interval_ms: Option<u64>,
+    #[serde(deserialize_with = "deserialize_secs")]
+    timeout_secs: u64,
+    #[serde(deserialize_with = "deserialize_bytes")]
+    max_bytes: usize,
+    #[serde(default, deserialize_with = "deserialize_opt_bytes")]
+    max_len: Option<i32>,
+}
+
+fn parse(toml: &str) -> Result<Fields, String> {
+    toml::from_str(toml).map_err(|e| e.to_string())
+}
+
+#[test]
+fn verify_unit_forms() {
+    let plain = parse("timeout_ms = 500\ntimeout_secs = 2\nmax_bytes = 4096").unwrap();
+    assert_eq!(
+        plain,
+        Fields {
+            timeout_ms: 500,
+            interval_ms: None,
+            timeout_secs: 2,
+            max_bytes: 4096,
+            max_len: None,
+        }
+    );
+
+    let with_units = parse(
+        r#"
+        timeout_ms = "2s"
+        interval_ms = "1.5m"
+        timeout_secs = "2h"
+        max_bytes = "4MB"
+        max_len = "4 MiB"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        with_units,
+        Fields {
+            timeout_ms: 2000,
+            interval_ms: Some(90_000),
+            timeout_secs: 7200,
+            max_bytes: 4_000_000,
+            max_len: Some(4 << 20),
+        }
+    );
+
+    let fields = parse(
+        r#"
+        timeout_ms = "500ms"
+        timeout_secs = "3000ms"
+        max_bytes = "1.5KiB"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(fields.timeout_ms, 500);
+    assert_eq!(fields.timeout_secs, 3);
+    assert_eq!(fields.max_bytes, 1536);
+    assert_eq!(
+        parse("timeout_ms = \"2000us\"\ntimeout_secs = 0\nmax_bytes = \"0B\"")
+            .unwrap()
+            .timeout_ms,
+        2
+    );
+}
+
+#[test]
+fn verify_invalid_units() {
+    let error = |toml: &str| parse(toml).expect_err("Invalid units were accepted");
+
+    let unknown = error("timeout_ms = \"5 parsecs\"\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(unknown.contains("unknown unit \"parsecs\", expected one of us, ms, s, m, h"));
+    let missing = error("timeout_ms = \"5\"\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(missing.contains("missing unit"));
+    // Units of sizes are not units of durations.
+    let size_as_duration = error("timeout_ms = \"5MB\"\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(size_as_duration.contains("unknown unit \"MB\""));
+    let fractional = error("timeout_ms = \"1500us\"\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(fractional.contains("not a whole number of ms"));
+    let not_a_number = error("timeout_ms = \"1.2.3s\"\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(not_a_number.contains("not a number"));
+    let negative = error("timeout_ms = -5\ntimeout_secs = 1\nmax_bytes = 1");
+    assert!(negative.contains("a non negative integer"));
+    let out_of_range = error("timeout_ms = 1\ntimeout_secs = 1\nmax_bytes = 1\nmax_len = \"4GB\"");
+    assert!(out_of_range.contains("size 4000000000B is out of range"));
+}
+
+#[test]
+fn verify_config_units() {
+    let config: MempoolProxyConfig = toml::from_str(
+        r#"
+        pull_timeout_ms = "1.5s"
+        max_txn_bytes = "2MiB"
+
+        [connection]
+        keepalive_time_ms = "10s"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.pull_timeout_ms, 1500);
+    assert_eq!(config.max_txn_bytes, 2 << 20);
+    assert_eq!(config.connection.keepalive_time_ms, Some(10_000));
+    // Values are serialized in the units of the fields, and read back as such.
+    let serialized = toml::to_string(&config).unwrap();
+    assert!(serialized.contains("pull_timeout_ms = 1500"));
+    assert_eq!(
+        toml::from_str::<MempoolProxyConfig>(&serialized).unwrap(),
+        config
+    );
+}
@@ -0,0 +1,76 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use libra_tools::tempdir::TempPath;
+use std::{collections::HashSet, thread};
+
+#[test]
+fn verify_write_file_atomic() {
+    let dir = TempPath::new();
+    dir.create_as_dir().expect("error creating tempdir");
+    let path = dir.path().join("node.config.toml");
+
+    write_file_atomic(&path, b"original").expect("Unable to write file");
+    assert_eq!(fs::read(&path).unwrap(), b"original");
+    write_file_atomic(&path, b"replaced").expect("Unable to write file");
+    assert_eq!(fs::read(&path).unwrap(), b"replaced");
+
+    // A write interrupted after some of the contents made it to disk.
+    let error = write_atomic(&path, |file| {
+        file.write_all(b"trunc")?;
+        Err(io::Error::new(io::ErrorKind::Interrupted, "killed"))
+    })
+    .expect_err("The write was interrupted");
+    assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+    assert_eq!(fs::read(&path).unwrap(), b"replaced");
+    // Only the original file remains in the directory.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn verify_get_available_port_in_range() {
+    let start = get_available_port().saturating_sub(1);
+    let end = start.saturating_add(2);
+    match get_available_port_in_range(start, end) {
+        Ok(port) => assert!(start <= port && port <= end),
+        Err(error) => {
+            assert_eq!(error.kind(), io::ErrorKind::AddrNotAvailable);
+            assert_eq!(
+                error.to_string(),
+                format!("no available port in range {}..={}", start, end)
+            );
+        }
+    }
+
+    let error = get_available_port_in_range(end, start).expect_err("The range is empty");
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn verify_get_available_ports() {
+    const NUM_THREADS: usize = 8;
+    const PORTS_PER_THREAD: usize = 32;
+
+    let handles: Vec<_> = (0..NUM_THREADS)
+        .map(|_| thread::spawn(|| get_available_ports(PORTS_PER_THREAD)))
+        .collect();
+    let mut ports = HashSet::new();
+    for handle in handles {
+        let thread_ports = handle.join().unwrap();
+        assert_eq!(thread_ports.len(), PORTS_PER_THREAD);
+        ports.extend(thread_ports);
+    }
+    assert_eq!(ports.len(), NUM_THREADS * PORTS_PER_THREAD);
+}
+
+#[test]
+fn verify_get_local_ip() {
+    // Hosts with only loopback interfaces have no local ip.
+    if let Some(ip) = get_local_ip() {
+        assert!(!ip.is_loopback());
+        assert!(!ip.is_unspecified());
+        let addr = ip_to_multiaddr(ip).to_string();
+        assert!(addr.starts_with("/ip4/") || addr.starts_with("/ip6/"));
+    }
+}
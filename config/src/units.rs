@@ -0,0 +1,215 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deserializers of the duration and size fields of the config, accepting either a plain integer
+//! in the units of the field, e.g. `pull_timeout_ms = 500`, or a string with explicit units, e.g.
+//! `pull_timeout_ms = "500ms"` or `pull_timeout_ms = "2s"`. Values are normalized to the units
+//! of the field, and must be a whole number of them: `"1500us"` is rejected for a field in ms.
+//! The fields are still serialized as plain integers.
+//!
+//! Duration units are `us`, `ms`, `s`, `m` and `h`. Size units are `B`, the decimal `KB`, `MB`
+//! and `GB`, and the binary `KiB`, `MiB` and `GiB`.
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{convert::TryFrom, fmt};
+
+#[cfg(test)]
+#[path = "unit_tests/units_test.rs"]
+mod units_test;
+
+/// Duration units, with their value in microseconds.
+const DURATION_UNITS: &[(&str, u64)] = &[
+    ("us", 1),
+    ("ms", 1_000),
+    ("s", 1_000_000),
+    ("m", 60_000_000),
+    ("h", 3_600_000_000),
+];
+
+/// Size units, with their value in bytes.
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("B", 1),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+];
+
+/// The value of a quantity field as written in the config.
+enum Quantity {
+    /// A number in the units of the field.
+    Number(u64),
+    /// A number followed by its units.
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuantityVisitor;
+
+        impl<'de> Visitor<'de> for QuantityVisitor {
+            type Value = Quantity;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a non negative integer or a string like \"500ms\" or \"4MB\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Quantity, E> {
+                Ok(Quantity::Number(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Quantity, E> {
+                u64::try_from(value)
+                    .map(Quantity::Number)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Quantity, E> {
+                Ok(Quantity::Text(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+impl Quantity {
+    /// The quantity in units of `field_unit` from `units`, a plain number being in these units.
+    fn normalize(self, kind: &str, units: &[(&str, u64)], field_unit: &str) -> Result<u64, String> {
+        let text = match self {
+            Quantity::Number(value) => return Ok(value),
+            Quantity::Text(text) => text,
+        };
+        let invalid = |reason: String| format!("invalid {} {:?}: {}", kind, text, reason);
+        let unit_names = || {
+            units
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let trimmed = text.trim();
+        let split = trimmed
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or_else(|| trimmed.len());
+        let (number, unit) = (&trimmed[..split], trimmed[split..].trim());
+        if unit.is_empty() {
+            return Err(invalid(format!(
+                "missing unit, expected one of {}",
+                unit_names()
+            )));
+        }
+        let unit_value = Self::unit_value(units, unit).ok_or_else(|| {
+            invalid(format!(
+                "unknown unit {:?}, expected one of {}",
+                unit,
+                unit_names()
+            ))
+        })?;
+        let field_unit_value =
+            Self::unit_value(units, field_unit).expect("The unit of the field is not a unit");
+
+        // The number is parsed as a fraction, so that e.g. "1.5s" is exactly 1500ms.
+        let (integer, fraction) = match number.find('.') {
+            Some(dot) => (&number[..dot], &number[dot + 1..]),
+            None => (number, ""),
+        };
+        let digits = format!("{}{}", integer, fraction);
+        if digits.is_empty() || fraction.contains('.') {
+            return Err(invalid("not a number".to_string()));
+        }
+        let numerator = digits
+            .parse::<u128>()
+            .ok()
+            .and_then(|digits| digits.checked_mul(u128::from(unit_value)))
+            .ok_or_else(|| invalid("out of range".to_string()))?;
+        let denominator = 10u128
+            .checked_pow(fraction.len() as u32)
+            .and_then(|scale| scale.checked_mul(u128::from(field_unit_value)))
+            .ok_or_else(|| invalid("too many decimals".to_string()))?;
+        if numerator % denominator != 0 {
+            return Err(invalid(format!("not a whole number of {}", field_unit)));
+        }
+        u64::try_from(numerator / denominator).map_err(|_| invalid("out of range".to_string()))
+    }
+
+    fn unit_value(units: &[(&str, u64)], unit: &str) -> Option<u64> {
+        units
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, value)| *value)
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    field_unit: &str,
+) -> Result<u64, D::Error> {
+    Quantity::deserialize(deserializer)?
+        .normalize("duration", DURATION_UNITS, field_unit)
+        .map_err(de::Error::custom)
+}
+
+fn deserialize_opt_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    field_unit: &str,
+) -> Result<Option<u64>, D::Error> {
+    Option::<Quantity>::deserialize(deserializer)?
+        .map(|quantity| quantity.normalize("duration", DURATION_UNITS, field_unit))
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+/// Deserializes a duration in milliseconds, see the module documentation.
+pub fn deserialize_millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserialize_duration(deserializer, "ms")
+}
+
+/// Deserializes an optional duration in milliseconds, see the module documentation.
+pub fn deserialize_opt_millis<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserialize_opt_duration(deserializer, "ms")
+}
+
+/// Deserializes a duration in seconds, see the module documentation.
+pub fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserialize_duration(deserializer, "s")
+}
+
+/// Deserializes a size in bytes into any integer type it fits in, see the module documentation.
+pub fn deserialize_bytes<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    let bytes = Quantity::deserialize(deserializer)?
+        .normalize("size", SIZE_UNITS, "B")
+        .map_err(de::Error::custom)?;
+    T::try_from(bytes).map_err(|_| de::Error::custom(format!("size {}B is out of range", bytes)))
+}
+
+/// Deserializes an optional size in bytes into any integer type it fits in, see the module
+/// documentation.
+pub fn deserialize_opt_bytes<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    match Option::<Quantity>::deserialize(deserializer)? {
+        Some(quantity) => {
+            let bytes = quantity
+                .normalize("size", SIZE_UNITS, "B")
+                .map_err(de::Error::custom)?;
+            T::try_from(bytes)
+                .map(Some)
+                .map_err(|_| de::Error::custom(format!("size {}B is out of range", bytes)))
+        }
+        None => Ok(None),
+    }
+}
@@ -3,7 +3,16 @@
 
 use get_if_addrs::get_if_addrs;
 use parity_multiaddr::{Multiaddr, Protocol};
-use std::net::{IpAddr, TcpListener, TcpStream};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+};
+
+#[cfg(test)]
+#[path = "unit_tests/utils_test.rs"]
+mod utils_test;
 
 /// Return an ephemeral, available port. On unix systems, the port returned will be in the
 /// TIME_WAIT state ensuring that the OS won't hand out this port for some grace period.
@@ -20,9 +29,61 @@ pub fn get_available_port() -> u16 {
     panic!("Error: could not find an available port");
 }
 
+/// Return an available port within the inclusive range `start..=end`, e.g. to stay within the
+/// ports a firewall lets through. Like [`get_available_port`], the port returned is left in the
+/// TIME_WAIT state. Fails if no port of the range is available.
+pub fn get_available_port_in_range(start: u16, end: u16) -> io::Result<u16> {
+    if start > end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid port range {}..={}", start, end),
+        ));
+    }
+    (start..=end)
+        .find_map(|port| reserve_port(port).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("no available port in range {}..={}", start, end),
+            )
+        })
+}
+
 fn get_ephemeral_port() -> ::std::io::Result<u16> {
     // Request a random available port from the OS
-    let listener = TcpListener::bind(("localhost", 0))?;
+    reserve_port(0)
+}
+
+/// Return `n` distinct ephemeral, available ports, left in the TIME_WAIT state like the port of
+/// [`get_available_port`]. The listeners of all the ports are held until every port is chosen,
+/// so that the OS cannot hand out the same port twice within a call.
+pub fn get_available_ports(n: usize) -> Vec<u16> {
+    const MAX_PORT_RETRIES: u32 = 1000;
+
+    let mut listeners = Vec::with_capacity(n);
+    let mut retries = 0;
+    while listeners.len() < n {
+        match TcpListener::bind(("localhost", 0)) {
+            Ok(listener) => listeners.push(listener),
+            Err(_) if retries < MAX_PORT_RETRIES => retries += 1,
+            Err(e) => panic!("Error: could not find {} available ports: {}", n, e),
+        }
+    }
+
+    listeners
+        .into_iter()
+        .map(|listener| {
+            time_wait(listener)
+                .unwrap_or_else(|e| panic!("Error: could not reserve an available port: {}", e))
+        })
+        .collect()
+}
+
+fn reserve_port(port: u16) -> ::std::io::Result<u16> {
+    time_wait(TcpListener::bind(("localhost", port))?)
+}
+
+fn time_wait(listener: TcpListener) -> ::std::io::Result<u16> {
     let addr = listener.local_addr()?;
 
     // Create and accept a connection (which we'll promptly drop) in order to force the port
@@ -34,23 +95,107 @@ fn get_ephemeral_port() -> ::std::io::Result<u16> {
     Ok(addr.port())
 }
 
-/// Extracts one local non-loopback IP address, if one exists. Otherwise returns None.
-pub fn get_local_ip() -> Option<Multiaddr> {
-    get_if_addrs().ok().and_then(|if_addrs| {
-        if_addrs
-            .into_iter()
-            .find(|if_addr| !if_addr.is_loopback())
-            .and_then(|if_addr| {
-                let mut addr = Multiaddr::empty();
-                match if_addr.ip() {
-                    IpAddr::V4(a) => {
-                        addr.push(Protocol::Ip4(a));
-                    }
-                    IpAddr::V6(a) => {
-                        addr.push(Protocol::Ip6(a));
-                    }
-                }
-                Some(addr)
-            })
+/// Checks that `addr` can be listened on, by binding to each address it resolves to and
+/// releasing it right away.
+pub fn check_bindable<A: ToSocketAddrs>(addr: A) -> io::Result<()> {
+    for addr in addr.to_socket_addrs()? {
+        TcpListener::bind(addr)?;
+    }
+    Ok(())
+}
+
+/// Returns the primary non-loopback IP address of the machine, i.e. the source address of the
+/// default route, found by connecting a UDP socket to a public address (which sends no data).
+/// Hosts without a default route fall back to the first non-loopback interface, hosts with only
+/// loopback interfaces return None.
+pub fn get_local_ip() -> Option<IpAddr> {
+    get_routable_ip().or_else(|| {
+        get_if_addrs().ok().and_then(|if_addrs| {
+            if_addrs
+                .into_iter()
+                .find(|if_addr| !if_addr.is_loopback())
+                .map(|if_addr| if_addr.ip())
+        })
     })
 }
+
+fn get_routable_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(8, 8, 8, 8), 80)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        None
+    } else {
+        Some(ip)
+    }
+}
+
+/// Converts an IP address into the equivalent ip4 or ip6 multiaddr.
+pub fn ip_to_multiaddr(ip: IpAddr) -> Multiaddr {
+    let mut addr = Multiaddr::empty();
+    match ip {
+        IpAddr::V4(a) => {
+            addr.push(Protocol::Ip4(a));
+        }
+        IpAddr::V6(a) => {
+            addr.push(Protocol::Ip6(a));
+        }
+    }
+    addr
+}
+
+/// Converts an ip4 or ip6 multiaddr followed by a tcp port into the equivalent socket address.
+pub fn multiaddr_to_socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut protocols = addr.iter();
+    let ip = match protocols.next()? {
+        Protocol::Ip4(ip) => IpAddr::V4(ip),
+        Protocol::Ip6(ip) => IpAddr::V6(ip),
+        _ => return None,
+    };
+    match (protocols.next()?, protocols.next()) {
+        (Protocol::Tcp(port), None) => Some(SocketAddr::new(ip, port)),
+        _ => None,
+    }
+}
+
+/// Replaces the contents of the file at `path` with `contents`, such that the file holds either
+/// its previous or its new contents even if the process dies midway: the contents are written
+/// and synced to a temporary file of the same directory, which is then renamed into place.
+pub fn write_file_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    write_atomic(path.as_ref(), |file| file.write_all(contents))
+}
+
+fn write_atomic<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let temp_path = temp_path_for(path)?;
+    let result = File::create(&temp_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result.and_then(|()| fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            // The temporary file is garbage at this point, failing to remove it is not worth
+            // hiding the original error.
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+// Renaming is only atomic within a file system, hence the temporary file is a sibling of `path`.
+fn temp_path_for(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not a file path", path),
+        )
+    })?;
+    Ok(path.with_file_name(format!(
+        ".{}.{:016x}.tmp",
+        file_name.to_string_lossy(),
+        rand::random::<u64>()
+    )))
+}
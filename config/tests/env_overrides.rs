@@ -0,0 +1,53 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+// These tests live in their own binary, as the environment they mutate is shared by every test
+// of the process and `NodeConfig::load` reads it.
+
+use libra_config::config::NodeConfigHelpers;
+use parity_multiaddr::Multiaddr;
+use std::{env, path::PathBuf};
+
+#[test]
+fn apply_env_overrides() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(false);
+    let mempool_service_port = config.mempool.mempool_service_port;
+
+    let overrides = [
+        ("LIBRA_ADMISSION_CONTROL_SERVICE_PORT", "1234"),
+        ("LIBRA_NETWORK_LISTEN_ADDRESS", "/ip4/10.0.0.1/tcp/6180"),
+        (
+            "LIBRA_CONSENSUS_PEERS_FILE",
+            "/etc/libra/consensus_peers.config.toml",
+        ),
+        ("LIBRA_UNKNOWN_FIELD", "ignored"),
+    ];
+    for (name, value) in &overrides {
+        env::set_var(name, value);
+    }
+    let result = config.apply_env_overrides();
+    for (name, _) in &overrides {
+        env::remove_var(name);
+    }
+    result.expect("Valid overrides");
+    assert_eq!(
+        config.admission_control.admission_control_service_port,
+        1234
+    );
+    assert_eq!(
+        config.networks[0].listen_address,
+        "/ip4/10.0.0.1/tcp/6180".parse::<Multiaddr>().unwrap()
+    );
+    assert_eq!(
+        config.consensus.consensus_peers_file,
+        PathBuf::from("/etc/libra/consensus_peers.config.toml")
+    );
+    assert_eq!(config.mempool.mempool_service_port, mempool_service_port);
+
+    env::set_var("LIBRA_MEMPOOL_SERVICE_PORT", "not a port");
+    let result = config.apply_env_overrides();
+    env::remove_var("LIBRA_MEMPOOL_SERVICE_PORT");
+    let error = result.expect_err("The port is not a number");
+    assert!(error.to_string().contains("LIBRA_MEMPOOL_SERVICE_PORT"));
+    assert_eq!(config.mempool.mempool_service_port, mempool_service_port);
+}
@@ -0,0 +1,273 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Benchmarks of a pull followed by the commit of the pulled block, through MempoolProxy over a
+//! mempool answering from memory and through InMemoryTxnManager.
+//! The block sizes and the exclusion set sizes can be set as comma separated lists with the
+//! `TXN_MANAGER_BENCH_BLOCK_SIZES` and `TXN_MANAGER_BENCH_EXCLUSION_SIZES` environment variables.
+//!
+//! Run with `cargo bench -p consensus --features fuzzing --bench txn_manager_bench`. The bench
+//! requires the `fuzzing` feature, which is off by default, so neither a plain `cargo bench` nor
+//! CI builds it.
+
+// Allow fns to take &Params, since criterion only passes parameters by ref
+#![allow(clippy::trivially_copy_pass_by_ref)]
+
+use consensus::{
+    InMemoryTxnManager, MempoolInterface, MempoolProxy, MempoolResponseFuture, TxnManager,
+};
+use criterion::{
+    criterion_group, criterion_main, BatchSize, Bencher, Criterion, ParameterizedBenchmark,
+    Throughput,
+};
+use executor::StateComputeResult;
+use futures::{future, FutureExt};
+use grpcio::CallOption;
+use libra_config::config::MempoolProxyConfig;
+use libra_crypto::ed25519::compat;
+use libra_mempool::proto::mempool::{
+    CommitTransactionsRequest, CommitTransactionsResponse, GetBlockRequest, GetBlockResponse,
+    HealthCheckRequest, HealthCheckResponse,
+};
+use libra_types::{
+    account_address::AccountAddress,
+    proto::types::SignedTransactionsBlock,
+    transaction::{RawTransaction, Script, SignedTransaction, TransactionStatus},
+    vm_error::{StatusCode, VMStatus},
+};
+use stats_alloc::{Region, StatsAlloc, INSTRUMENTED_SYSTEM};
+use std::{alloc::System, env, fmt, sync::Arc, time::Duration};
+use tokio::runtime::Runtime;
+
+#[global_allocator]
+static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+
+const DEFAULT_BLOCK_SIZES: &[u64] = &[100, 1000, 10_000];
+const DEFAULT_EXCLUSION_SIZES: &[u64] = &[0, 1000];
+
+/// Mempool answering every pull with the same block, without a grpc round-trip.
+struct StaticMempool {
+    response: GetBlockResponse,
+}
+
+impl StaticMempool {
+    fn new(block: &[SignedTransaction]) -> Self {
+        let mut proto_block = SignedTransactionsBlock::default();
+        proto_block.transactions = block.iter().cloned().map(Into::into).collect();
+        let mut response = GetBlockResponse::default();
+        response.block = Some(proto_block);
+        Self { response }
+    }
+}
+
+impl MempoolInterface for StaticMempool {
+    fn get_block_async(
+        &self,
+        _req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        Ok(future::ok(self.response.clone()).boxed())
+    }
+
+    fn commit_transactions_async(
+        &self,
+        _req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        Ok(future::ok(CommitTransactionsResponse::default()).boxed())
+    }
+
+    fn health_check_async_opt(
+        &self,
+        _req: &HealthCheckRequest,
+        _opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        let mut response = HealthCheckResponse::default();
+        response.is_healthy = true;
+        Ok(future::ok(response).boxed())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Params {
+    block_size: u64,
+    exclusion_size: u64,
+}
+
+/// Names the benchmarks, e.g. "1000txns_100excluded".
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}txns_{}excluded", self.block_size, self.exclusion_size)
+    }
+}
+
+/// The transactions of a benchmark: `block` is pulled and committed while `excluded` is pending
+/// in the branch.
+struct Fixture {
+    block: Vec<SignedTransaction>,
+    excluded: Vec<SignedTransaction>,
+    compute_result: StateComputeResult,
+}
+
+impl Fixture {
+    fn new(params: &Params) -> Self {
+        let (private_key, public_key) = compat::generate_keypair(None);
+        let mut txns = (0..params.block_size + params.exclusion_size).map(|_| {
+            RawTransaction::new_script(
+                AccountAddress::random(),
+                0,
+                Script::new(vec![], vec![]),
+                0,
+                0,
+                Duration::from_secs(0),
+            )
+            .sign(&private_key, public_key.clone())
+            .expect("Failed to sign txn")
+            .into_inner()
+        });
+        let block: Vec<_> = txns.by_ref().take(params.block_size as usize).collect();
+        let excluded = txns.collect();
+        // A status for the block prologue, then one per transaction.
+        let keep = TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED));
+        let compute_result = StateComputeResult {
+            compute_status: vec![keep; block.len() + 1],
+            ..StateComputeResult::default()
+        };
+        Self {
+            block,
+            excluded,
+            compute_result,
+        }
+    }
+}
+
+/// Pulls a block through `txn_manager` then commits it.
+fn pull_then_commit<T: TxnManager<Payload = Vec<SignedTransaction>>>(
+    runtime: &mut Runtime,
+    txn_manager: &T,
+    fixture: &Fixture,
+    max_size: u64,
+    timestamp_usecs: u64,
+) where
+    T::Error: fmt::Debug,
+{
+    let block = runtime
+        .block_on(txn_manager.pull_txns(max_size, vec![&fixture.excluded]))
+        .expect("Failed to pull txns");
+    runtime
+        .block_on(txn_manager.commit_txns(&block, &fixture.compute_result, timestamp_usecs))
+        .expect("Failed to commit txns");
+}
+
+fn mempool_proxy_bench(b: &mut Bencher, params: &Params) {
+    let mut runtime = Runtime::new().unwrap();
+    let fixture = Fixture::new(params);
+    let proxy = MempoolProxy::new(
+        Arc::new(StaticMempool::new(&fixture.block)),
+        &MempoolProxyConfig::default(),
+    );
+    // Successive blocks have increasing timestamps, as committed blocks do.
+    let mut timestamp_usecs = 0;
+    b.iter(|| {
+        timestamp_usecs += 1;
+        pull_then_commit(
+            &mut runtime,
+            &proxy,
+            &fixture,
+            params.block_size,
+            timestamp_usecs,
+        )
+    });
+}
+
+fn in_memory_bench(b: &mut Bencher, params: &Params) {
+    let mut runtime = Runtime::new().unwrap();
+    let fixture = Fixture::new(params);
+    let txns: Vec<_> = fixture
+        .excluded
+        .iter()
+        .chain(&fixture.block)
+        .cloned()
+        .collect();
+    // Committing drops the block from the store, every iteration starts from a full one.
+    b.iter_batched(
+        || InMemoryTxnManager::new(txns.clone()),
+        |txn_manager| pull_then_commit(&mut runtime, &txn_manager, &fixture, params.block_size, 1),
+        BatchSize::LargeInput,
+    );
+}
+
+/// Prints the number of allocations of a pull then commit, which criterion does not measure.
+fn report_allocations(params: &Params) {
+    let mut runtime = Runtime::new().unwrap();
+    let fixture = Fixture::new(params);
+    let proxy = MempoolProxy::new(
+        Arc::new(StaticMempool::new(&fixture.block)),
+        &MempoolProxyConfig::default(),
+    );
+    // The first call also allocates the lazily initialized state, e.g. the counters.
+    pull_then_commit(&mut runtime, &proxy, &fixture, params.block_size, 1);
+    let region = Region::new(&GLOBAL);
+    pull_then_commit(&mut runtime, &proxy, &fixture, params.block_size, 2);
+    let stats = region.change();
+    println!(
+        "mempool_proxy/{:?}: {} allocations, {} reallocations, {} bytes allocated per call",
+        params, stats.allocations, stats.reallocations, stats.bytes_allocated
+    );
+
+    let txn_manager = InMemoryTxnManager::new(
+        fixture
+            .excluded
+            .iter()
+            .chain(&fixture.block)
+            .cloned()
+            .collect(),
+    );
+    let region = Region::new(&GLOBAL);
+    pull_then_commit(&mut runtime, &txn_manager, &fixture, params.block_size, 1);
+    let stats = region.change();
+    println!(
+        "in_memory/{:?}: {} allocations, {} reallocations, {} bytes allocated per call",
+        params, stats.allocations, stats.reallocations, stats.bytes_allocated
+    );
+}
+
+/// Sizes set by the comma separated list of the environment variable `var`, or `default`.
+fn sizes_from_env(var: &str, default: &[u64]) -> Vec<u64> {
+    match env::var(var) {
+        Ok(sizes) => sizes
+            .split(',')
+            .map(|size| {
+                size.trim()
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Invalid size {:?} in {}: {}", size, var, e))
+            })
+            .collect(),
+        Err(_) => default.to_vec(),
+    }
+}
+
+fn txn_manager_benchmark(c: &mut Criterion) {
+    let block_sizes = sizes_from_env("TXN_MANAGER_BENCH_BLOCK_SIZES", DEFAULT_BLOCK_SIZES);
+    let exclusion_sizes =
+        sizes_from_env("TXN_MANAGER_BENCH_EXCLUSION_SIZES", DEFAULT_EXCLUSION_SIZES);
+    let params: Vec<_> = block_sizes
+        .iter()
+        .flat_map(|&block_size| {
+            exclusion_sizes.iter().map(move |&exclusion_size| Params {
+                block_size,
+                exclusion_size,
+            })
+        })
+        .collect();
+    params.iter().for_each(report_allocations);
+
+    c.bench(
+        "pull_then_commit",
+        ParameterizedBenchmark::new("mempool_proxy", mempool_proxy_bench, params)
+            .with_function("in_memory", in_memory_bench)
+            .sample_size(10)
+            .throughput(|params| Throughput::Elements(params.block_size)),
+    );
+}
+
+criterion_group!(benches, txn_manager_benchmark);
+criterion_main!(benches);
@@ -1,46 +1,40 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::state_replication::{StateComputer, TxnManager};
+use crate::state_replication::{StateComputer, TxnManager, TxnManagerFailure};
 use crate::{
     chained_bft::{
-        chained_bft_smr::{ChainedBftSMR, ChainedBftSMRConfig},
+        chained_bft_smr::{ChainedBftSMR, ChainedBftSMRConfig, InitialSetup},
         persistent_storage::{PersistentStorage, StorageWriteProxy},
     },
     consensus_provider::ConsensusProvider,
+    recording_txn_manager::RecordingTxnManager,
     state_computer::ExecutionProxy,
     state_replication::StateMachineReplication,
     txn_manager::MempoolProxy,
 };
-use consensus_types::common::Author;
 use executor::Executor;
 use failure::prelude::*;
+use futures::executor::block_on;
 use libra_config::config::NodeConfig;
 use libra_logger::prelude::*;
-use libra_mempool::proto::mempool::MempoolClient;
 use libra_types::{
     account_address::AccountAddress, crypto_proxies::ValidatorSigner,
     transaction::SignedTransaction,
 };
 use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
 use state_synchronizer::StateSyncClient;
-use std::{convert::TryFrom, sync::Arc};
+use std::{convert::TryFrom, path::PathBuf, sync::Arc};
 use tokio::runtime;
 use vm_runtime::MoveVM;
 
-///  The state necessary to begin state machine replication including ValidatorSet, networking etc.
-pub struct InitialSetup {
-    pub author: Author,
-    pub signer: ValidatorSigner,
-    pub network_sender: ConsensusNetworkSender,
-    pub network_events: ConsensusNetworkEvents,
-}
-
 /// Supports the implementation of ConsensusProvider using LibraBFT.
 pub struct ChainedBftProvider {
     smr: ChainedBftSMR<Vec<SignedTransaction>>,
-    txn_manager: Arc<dyn TxnManager<Payload = Vec<SignedTransaction>>>,
+    txn_manager: Arc<MempoolProxy>,
     state_computer: Arc<dyn StateComputer<Payload = Vec<SignedTransaction>>>,
+    /// Log the pulled and committed payloads are recorded to, if any.
+    txn_log_file: Option<PathBuf>,
 }
 
 impl ChainedBftProvider {
@@ -48,7 +42,7 @@ impl ChainedBftProvider {
         node_config: &mut NodeConfig,
         network_sender: ConsensusNetworkSender,
         network_events: ConsensusNetworkEvents,
-        mempool_client: Arc<MempoolClient>,
+        txn_manager: Arc<MempoolProxy>,
         executor: Arc<Executor<MoveVM>>,
         synchronizer_client: Arc<StateSyncClient>,
     ) -> Self {
@@ -64,13 +58,14 @@ impl ChainedBftProvider {
         let config = ChainedBftSMRConfig::from_node_config(&node_config.consensus);
         let storage = Arc::new(StorageWriteProxy::new(node_config));
         let initial_data = storage.start();
-        let txn_manager = Arc::new(MempoolProxy::new(mempool_client.clone()));
         let state_computer = Arc::new(ExecutionProxy::new(executor, synchronizer_client.clone()));
         let smr = ChainedBftSMR::new(initial_setup, runtime, config, storage, initial_data);
+        let txn_log_file = node_config.get_txn_log_file();
         Self {
             smr,
             txn_manager,
             state_computer,
+            txn_log_file,
         }
     }
 
@@ -108,13 +103,33 @@ impl ChainedBftProvider {
 impl ConsensusProvider for ChainedBftProvider {
     fn start(&mut self) -> Result<()> {
         debug!("Starting consensus provider.");
-        self.smr.start(
-            Arc::clone(&self.txn_manager),
-            Arc::clone(&self.state_computer),
-        )
+        block_on(self.txn_manager.ping())
+            .map_err(|e| format_err!("Mempool is not reachable: {}", e))?;
+        let txn_manager: Arc<
+            dyn TxnManager<Payload = Vec<SignedTransaction>, Error = TxnManagerFailure>,
+        > = match &self.txn_log_file {
+            Some(txn_log_file) => {
+                info!(
+                    "Recording the pulled and committed txns to {:?}",
+                    txn_log_file
+                );
+                Arc::new(RecordingTxnManager::new(
+                    Arc::clone(&self.txn_manager),
+                    txn_log_file,
+                )?)
+            }
+            None => Arc::clone(&self.txn_manager),
+        };
+        self.smr
+            .start(txn_manager, Arc::clone(&self.state_computer))
     }
 
     fn stop(&mut self) {
+        // Commits are driven by the runtime of the SMR, let them reach mempool before it is
+        // dropped.
+        if let Err(e) = block_on(self.txn_manager.flush()) {
+            warn!("Failed to flush the commits to mempool: {}", e);
+        }
         self.smr.stop();
         debug!("Consensus provider stopped.");
     }
@@ -1,7 +1,6 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::chained_bft::chained_bft_consensus_provider::InitialSetup;
 use crate::chained_bft::epoch_manager::EpochManager;
 use crate::chained_bft::network::{NetworkReceivers, NetworkTask};
 use crate::{
@@ -11,21 +10,33 @@ use crate::{
         persistent_storage::{PersistentStorage, RecoveryData},
     },
     counters,
-    state_replication::{StateComputer, StateMachineReplication, TxnManager},
+    state_replication::{StateComputer, StateMachineReplication, TxnManager, TxnManagerFailure},
     util::time_service::ClockTimeService,
 };
 use channel;
-use consensus_types::common::{Payload, Round};
+use consensus_types::common::{Author, Payload, Round};
+use executor::StateComputeResult;
 use failure::prelude::*;
-use futures::{select, stream::StreamExt};
+use futures::{select, stream::StreamExt, Future, FutureExt};
 use libra_config::config::{ConsensusConfig, ConsensusProposerType, SafetyRulesConfig};
 use libra_logger::prelude::*;
+use libra_types::crypto_proxies::ValidatorSigner;
+use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
 use std::{
+    pin::Pin,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::runtime::{Handle, Runtime};
 
+///  The state necessary to begin state machine replication including ValidatorSet, networking etc.
+pub struct InitialSetup {
+    pub author: Author,
+    pub signer: ValidatorSigner,
+    pub network_sender: ConsensusNetworkSender,
+    pub network_events: ConsensusNetworkEvents,
+}
+
 /// Consensus configuration derived from ConsensusConfig
 pub struct ChainedBftSMRConfig {
     /// Keep up to this number of committed blocks before cleaning them up from the block store.
@@ -151,6 +162,65 @@ impl<T: Payload> ChainedBftSMR<T> {
     }
 }
 
+/// TxnManager delegating to a TxnManager of any error type, whose failures it reports as
+/// TxnManagerFailure, so that the components of consensus share a single TxnManager type.
+struct ErasedTxnManager<P, E> {
+    inner: Arc<dyn TxnManager<Payload = P, Error = E>>,
+}
+
+fn erase_error<E: std::error::Error + Send + Sync + 'static>(error: E) -> TxnManagerFailure {
+    failure::Error::from(error).compat()
+}
+
+impl<P, E> TxnManager for ErasedTxnManager<P, E>
+where
+    P: 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    type Payload = P;
+    type Error = TxnManagerFailure;
+
+    fn pull_txns(
+        &self,
+        max_size: u64,
+        exclude_txns: Vec<&Self::Payload>,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<Self::Payload, Self::Error>> + Send>> {
+        self.inner
+            .pull_txns(max_size, exclude_txns)
+            .map(|result| result.map_err(erase_error))
+            .boxed()
+    }
+
+    fn commit_txns<'a>(
+        &'a self,
+        txns: &Self::Payload,
+        compute_result: &StateComputeResult,
+        timestamp_usecs: u64,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        self.inner
+            .commit_txns(txns, compute_result, timestamp_usecs)
+            .map(|result| result.map_err(erase_error))
+            .boxed()
+    }
+
+    fn mempool_size(
+        &self,
+    ) -> Option<Pin<Box<dyn Future<Output = std::result::Result<usize, Self::Error>> + Send>>> {
+        self.inner
+            .mempool_size()
+            .map(|size| size.map(|result| result.map_err(erase_error)).boxed())
+    }
+
+    fn flush<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        self.inner
+            .flush()
+            .map(|result| result.map_err(erase_error))
+            .boxed()
+    }
+}
+
 impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
     type Payload = T;
 
@@ -158,9 +228,9 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
     /// 1. Construct the EpochManager from the latest libradb state
     /// 2. Construct per-epoch component with the fixed Validators provided by EpochManager including
     /// ProposerElection, Pacemaker, SafetyRules, Network(Populate with known validators), EventProcessor
-    fn start(
+    fn start<E: std::error::Error + Send + Sync + 'static>(
         &mut self,
-        txn_manager: Arc<dyn TxnManager<Payload = Self::Payload>>,
+        txn_manager: Arc<dyn TxnManager<Payload = Self::Payload, Error = E>>,
         state_computer: Arc<dyn StateComputer<Payload = Self::Payload>>,
     ) -> Result<()> {
         let initial_setup = self
@@ -191,7 +261,7 @@ impl<T: Payload> StateMachineReplication for ChainedBftSMR<T> {
             self_sender,
             initial_setup.network_sender,
             timeout_sender,
-            txn_manager,
+            Arc::new(ErasedTxnManager { inner: txn_manager }),
             state_computer,
             self.storage.clone(),
             signer.clone(),
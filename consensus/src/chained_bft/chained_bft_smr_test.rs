@@ -4,8 +4,7 @@
 use crate::{
     chained_bft::{
         block_storage::BlockReader,
-        chained_bft_consensus_provider::InitialSetup,
-        chained_bft_smr::{ChainedBftSMR, ChainedBftSMRConfig},
+        chained_bft_smr::{ChainedBftSMR, ChainedBftSMRConfig, InitialSetup},
         network_tests::NetworkPlayground,
         persistent_storage::RecoveryData,
         test_utils::{
@@ -12,7 +12,7 @@ use crate::chained_bft::liveness::rotating_proposer_election::{choose_leader, Ro
 use crate::chained_bft::network::NetworkSender;
 use crate::chained_bft::persistent_storage::{PersistentStorage, RecoveryData};
 use crate::counters;
-use crate::state_replication::{StateComputer, TxnManager};
+use crate::state_replication::{StateComputer, TxnManager, TxnManagerFailure};
 use crate::util::time_service::{ClockTimeService, TimeService};
 use consensus_types::common::{Payload, Round};
 use consensus_types::epoch_retrieval::EpochRetrievalRequest;
@@ -38,7 +38,7 @@ pub struct EpochManager<T> {
     self_sender: channel::Sender<failure::Result<Event<ConsensusMsg>>>,
     network_sender: ConsensusNetworkSender,
     timeout_sender: channel::Sender<Round>,
-    txn_manager: Arc<dyn TxnManager<Payload = T>>,
+    txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
     state_computer: Arc<dyn StateComputer<Payload = T>>,
     storage: Arc<dyn PersistentStorage<T>>,
     // TODO: remove once we have separate key management structure, and we'll share a slim client
@@ -54,7 +54,7 @@ impl<T: Payload> EpochManager<T> {
         self_sender: channel::Sender<failure::Result<Event<ConsensusMsg>>>,
         network_sender: ConsensusNetworkSender,
         timeout_sender: channel::Sender<Round>,
-        txn_manager: Arc<dyn TxnManager<Payload = T>>,
+        txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
         state_computer: Arc<dyn StateComputer<Payload = T>>,
         storage: Arc<dyn PersistentStorage<T>>,
         signer: Arc<ValidatorSigner>,
@@ -13,7 +13,7 @@ use crate::{
         persistent_storage::PersistentStorage,
     },
     counters,
-    state_replication::TxnManager,
+    state_replication::{TxnManager, TxnManagerFailure},
     util::time_service::{
         duration_since_epoch, wait_if_possible, TimeService, WaitingError, WaitingSuccess,
     },
@@ -72,7 +72,7 @@ pub struct EventProcessor<T> {
     proposer_election: Box<dyn ProposerElection<T> + Send + Sync>,
     proposal_generator: ProposalGenerator<T>,
     safety_rules: SafetyRules,
-    txn_manager: Arc<dyn TxnManager<Payload = T>>,
+    txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
     network: NetworkSender,
     storage: Arc<dyn PersistentStorage<T>>,
     time_service: Arc<dyn TimeService>,
@@ -89,7 +89,7 @@ impl<T: Payload> EventProcessor<T> {
         proposer_election: Box<dyn ProposerElection<T> + Send + Sync>,
         proposal_generator: ProposalGenerator<T>,
         safety_rules: SafetyRules,
-        txn_manager: Arc<dyn TxnManager<Payload = T>>,
+        txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
         network: NetworkSender,
         storage: Arc<dyn PersistentStorage<T>>,
         time_service: Arc<dyn TimeService>,
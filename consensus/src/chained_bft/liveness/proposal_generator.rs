@@ -4,7 +4,7 @@
 use crate::{
     chained_bft::block_storage::BlockReader,
     counters,
-    state_replication::TxnManager,
+    state_replication::{TxnManager, TxnManagerFailure},
     util::time_service::{wait_if_possible, TimeService, WaitingError, WaitingSuccess},
 };
 use consensus_types::{
@@ -40,7 +40,7 @@ pub struct ProposalGenerator<T> {
     // proposed block.
     block_store: Arc<dyn BlockReader<Payload = T> + Send + Sync>,
     // Transaction manager is delivering the transactions.
-    txn_manager: Arc<dyn TxnManager<Payload = T>>,
+    txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
     // Time service to generate block timestamps
     time_service: Arc<dyn TimeService>,
     // Max number of transactions to be added to a proposed block.
@@ -53,7 +53,7 @@ impl<T: Payload> ProposalGenerator<T> {
     pub fn new(
         author: Author,
         block_store: Arc<dyn BlockReader<Payload = T> + Send + Sync>,
-        txn_manager: Arc<dyn TxnManager<Payload = T>>,
+        txn_manager: Arc<dyn TxnManager<Payload = T, Error = TxnManagerFailure>>,
         time_service: Arc<dyn TimeService>,
         max_block_size: u64,
     ) -> Self {
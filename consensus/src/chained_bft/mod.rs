@@ -4,6 +4,7 @@
 mod consensusdb;
 
 mod block_storage;
+#[cfg(feature = "grpc-mempool")]
 pub mod chained_bft_consensus_provider;
 mod chained_bft_smr;
 mod network;
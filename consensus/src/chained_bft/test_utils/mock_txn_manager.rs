@@ -1,9 +1,8 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::state_replication::TxnManager;
+use crate::state_replication::{TxnManager, TxnManagerFailure};
 use executor::StateComputeResult;
-use failure::Result;
 use futures::{channel::mpsc, future, Future, FutureExt, SinkExt};
 use std::{
     pin::Pin,
@@ -49,13 +48,14 @@ impl MockTransactionManager {
 
 impl TxnManager for MockTransactionManager {
     type Payload = Vec<MockTransaction>;
+    type Error = TxnManagerFailure;
 
     /// The returned future is fulfilled with the vector of SignedTransactions
     fn pull_txns(
         &self,
         max_size: u64,
         _exclude_txns: Vec<&Self::Payload>,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Payload>> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Result<Self::Payload, Self::Error>> + Send>> {
         let next_value = self.next_val.load(Ordering::SeqCst);
         let upper_bound = next_value + max_size as usize;
         let res = (next_value..upper_bound).collect();
@@ -68,7 +68,7 @@ impl TxnManager for MockTransactionManager {
         txns: &Self::Payload,
         _compute_result: &StateComputeResult,
         _timestamp_usecs: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send + 'a>> {
         let committed_tns = txns.clone();
         let mut commit_sender = self.commit_sender.clone();
         async move {
@@ -3,15 +3,22 @@
 
 use failure::prelude::*;
 use libra_config::config::NodeConfig;
-use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
 
-use crate::chained_bft::chained_bft_consensus_provider::ChainedBftProvider;
-use executor::Executor;
-use grpcio::{ChannelBuilder, EnvBuilder};
-use libra_mempool::proto::mempool::MempoolClient;
-use state_synchronizer::StateSyncClient;
+use grpcio::EnvBuilder;
 use std::sync::Arc;
 use storage_client::{StorageRead, StorageReadServiceClient};
+
+#[cfg(feature = "grpc-mempool")]
+use crate::{
+    chained_bft::chained_bft_consensus_provider::ChainedBftProvider, txn_manager::MempoolProxy,
+};
+#[cfg(feature = "grpc-mempool")]
+use executor::Executor;
+#[cfg(feature = "grpc-mempool")]
+use network::validator_network::{ConsensusNetworkEvents, ConsensusNetworkSender};
+#[cfg(feature = "grpc-mempool")]
+use state_synchronizer::StateSyncClient;
+#[cfg(feature = "grpc-mempool")]
 use vm_runtime::MoveVM;
 
 /// Public interface to a consensus protocol.
@@ -28,6 +35,7 @@ pub trait ConsensusProvider {
 }
 
 /// Helper function to create a ConsensusProvider based on configuration
+#[cfg(feature = "grpc-mempool")]
 pub fn make_consensus_provider(
     node_config: &mut NodeConfig,
     network_sender: ConsensusNetworkSender,
@@ -39,20 +47,20 @@ pub fn make_consensus_provider(
         node_config,
         network_sender,
         network_receiver,
-        create_mempool_client(node_config),
+        create_mempool_proxy(node_config),
         executor,
         state_sync_client,
     ))
 }
 
-/// Create a mempool client assuming the mempool is running on localhost
-fn create_mempool_client(config: &NodeConfig) -> Arc<MempoolClient> {
+/// Create a mempool proxy assuming the mempool is running on localhost
+#[cfg(feature = "grpc-mempool")]
+fn create_mempool_proxy(config: &NodeConfig) -> Arc<MempoolProxy> {
     let port = config.mempool.mempool_service_port;
     let connection_str = format!("localhost:{}", port);
-
-    let env = Arc::new(EnvBuilder::new().name_prefix("grpc-con-mem-").build());
-    Arc::new(MempoolClient::new(
-        ChannelBuilder::new(env).connect(&connection_str),
+    Arc::new(MempoolProxy::connect(
+        &connection_str,
+        &config.consensus.mempool_proxy,
     ))
 }
 
@@ -3,7 +3,19 @@
 
 use lazy_static;
 use libra_metrics::DurationHistogram;
-use prometheus::{Histogram, IntCounter, IntCounterVec, IntGauge};
+use prometheus::{
+    core::Collector, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+use std::collections::BTreeMap;
+
+/// Buckets (in seconds) of the mempool round-trip histograms, ranging from sub-millisecond
+/// responses of an idle local mempool to multi-second stalls.
+const MEMPOOL_LATENCY_BUCKETS: [f64; 14] = [
+    0.0002, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Prefix of the names of the consensus counters in the global registry.
+const METRICS_NAME_PREFIX: &str = "libra_consensus_";
 
 lazy_static::lazy_static! {
 //////////////////////
@@ -52,6 +64,81 @@ pub static ref SYNC_INFO_CHANNEL_MSGS: IntCounterVec = register_int_counter_vec!
 pub static ref EPOCH_CHANGE_CHANNEL_MSGS: IntCounterVec = register_int_counter_vec!("libra_consensus_epoch_change_dropped_channel_msgs_count", "Counters(queued,dequeued,dropped) related to epoch change channel", &["state"]).unwrap();
 
 
+//////////////////////
+// TXN MANAGER COUNTERS
+//////////////////////
+/// Count of the discarded transactions since last restart, by VM status code of the discard.
+pub static ref DISCARDED_TXNS_BY_REASON: IntCounterVec = register_int_counter_vec!("libra_consensus_discarded_txns_by_reason", "Count of the discarded transactions since last restart, by VM status code of the discard.", &["reason"]).unwrap();
+
+/// Count of the pull_txns calls that timed out waiting for mempool since last restart.
+pub static ref PULL_TXNS_TIMEOUT_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_timeout_count", "Count of the pull_txns calls that timed out waiting for mempool since last restart.").unwrap();
+
+/// Count of the pull_txns attempts retried after a transient mempool failure since last restart.
+pub static ref PULL_TXNS_RETRY_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_retry_count", "Count of the pull_txns attempts retried after a transient mempool failure since last restart.").unwrap();
+
+/// Histogram of the number of distinct transactions excluded by a pull_txns request.
+pub static ref PULL_TXNS_EXCLUDED_COUNT: Histogram = register_histogram!("libra_consensus_pull_txns_excluded_count", "Histogram of the number of distinct transactions excluded by a pull_txns request.").unwrap();
+
+/// This counter is set to the number of transactions excluded by the last pull_txns request.
+pub static ref PULL_TXNS_EXCLUDE_SET_SIZE: IntGauge = register_int_gauge!("libra_consensus_pull_txns_exclude_set_size", "This counter is set to the number of transactions excluded by the last pull_txns request.").unwrap();
+
+/// Count of the transactions returned by mempool despite being excluded by the pull_txns request
+/// since last restart.
+pub static ref PULL_TXNS_EXCLUSION_VIOLATION_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_exclusion_violation_count", "Count of the transactions returned by mempool despite being excluded by the pull_txns request since last restart.").unwrap();
+
+/// Count of the pulled transactions dropped for being rejected by the txn validator since last
+/// restart.
+pub static ref PULL_TXNS_VALIDATION_REJECTED_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_validation_rejected_count", "Count of the pulled transactions dropped for being rejected by the txn validator since last restart.").unwrap();
+
+/// Count of the pulled transactions dropped for being among the recently pulled ones since last
+/// restart.
+pub static ref PULL_TXNS_RECENTLY_PULLED_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_recently_pulled_count", "Count of the pulled transactions dropped for being among the recently pulled ones since last restart.").unwrap();
+
+/// Count of the mempool responses to pull_txns without a block since last restart.
+pub static ref PULL_TXNS_MISSING_BLOCK_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_missing_block_count", "Count of the mempool responses to pull_txns without a block since last restart.").unwrap();
+
+/// This counter is set to the max size of the blocks pulled from mempool, as adapted to its latency.
+pub static ref PULL_TXNS_EFFECTIVE_MAX_SIZE: IntGauge = register_int_gauge!("libra_consensus_pull_txns_effective_max_size", "This counter is set to the max size of the blocks pulled from mempool, as adapted to its latency.").unwrap();
+
+/// Count of the pull_txns calls that returned no transactions since last restart.
+pub static ref PULL_TXNS_EMPTY_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_empty_count", "Count of the pull_txns calls that returned no transactions since last restart.").unwrap();
+
+/// Count of the records of pulled and committed payloads dropped because the txn log is behind.
+pub static ref TXN_LOG_DROPPED_RECORDS_COUNT: IntCounter = register_int_counter!("libra_consensus_txn_log_dropped_records_count", "Count of the records of pulled and committed payloads dropped because the txn log is behind.").unwrap();
+
+/// Count of the commits skipped for repeating a commit already confirmed by mempool.
+pub static ref COMMIT_TXNS_DEDUP_SKIPPED_COUNT: IntCounter = register_int_counter!("libra_consensus_commit_txns_dedup_skipped_count", "Count of the commits skipped for repeating a commit already confirmed by mempool.").unwrap();
+
+/// Count of the transactions received from mempool in response to pull_txns, decoded or not.
+pub static ref PULL_TXNS_RECEIVED_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_received_count", "Count of the transactions received from mempool in response to pull_txns, decoded or not.").unwrap();
+
+/// Count of the pulled transactions that failed to decode. A rising ratio of it to
+/// `PULL_TXNS_RECEIVED_COUNT` hints at a version skew between mempool and consensus.
+pub static ref PULL_TXNS_DECODE_FAILURES: IntCounter = register_int_counter!("libra_consensus_pull_txns_decode_failures", "Count of the pulled transactions that failed to decode.").unwrap();
+
+/// Count of the pulled transactions dropped for exceeding the max serialized size.
+pub static ref PULL_TXNS_OVERSIZED_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_oversized_count", "Count of the pulled transactions dropped for exceeding the max serialized size.").unwrap();
+
+/// Count of the pulled blocks in which a single sender exceeded the alert share of transactions.
+pub static ref PULL_TXNS_DOMINANT_SENDER_COUNT: IntCounter = register_int_counter!("libra_consensus_pull_txns_dominant_sender_count", "Count of the pulled blocks in which a single sender exceeded the alert share of transactions.").unwrap();
+
+/// Histogram of the number of transactions returned by a pull_txns call.
+pub static ref PULL_TXNS_SIZE: Histogram = register_histogram!("libra_consensus_pull_txns_size", "Histogram of the number of transactions returned by a pull_txns call.").unwrap();
+
+/// Histogram of the time from issuing a pull_txns request to its resolution.
+pub static ref PULL_TXNS_LATENCY: DurationHistogram = DurationHistogram::new(register_histogram!("libra_consensus_pull_txns_latency_s", "Histogram of the time from issuing a pull_txns request to its resolution.", MEMPOOL_LATENCY_BUCKETS.to_vec()).unwrap());
+
+/// Histogram of the time from issuing a commit request to mempool to its resolution.
+pub static ref COMMIT_TXNS_LATENCY: DurationHistogram = DurationHistogram::new(register_histogram!("libra_consensus_commit_txns_latency_s", "Histogram of the time from issuing a commit request to mempool to its resolution.", MEMPOOL_LATENCY_BUCKETS.to_vec()).unwrap());
+
+/// Count of the mempool requests that waited for the number of in-flight requests to drop below
+/// the limit since last restart.
+pub static ref MEMPOOL_REQUESTS_THROTTLED_COUNT: IntCounter = register_int_counter!("libra_consensus_mempool_requests_throttled_count", "Count of the mempool requests that waited for the number of in-flight requests to drop below the limit since last restart.").unwrap();
+
+/// Count of the mempool pings that failed since last restart.
+pub static ref MEMPOOL_PING_FAILED_COUNT: IntCounter = register_int_counter!("libra_consensus_mempool_ping_failed_count", "Count of the mempool pings that failed since last restart.").unwrap();
+
+
 //////////////////////
 // PROPOSAL ELECTION
 //////////////////////
@@ -137,6 +224,10 @@ pub static ref BLOCK_COMMIT_DURATION_S: DurationHistogram = DurationHistogram::n
 /// Histogram for the number of txns per (committed) blocks.
 pub static ref NUM_TXNS_PER_BLOCK: Histogram = register_histogram!("libra_consensus_num_txns_per_block", "Histogram for the number of txns per (committed) blocks.").unwrap();
 
+/// Histogram for the serialized size in bytes of the txns of (committed) blocks, observed for a
+/// sample of the blocks.
+pub static ref BYTES_PER_BLOCK: Histogram = register_histogram!("libra_consensus_bytes_per_block", "Histogram for the serialized size in bytes of the txns of (committed) blocks, observed for a sample of the blocks.", prometheus::exponential_buckets(256.0, 2.0, 15).unwrap()).unwrap();
+
 /// Histogram of per-transaction execution time of non-empty blocks
 /// (calculated as the overall execution time of a block divided by the number of transactions).
 pub static ref TXN_EXECUTION_DURATION_S: DurationHistogram = DurationHistogram::new(register_histogram!("libra_consensus_txn_execution_duration_s", "Histogram of per-transaction execution time of non-empty blocks (calculated as the overall execution time of a block divided by the number of transactions).").unwrap());
@@ -213,3 +304,127 @@ pub static ref PENDING_SYNC_INFO_MSGS: IntGauge = register_int_gauge!("libra_con
 /// Count of the pending winning proposals.
 pub static ref PENDING_WINNING_PROPOSALS: IntGauge = register_int_gauge!("libra_consensus_pending_winning_proposals", "Count of the pending winning proposals.").unwrap();
 }
+
+/// Values of the txn manager counters at some point in time. The registry of the counters is
+/// global, so tests take a snapshot as a baseline and assert on the increments since then rather
+/// than on absolute values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TxnManagerCounters {
+    /// Value of `COMMITTED_BLOCKS_COUNT`.
+    pub committed_blocks: i64,
+    /// Value of `COMMITTED_TXNS_COUNT` for the kept transactions.
+    pub committed_txns_success: i64,
+    /// Value of `COMMITTED_TXNS_COUNT` for the discarded transactions.
+    pub committed_txns_failed: i64,
+    /// Values of `DISCARDED_TXNS_BY_REASON`, by reason.
+    pub discarded_txns_by_reason: BTreeMap<String, i64>,
+    /// Value of `PULL_TXNS_TIMEOUT_COUNT`.
+    pub pull_txns_timeout: i64,
+    /// Value of `PULL_TXNS_RETRY_COUNT`.
+    pub pull_txns_retry: i64,
+    /// Value of `PULL_TXNS_EXCLUSION_VIOLATION_COUNT`.
+    pub pull_txns_exclusion_violation: i64,
+    /// Value of `PULL_TXNS_MISSING_BLOCK_COUNT`.
+    pub pull_txns_missing_block: i64,
+    /// Value of `PULL_TXNS_EMPTY_COUNT`.
+    pub pull_txns_empty: i64,
+    /// Value of `MEMPOOL_REQUESTS_THROTTLED_COUNT`.
+    pub mempool_requests_throttled: i64,
+    /// Value of `MEMPOOL_PING_FAILED_COUNT`.
+    pub mempool_ping_failed: i64,
+}
+
+impl TxnManagerCounters {
+    /// Increments of the counters from `baseline` to `self`, reasons without increments are
+    /// omitted.
+    pub fn delta_since(&self, baseline: &Self) -> Self {
+        let discarded_txns_by_reason = self
+            .discarded_txns_by_reason
+            .iter()
+            .filter_map(|(reason, value)| {
+                let delta = value - baseline.discarded_txns_by_reason.get(reason).unwrap_or(&0);
+                if delta == 0 {
+                    None
+                } else {
+                    Some((reason.clone(), delta))
+                }
+            })
+            .collect();
+        Self {
+            committed_blocks: self.committed_blocks - baseline.committed_blocks,
+            committed_txns_success: self.committed_txns_success - baseline.committed_txns_success,
+            committed_txns_failed: self.committed_txns_failed - baseline.committed_txns_failed,
+            discarded_txns_by_reason,
+            pull_txns_timeout: self.pull_txns_timeout - baseline.pull_txns_timeout,
+            pull_txns_retry: self.pull_txns_retry - baseline.pull_txns_retry,
+            pull_txns_exclusion_violation: self.pull_txns_exclusion_violation
+                - baseline.pull_txns_exclusion_violation,
+            pull_txns_missing_block: self.pull_txns_missing_block
+                - baseline.pull_txns_missing_block,
+            pull_txns_empty: self.pull_txns_empty - baseline.pull_txns_empty,
+            mempool_requests_throttled: self.mempool_requests_throttled
+                - baseline.mempool_requests_throttled,
+            mempool_ping_failed: self.mempool_ping_failed - baseline.mempool_ping_failed,
+        }
+    }
+}
+
+/// Returns the current values of the txn manager counters.
+pub fn snapshot() -> TxnManagerCounters {
+    let discarded_txns_by_reason = DISCARDED_TXNS_BY_REASON
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            metric.get_label().first().map(|label| {
+                (
+                    label.get_value().to_string(),
+                    metric.get_counter().get_value() as i64,
+                )
+            })
+        })
+        .collect();
+    TxnManagerCounters {
+        committed_blocks: COMMITTED_BLOCKS_COUNT.get(),
+        committed_txns_success: COMMITTED_TXNS_COUNT.with_label_values(&["success"]).get(),
+        committed_txns_failed: COMMITTED_TXNS_COUNT.with_label_values(&["failed"]).get(),
+        discarded_txns_by_reason,
+        pull_txns_timeout: PULL_TXNS_TIMEOUT_COUNT.get(),
+        pull_txns_retry: PULL_TXNS_RETRY_COUNT.get(),
+        pull_txns_exclusion_violation: PULL_TXNS_EXCLUSION_VIOLATION_COUNT.get(),
+        pull_txns_missing_block: PULL_TXNS_MISSING_BLOCK_COUNT.get(),
+        pull_txns_empty: PULL_TXNS_EMPTY_COUNT.get(),
+        mempool_requests_throttled: MEMPOOL_REQUESTS_THROTTLED_COUNT.get(),
+        mempool_ping_failed: MEMPOOL_PING_FAILED_COUNT.get(),
+    }
+}
+
+/// Renders the consensus counters in the Prometheus text exposition format, leaving out the
+/// counters other crates register in the global registry.
+pub fn render() -> String {
+    let families: Vec<_> = prometheus::gather()
+        .into_iter()
+        .filter(|family| family.get_name().starts_with(METRICS_NAME_PREFIX))
+        .collect();
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&families, &mut buffer)
+        .expect("Encoding the consensus counters failed");
+    String::from_utf8(buffer).expect("The text encoding of the counters is not utf-8")
+}
+
+/// Zeroes the txn manager counters. Tests running concurrently share the counters, so only a
+/// test that is alone to touch them can rely on absolute values after a reset.
+#[cfg(test)]
+pub fn reset() {
+    COMMITTED_BLOCKS_COUNT.reset();
+    COMMITTED_TXNS_COUNT.reset();
+    DISCARDED_TXNS_BY_REASON.reset();
+    PULL_TXNS_TIMEOUT_COUNT.reset();
+    PULL_TXNS_RETRY_COUNT.reset();
+    PULL_TXNS_EXCLUSION_VIOLATION_COUNT.reset();
+    PULL_TXNS_MISSING_BLOCK_COUNT.reset();
+    PULL_TXNS_EMPTY_COUNT.reset();
+    MEMPOOL_REQUESTS_THROTTLED_COUNT.reset();
+    MEMPOOL_PING_FAILED_COUNT.reset();
+}
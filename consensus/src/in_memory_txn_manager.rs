@@ -0,0 +1,88 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::state_replication::{TxnManager, TxnManagerFailure};
+use executor::StateComputeResult;
+use futures::{future, Future, FutureExt};
+use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use std::{collections::HashSet, pin::Pin, sync::RwLock};
+
+#[cfg(test)]
+#[path = "in_memory_txn_manager_test.rs"]
+mod in_memory_txn_manager_test;
+
+/// TxnManager serving transactions from memory instead of mempool, e.g. to measure the
+/// throughput of consensus without the grpc round-trips.
+/// Transactions are pulled in insertion order and dropped from the store once committed.
+pub struct InMemoryTxnManager {
+    txns: RwLock<Vec<SignedTransaction>>,
+}
+
+impl InMemoryTxnManager {
+    /// Store serving `txns`.
+    pub fn new(txns: Vec<SignedTransaction>) -> Self {
+        Self {
+            txns: RwLock::new(txns),
+        }
+    }
+
+    /// Add transactions to the end of the store.
+    pub fn add_txns(&self, txns: Vec<SignedTransaction>) {
+        self.txns.write().unwrap().extend(txns);
+    }
+
+    /// Number of transactions that have not been committed yet.
+    pub fn len(&self) -> usize {
+        self.txns.read().unwrap().len()
+    }
+
+    /// Whether all the transactions have been committed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn txn_key(txn: &SignedTransaction) -> (AccountAddress, u64) {
+        (txn.sender(), txn.sequence_number())
+    }
+}
+
+impl TxnManager for InMemoryTxnManager {
+    type Payload = Vec<SignedTransaction>;
+    type Error = TxnManagerFailure;
+
+    fn pull_txns(
+        &self,
+        max_size: u64,
+        exclude_payloads: Vec<&Self::Payload>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self::Payload, Self::Error>> + Send>> {
+        let excluded: HashSet<_> = exclude_payloads
+            .into_iter()
+            .flatten()
+            .map(Self::txn_key)
+            .collect();
+        let block = self
+            .txns
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|txn| !excluded.contains(&Self::txn_key(txn)))
+            .take(max_size as usize)
+            .cloned()
+            .collect();
+        future::ok(block).boxed()
+    }
+
+    fn commit_txns<'a>(
+        &'a self,
+        txns: &Self::Payload,
+        _compute_result: &StateComputeResult,
+        _timestamp_usecs: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send + 'a>> {
+        let committed: HashSet<_> = txns.iter().map(Self::txn_key).collect();
+        self.txns
+            .write()
+            .unwrap()
+            .retain(|txn| !committed.contains(&Self::txn_key(txn)));
+        future::ok(()).boxed()
+    }
+}
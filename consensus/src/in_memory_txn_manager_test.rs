@@ -0,0 +1,61 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::InMemoryTxnManager;
+use crate::state_replication::TxnManager;
+use executor::StateComputeResult;
+use futures::executor::block_on;
+use libra_crypto::ed25519::compat;
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{RawTransaction, Script, SignedTransaction},
+};
+use std::time::Duration;
+
+fn create_signed_txn(sender: AccountAddress, sequence_number: u64) -> SignedTransaction {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    RawTransaction::new_script(
+        sender,
+        sequence_number,
+        Script::new(vec![], vec![]),
+        0,
+        0,
+        Duration::from_secs(0),
+    )
+    .sign(&private_key, public_key)
+    .expect("Failed to sign txn")
+    .into_inner()
+}
+
+#[test]
+fn test_pull_txns() {
+    let sender = AccountAddress::random();
+    let txns: Vec<_> = (0..4).map(|i| create_signed_txn(sender, i)).collect();
+    let txn_manager = InMemoryTxnManager::new(txns.clone());
+
+    let pulled = block_on(txn_manager.pull_txns(2, vec![])).unwrap();
+    assert_eq!(pulled, txns[..2].to_vec());
+
+    // Pending txns are skipped and the block is filled with the following ones.
+    let pending = vec![txns[0].clone(), txns[2].clone()];
+    let pulled = block_on(txn_manager.pull_txns(2, vec![&pending])).unwrap();
+    assert_eq!(pulled, vec![txns[1].clone(), txns[3].clone()]);
+    assert_eq!(txn_manager.len(), 4);
+}
+
+#[test]
+fn test_commit_txns() {
+    let sender = AccountAddress::random();
+    let txns: Vec<_> = (0..3).map(|i| create_signed_txn(sender, i)).collect();
+    let txn_manager = InMemoryTxnManager::new(txns[..2].to_vec());
+    txn_manager.add_txns(vec![txns[2].clone()]);
+
+    let committed = txns[..2].to_vec();
+    block_on(txn_manager.commit_txns(&committed, &StateComputeResult::default(), 0)).unwrap();
+    assert_eq!(txn_manager.len(), 1);
+    let pulled = block_on(txn_manager.pull_txns(10, vec![])).unwrap();
+    assert_eq!(pulled, vec![txns[2].clone()]);
+
+    block_on(txn_manager.commit_txns(&pulled, &StateComputeResult::default(), 0)).unwrap();
+    assert!(txn_manager.is_empty());
+}
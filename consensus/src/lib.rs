@@ -8,6 +8,10 @@
 //! Encapsulates public consensus traits and any implementations of those traits.
 //! Currently, the only consensus protocol supported is LibraBFT (based on
 //! [HotStuff](https://arxiv.org/pdf/1803.05069.pdf)).
+//!
+//! The `grpc-mempool` feature, on by default, provides MempoolProxy, the TxnManager talking to
+//! mempool over grpc, and the ConsensusProvider built on it. Without it, the crate does not depend
+//! on the mempool protos, e.g. for simulations bringing their own TxnManager.
 
 #![cfg_attr(not(feature = "fuzzing"), deny(missing_docs))]
 #![cfg_attr(feature = "fuzzing", allow(dead_code))]
@@ -21,8 +25,11 @@ extern crate debug_interface;
 #[macro_use]
 extern crate prometheus;
 
+// Without grpc-mempool no ConsensusProvider drives the protocol, whose modules are then unused.
+#[cfg_attr(not(feature = "grpc-mempool"), allow(dead_code))]
 mod chained_bft;
 
+#[cfg_attr(not(feature = "grpc-mempool"), allow(dead_code))]
 mod util;
 
 #[cfg(feature = "fuzzing")]
@@ -32,8 +39,27 @@ pub use chained_bft::event_processor_fuzzing;
 /// use in the Libra Core blockchain.
 pub mod consensus_provider;
 
-mod counters;
+/// Prometheus counters of consensus.
+pub mod counters;
+
+mod in_memory_txn_manager;
 
+mod recording_txn_manager;
+pub use recording_txn_manager::{read_records, RecordingTxnManager, TxnManagerRecord};
+
+#[cfg_attr(not(feature = "grpc-mempool"), allow(dead_code))]
 mod state_computer;
 mod state_replication;
+#[cfg(feature = "grpc-mempool")]
 mod txn_manager;
+
+#[cfg(feature = "grpc-mempool")]
+pub use crate::txn_manager::{
+    Clock, CommitSummary, ContextualMempoolProxy, MempoolInterface, MempoolProxy,
+    MempoolResponseFuture, ProxyStats, SystemClock, TxnManagerContext, TxnManagerError,
+    TxnValidator,
+};
+pub use crate::{
+    in_memory_txn_manager::InMemoryTxnManager,
+    state_replication::{TxnManager, TxnManagerFailure},
+};
@@ -0,0 +1,253 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{counters, state_replication::TxnManager};
+use executor::StateComputeResult;
+use failure::prelude::*;
+use futures::{Future, FutureExt};
+use libra_logger::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    pin::Pin,
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+#[cfg(any(test, feature = "fuzzing"))]
+use crate::in_memory_txn_manager::InMemoryTxnManager;
+
+#[cfg(test)]
+#[path = "recording_txn_manager_test.rs"]
+mod recording_txn_manager_test;
+
+/// Max number of records waiting to be written to the log, further records are dropped so that
+/// a slow disk never holds back consensus.
+const RECORD_BUFFER_SIZE: usize = 1024;
+
+/// An operation of a TxnManager, as recorded in the log of RecordingTxnManager.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum TxnManagerRecord<P> {
+    /// A successful pull.
+    Pull {
+        /// Max number of transactions pulled.
+        max_size: u64,
+        /// The pulled payload.
+        payload: P,
+    },
+    /// A successful commit.
+    Commit {
+        /// Timestamp of the committed block.
+        timestamp_usecs: u64,
+        /// The committed payload.
+        payload: P,
+    },
+}
+
+/// TxnManager delegating to an inner TxnManager, e.g. MempoolProxy, while appending every pulled
+/// and committed payload to a log, for post-incident analysis. The log is written by a dedicated
+/// thread through a bounded buffer, records that do not fit in it are dropped rather than
+/// delaying the operation.
+/// Records are encoded with LCS, each prefixed with its length as a little endian u32.
+pub struct RecordingTxnManager<T: TxnManager> {
+    inner: Arc<T>,
+    /// Emptied on drop, which stops the writer even if pending futures still refer to it.
+    records: Arc<Mutex<Option<SyncSender<TxnManagerRecord<T::Payload>>>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl<T> RecordingTxnManager<T>
+where
+    T: TxnManager,
+    T::Payload: Clone + Serialize + Send + 'static,
+{
+    /// Wraps `inner`, appending the records to the log file at `path`.
+    pub fn new(inner: Arc<T>, path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format_err!("Cannot open txn log {:?}: {}", path, e))?;
+        let (sender, receiver) = mpsc::sync_channel(RECORD_BUFFER_SIZE);
+        let writer = thread::Builder::new()
+            .name("txn-log".to_string())
+            .spawn(move || write_records(receiver, file))?;
+        Ok(Self {
+            inner,
+            records: Arc::new(Mutex::new(Some(sender))),
+            writer: Some(writer),
+        })
+    }
+
+    fn record(
+        records: &Mutex<Option<SyncSender<TxnManagerRecord<T::Payload>>>>,
+        record: TxnManagerRecord<T::Payload>,
+    ) {
+        if let Some(records) = &*records.lock().unwrap() {
+            if let Err(TrySendError::Full(_)) = records.try_send(record) {
+                counters::TXN_LOG_DROPPED_RECORDS_COUNT.inc();
+            }
+        }
+    }
+}
+
+impl<T: TxnManager> Drop for RecordingTxnManager<T> {
+    /// Waits for the buffered records to be written.
+    fn drop(&mut self) {
+        self.records.lock().unwrap().take();
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!("The txn log writer panicked");
+            }
+        }
+    }
+}
+
+impl<T> TxnManager for RecordingTxnManager<T>
+where
+    T: TxnManager + 'static,
+    T::Payload: Clone + Serialize + Send + 'static,
+{
+    type Payload = T::Payload;
+    type Error = T::Error;
+
+    fn pull_txns(
+        &self,
+        max_size: u64,
+        exclude_payloads: Vec<&Self::Payload>,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<Self::Payload, Self::Error>> + Send>> {
+        let pull = self.inner.pull_txns(max_size, exclude_payloads);
+        let records = Arc::clone(&self.records);
+        async move {
+            let payload = pull.await?;
+            Self::record(
+                &records,
+                TxnManagerRecord::Pull {
+                    max_size,
+                    payload: payload.clone(),
+                },
+            );
+            Ok(payload)
+        }
+            .boxed()
+    }
+
+    fn commit_txns<'a>(
+        &'a self,
+        txns: &Self::Payload,
+        compute_result: &StateComputeResult,
+        timestamp_usecs: u64,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        let commit = self
+            .inner
+            .commit_txns(txns, compute_result, timestamp_usecs);
+        let payload = txns.clone();
+        async move {
+            commit.await?;
+            Self::record(
+                &self.records,
+                TxnManagerRecord::Commit {
+                    timestamp_usecs,
+                    payload,
+                },
+            );
+            Ok(())
+        }
+            .boxed()
+    }
+
+    fn mempool_size(
+        &self,
+    ) -> Option<Pin<Box<dyn Future<Output = std::result::Result<usize, Self::Error>> + Send>>> {
+        self.inner.mempool_size()
+    }
+
+    fn flush<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the records until the manager is dropped, flushing whenever none is waiting.
+fn write_records<P: Serialize>(receiver: Receiver<TxnManagerRecord<P>>, file: File) {
+    let mut writer = BufWriter::new(file);
+    while let Ok(mut record) = receiver.recv() {
+        loop {
+            if let Err(e) = write_record(&mut writer, &record) {
+                error!("Failed to write to the txn log: {}", e);
+            }
+            match receiver.try_recv() {
+                Ok(next) => record = next,
+                Err(_) => break,
+            }
+        }
+        if let Err(e) = writer.flush() {
+            error!("Failed to flush the txn log: {}", e);
+        }
+    }
+}
+
+fn write_record<W: Write, P: Serialize>(
+    writer: &mut W,
+    record: &TxnManagerRecord<P>,
+) -> Result<()> {
+    let bytes = lcs::to_bytes(record)?;
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Reads back the records of a log written by RecordingTxnManager. A record truncated by a crash
+/// of the writer ends the log.
+pub fn read_records<P: DeserializeOwned>(path: &Path) -> Result<Vec<TxnManagerRecord<P>>> {
+    let file =
+        File::open(path).map_err(|e| format_err!("Cannot open txn log {:?}: {}", path, e))?;
+    let mut reader = BufReader::new(file);
+    let mut records = vec![];
+    loop {
+        let mut len = [0u8; 4];
+        let mut bytes = match reader.read_exact(&mut len) {
+            Ok(()) => vec![0u8; u32::from_le_bytes(len) as usize],
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => records.push(lcs::from_bytes(&bytes)?),
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                warn!("Ignoring the truncated last record of txn log {:?}", path);
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(records)
+}
+
+/// Replays the recorded operations into `txn_manager`: the pulled transactions are added to it
+/// and the committed ones are committed.
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) fn replay(
+    records: Vec<TxnManagerRecord<Vec<libra_types::transaction::SignedTransaction>>>,
+    txn_manager: &InMemoryTxnManager,
+) -> Result<()> {
+    for record in records {
+        match record {
+            TxnManagerRecord::Pull { payload, .. } => txn_manager.add_txns(payload),
+            TxnManagerRecord::Commit {
+                timestamp_usecs,
+                payload,
+            } => futures::executor::block_on(txn_manager.commit_txns(
+                &payload,
+                &StateComputeResult::default(),
+                timestamp_usecs,
+            ))?,
+        }
+    }
+    Ok(())
+}
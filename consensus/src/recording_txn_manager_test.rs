@@ -0,0 +1,102 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{read_records, replay, RecordingTxnManager, TxnManagerRecord};
+use crate::{in_memory_txn_manager::InMemoryTxnManager, state_replication::TxnManager};
+use executor::StateComputeResult;
+use futures::executor::block_on;
+use libra_crypto::ed25519::compat;
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{RawTransaction, Script, SignedTransaction},
+};
+use std::{sync::Arc, time::Duration};
+use tempfile::tempdir;
+
+fn create_signed_txn(sender: AccountAddress, sequence_number: u64) -> SignedTransaction {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    RawTransaction::new_script(
+        sender,
+        sequence_number,
+        Script::new(vec![], vec![]),
+        0,
+        0,
+        Duration::from_secs(0),
+    )
+    .sign(&private_key, public_key)
+    .expect("Failed to sign txn")
+    .into_inner()
+}
+
+#[test]
+fn test_record_and_replay() {
+    let sender = AccountAddress::random();
+    let txns: Vec<_> = (0..4).map(|i| create_signed_txn(sender, i)).collect();
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("txn.log");
+    let inner = Arc::new(InMemoryTxnManager::new(txns.clone()));
+    let txn_manager = RecordingTxnManager::new(Arc::clone(&inner), &path).unwrap();
+
+    // The operations behave as the ones of the inner txn manager.
+    let first = block_on(txn_manager.pull_txns(2, vec![])).unwrap();
+    assert_eq!(first, txns[..2].to_vec());
+    block_on(txn_manager.commit_txns(&first, &StateComputeResult::default(), 1)).unwrap();
+    let second = block_on(txn_manager.pull_txns(10, vec![])).unwrap();
+    assert_eq!(second, txns[2..].to_vec());
+    assert_eq!(inner.len(), 2);
+    // Dropping the txn manager waits for the records to be written.
+    drop(txn_manager);
+
+    let records = read_records(&path).unwrap();
+    assert_eq!(
+        records,
+        vec![
+            TxnManagerRecord::Pull {
+                max_size: 2,
+                payload: first,
+            },
+            TxnManagerRecord::Commit {
+                timestamp_usecs: 1,
+                payload: txns[..2].to_vec(),
+            },
+            TxnManagerRecord::Pull {
+                max_size: 10,
+                payload: second,
+            },
+        ]
+    );
+
+    // Replaying the log leaves the pulled txns that were not committed.
+    let replayed = InMemoryTxnManager::new(vec![]);
+    replay(records, &replayed).unwrap();
+    assert_eq!(
+        block_on(replayed.pull_txns(10, vec![])).unwrap(),
+        txns[2..].to_vec()
+    );
+}
+
+#[test]
+fn test_read_truncated_records() {
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0)];
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("txn.log");
+    let txn_manager =
+        RecordingTxnManager::new(Arc::new(InMemoryTxnManager::new(txns.clone())), &path).unwrap();
+    block_on(txn_manager.pull_txns(1, vec![])).unwrap();
+    block_on(txn_manager.pull_txns(1, vec![])).unwrap();
+    drop(txn_manager);
+
+    // A record cut short by a crash ends the log.
+    let len = std::fs::metadata(&path).unwrap().len();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(len - 1).unwrap();
+    let records: Vec<TxnManagerRecord<Vec<SignedTransaction>>> = read_records(&path).unwrap();
+    assert_eq!(
+        records,
+        vec![TxnManagerRecord::Pull {
+            max_size: 1,
+            payload: txns,
+        }]
+    );
+}
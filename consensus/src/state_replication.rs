@@ -5,13 +5,20 @@ use consensus_types::block::Block;
 use consensus_types::executed_block::ExecutedBlock;
 use executor::{ExecutedTrees, ProcessedVMOutput, StateComputeResult};
 use failure::Result;
-use futures::Future;
+use futures::{future, Future, FutureExt};
 use libra_types::crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof};
 use std::{pin::Pin, sync::Arc};
 
+/// Error of the TxnManagers failing with `failure::Error`, which does not implement
+/// `std::error::Error` itself.
+pub type TxnManagerFailure = failure::Compat<failure::Error>;
+
 /// Retrieves and updates the status of transactions on demand (e.g., via talking with Mempool)
 pub trait TxnManager: Send + Sync {
+    /// Transactions of a block, as pulled and committed.
     type Payload;
+    /// Error the futures of the txn manager fail with.
+    type Error: std::error::Error + Send + Sync + 'static;
 
     /// Brings new transactions to be applied.
     /// The `exclude_txns` list includes the transactions that are already pending in the
@@ -20,7 +27,7 @@ pub trait TxnManager: Send + Sync {
         &self,
         max_size: u64,
         exclude_txns: Vec<&Self::Payload>,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Payload>> + Send>>;
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<Self::Payload, Self::Error>> + Send>>;
 
     /// Notifies TxnManager about the payload of the committed block including the state compute
     /// result, which includes the specifics of what transactions succeeded and failed.
@@ -30,7 +37,22 @@ pub trait TxnManager: Send + Sync {
         compute_result: &StateComputeResult,
         // Monotonic timestamp_usecs of committed blocks is used to GC expired transactions.
         timestamp_usecs: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>>;
+
+    /// Number of transactions waiting to be pulled, if the implementation is able to tell.
+    fn mempool_size(
+        &self,
+    ) -> Option<Pin<Box<dyn Future<Output = std::result::Result<usize, Self::Error>> + Send>>> {
+        None
+    }
+
+    /// Waits for the commits notified so far to complete, so that shutting down does not lose
+    /// them. Commits whose future was dropped are not waited for.
+    fn flush<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        future::ok(()).boxed()
+    }
 }
 
 /// While Consensus is managing proposed blocks, `StateComputer` is managing the results of the
@@ -89,9 +111,9 @@ pub trait StateMachineReplication {
     type Payload;
     /// The function is synchronous: it returns when the state is initialized / recovered from
     /// persisted storage and all the threads have been started.
-    fn start(
+    fn start<E: std::error::Error + Send + Sync + 'static>(
         &mut self,
-        txn_manager: Arc<dyn TxnManager<Payload = Self::Payload>>,
+        txn_manager: Arc<dyn TxnManager<Payload = Self::Payload, Error = E>>,
         state_computer: Arc<dyn StateComputer<Payload = Self::Payload>>,
     ) -> Result<()>;
 
@@ -1,28 +1,841 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{counters, state_replication::TxnManager};
+use crate::{
+    counters,
+    state_replication::{TxnManager, TxnManagerFailure},
+};
+use consensus_types::common::Round;
 use executor::StateComputeResult;
-use failure::Result;
-use futures::{compat::Future01CompatExt, future, Future, FutureExt};
+use failure::prelude::*;
+use futures::{
+    channel::oneshot, future, stream, Future, FutureExt, Stream, StreamExt, TryStreamExt,
+};
+use futures_01::{
+    executor::{self as executor_01, Notify, NotifyHandle},
+    Async, Future as Future01,
+};
+use futures_semaphore::{Permit, Semaphore};
+use grpcio::{CallOption, ChannelBuilder, ClientUnaryReceiver, EnvBuilder};
+use libra_config::config::{AdaptivePullConfig, MempoolProxyConfig};
+use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_logger::prelude::*;
 use libra_mempool::proto::mempool::{
-    CommitTransactionsRequest, CommittedTransaction, GetBlockRequest, MempoolClient,
-    TransactionExclusion,
+    CommitTransactionsRequest, CommitTransactionsResponse, CommittedTransaction, GetBlockRequest,
+    GetBlockResponse, HealthCheckRequest, HealthCheckResponse, MempoolClient, TransactionExclusion,
 };
-use libra_types::transaction::{SignedTransaction, TransactionStatus};
-use std::{convert::TryFrom, pin::Pin, sync::Arc};
+use libra_types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    proto::types::SignedTransaction as SignedTransactionProto,
+    transaction::{SignedTransaction, Transaction, TransactionStatus},
+    vm_error::{StatusCode, VMStatus},
+};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, timeout};
+
+#[cfg(test)]
+#[path = "txn_manager_test.rs"]
+mod txn_manager_test;
+
+/// Max block size of the request used to estimate the number of transactions held by mempool.
+const MEMPOOL_SIZE_ESTIMATE_MAX_BLOCK_SIZE: u64 = 10_000;
+
+/// Sender, sequence number and, when excluding by hash, hash of an excluded transaction.
+type ExclusionKey = (AccountAddress, u64, Option<HashValue>);
+
+/// Number of transactions decoded at once by pull_txns_stream.
+const STREAM_DECODE_CHUNK_SIZE: usize = 1000;
+
+/// Blocks with fewer transactions are decoded serially, as parallelism does not pay off.
+const PARALLEL_DECODE_THRESHOLD: usize = 100;
+
+/// Source of time of MempoolProxy, for tests to drive the latencies it measures, the windows of
+/// its rate limited logs and the backoff of its retries deterministically. Unlike TimeService,
+/// it is monotonic and needs no executor. The deadlines of the grpc calls are still enforced by
+/// the tokio timer.
+pub trait Clock: Send + Sync {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Future resolving once `duration` elapsed.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// The clock of the system, used by default.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        delay_for(duration).boxed()
+    }
+}
+
+/// Why a request of MempoolProxy failed, so that callers can branch on the category of the
+/// failure, e.g. to only retry when mempool is unreachable. The TxnManager trait reports it as a
+/// TxnManagerFailure, whose inner `failure::Error` downcasts back to it.
+#[derive(Debug, Fail)]
+pub enum TxnManagerError {
+    #[fail(display = "{}", _0)]
+    /// Mempool is unreachable, or the grpc call to it failed.
+    Connection(grpcio::Error),
+    #[fail(display = "{}", _0)]
+    /// Mempool did not respond in time.
+    Timeout(String),
+    #[fail(display = "{}", _0)]
+    /// The response of mempool could not be decoded.
+    Decode(String),
+    #[fail(display = "{}", _0)]
+    /// Mempool does not implement the rpc, consensus and mempool run incompatible versions.
+    ProtocolMismatch(String),
+    #[fail(display = "{}", _0)]
+    /// Mempool received the request but failed it.
+    MempoolRejected(String),
+    #[fail(display = "{}", _0)]
+    /// The request is invalid and was not sent to mempool.
+    InvalidRequest(String),
+}
+
+impl TxnManagerError {
+    /// The failure the TxnManager trait reports the error with.
+    fn into_failure(self) -> TxnManagerFailure {
+        failure::Error::from(self).compat()
+    }
+
+    /// Categorizes the failure of a grpc call to mempool.
+    fn from_grpc(error: grpcio::Error) -> Self {
+        let has_status = |code| match &error {
+            grpcio::Error::RpcFailure(status) => status.status == code,
+            _ => false,
+        };
+        if has_status(grpcio::RpcStatusCode::DEADLINE_EXCEEDED) {
+            TxnManagerError::Timeout(error.to_string())
+        } else if has_status(grpcio::RpcStatusCode::UNIMPLEMENTED) {
+            TxnManagerError::ProtocolMismatch(error.to_string())
+        } else if has_status(grpcio::RpcStatusCode::UNAVAILABLE) {
+            TxnManagerError::Connection(error)
+        } else if let grpcio::Error::RpcFailure(_) = &error {
+            TxnManagerError::MempoolRejected(error.to_string())
+        } else if let grpcio::Error::Codec(_) = &error {
+            TxnManagerError::Decode(error.to_string())
+        } else {
+            TxnManagerError::Connection(error)
+        }
+    }
+}
+
+/// Breakdown of the transactions of a committed block, as reported to mempool.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CommitSummary {
+    /// Number of transactions whose output was kept.
+    pub kept: usize,
+    /// Number of transactions whose output was discarded.
+    pub discarded: usize,
+}
+
+/// Consensus event an operation of MempoolProxy is performed for, logged with the operation to
+/// correlate it with the logs of consensus, see `MempoolProxy::with_context`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TxnManagerContext {
+    /// Round of the block the operation is performed for.
+    pub round: Option<Round>,
+    /// Id of the block the operation is performed for.
+    pub block_id: Option<HashValue>,
+}
+
+impl TxnManagerContext {
+    /// The block id as logged, in full rather than the prefix HashValue displays.
+    fn block_id_field(&self) -> Option<String> {
+        self.block_id.map(|block_id| format!("{:x}", block_id))
+    }
+}
+
+/// Adapts the max size of the blocks pulled from mempool to its latency: the size is halved while
+/// the average latency of the recent pulls exceeds the threshold, and grows back by a tenth per
+/// pull otherwise, within the configured bounds.
+struct PullSizeController {
+    slow_latency: Duration,
+    min_size: u64,
+    max_size: u64,
+    effective_max_size: u64,
+    avg_latency: Option<Duration>,
+}
+
+impl PullSizeController {
+    fn new(config: &AdaptivePullConfig) -> Self {
+        let max_size = std::cmp::max(config.max_size, 1);
+        let min_size = std::cmp::min(std::cmp::max(config.min_size, 1), max_size);
+        counters::PULL_TXNS_EFFECTIVE_MAX_SIZE.set(max_size as i64);
+        Self {
+            slow_latency: Duration::from_millis(config.slow_latency_ms),
+            min_size,
+            max_size,
+            effective_max_size: max_size,
+            avg_latency: None,
+        }
+    }
+
+    fn effective_max_size(&self) -> u64 {
+        self.effective_max_size
+    }
+
+    /// Accounts for the latency of a pull in the size of the next ones.
+    fn record(&mut self, latency: Duration) {
+        // Exponentially weighted, so that only the recent pulls matter.
+        let avg_latency = match self.avg_latency {
+            Some(avg_latency) => (avg_latency + latency) / 2,
+            None => latency,
+        };
+        self.avg_latency = Some(avg_latency);
+        self.effective_max_size = if avg_latency > self.slow_latency {
+            std::cmp::max(self.effective_max_size / 2, self.min_size)
+        } else {
+            let step = std::cmp::max(self.effective_max_size / 10, 1);
+            std::cmp::min(self.effective_max_size + step, self.max_size)
+        };
+        counters::PULL_TXNS_EFFECTIVE_MAX_SIZE.set(self.effective_max_size as i64);
+    }
+}
+
+/// Coalesces the security events repeated within a window of the last one logged into a single
+/// log line, so that a flood of them does not blow up the log volume and mask other events.
+struct SecurityLogRateLimiter {
+    window: Duration,
+    /// Start of the current window and number of events coalesced within it.
+    state: Mutex<(Option<Instant>, u64)>,
+}
+
+impl SecurityLogRateLimiter {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    /// Accounts for an event happening at `now`. Returns the number of events the log line of
+    /// this event stands for, itself included, or `None` if the event is coalesced into a later
+    /// line.
+    fn record(&self, now: Instant) -> Option<u64> {
+        if self.window == Duration::from_secs(0) {
+            return Some(1);
+        }
+        let mut state = self.state.lock().unwrap();
+        let (window_start, coalesced) = &mut *state;
+        match window_start {
+            Some(start) if now.duration_since(*start) < self.window => {
+                *coalesced += 1;
+                None
+            }
+            _ => {
+                *window_start = Some(now);
+                let count = *coalesced + 1;
+                *coalesced = 0;
+                Some(count)
+            }
+        }
+    }
+}
+
+/// Remembers the senders and sequence numbers of the last pulled transactions, so that a
+/// transaction mempool returns again shortly after, e.g. because the exclusions of the pull raced
+/// with the previous proposal, is not proposed twice.
+struct RecentlyPulled {
+    capacity: usize,
+    /// The remembered transactions, least recently pulled first.
+    order: VecDeque<(AccountAddress, u64)>,
+    keys: HashSet<(AccountAddress, u64)>,
+}
+
+impl RecentlyPulled {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            keys: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Records that `txn` was pulled, evicting the least recently pulled txn once the window is
+    /// full. Returns whether the txn was already within the window, in which case it keeps its
+    /// place: a txn mempool keeps returning leaves the window, and is pulled again, once as many
+    /// other txns were pulled after it as the window holds.
+    fn record(&mut self, txn: &SignedTransaction) -> bool {
+        let key = (txn.sender(), txn.sequence_number());
+        if self.keys.contains(&key) {
+            return true;
+        }
+        if self.capacity == 0 {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.keys.remove(&evicted);
+            }
+        }
+        self.order.push_back(key);
+        self.keys.insert(key);
+        false
+    }
+}
+
+/// Runtime stats of a MempoolProxy, see `MempoolProxy::stats`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ProxyStats {
+    /// Number of requests to mempool awaiting a response.
+    pub in_flight_requests: usize,
+    /// Time the last pull took, timeouts and retries included, zero before the first pull.
+    pub last_pull_latency: Duration,
+    /// Time the last commit request took to be answered, zero before the first one.
+    pub last_commit_latency: Duration,
+    /// Max size of the last pull, as clamped and adapted to the latency of mempool, zero before
+    /// the first pull.
+    pub effective_max_size: u64,
+    /// Number of pulls done, whether they succeeded or not.
+    pub total_pulls: u64,
+    /// Number of blocks committed to mempool.
+    pub total_commits: u64,
+}
+
+/// The atomics the ProxyStats of a proxy are read from, so that reading them never waits for the
+/// requests in flight.
+#[derive(Default)]
+struct ProxyStatsRecorder {
+    last_pull_latency_us: AtomicU64,
+    last_commit_latency_us: AtomicU64,
+    effective_max_size: AtomicU64,
+    total_pulls: AtomicU64,
+    total_commits: AtomicU64,
+}
+
+impl ProxyStatsRecorder {
+    fn record_pull(&self, latency: Duration) {
+        self.last_pull_latency_us
+            .store(latency.as_micros() as u64, Ordering::Relaxed);
+        self.total_pulls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_commit_request(&self, latency: Duration) {
+        self.last_commit_latency_us
+            .store(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Size of a page of `MempoolProxy::pull_all`, as requested from mempool and as returned by it,
+/// before pulled transactions are dropped: a page smaller than requested tells that mempool has no
+/// more transactions to return. The exclusion keys of every decoded transaction of the page,
+/// dropped or not, are recorded so that the next pages exclude them.
+#[derive(Default)]
+struct PulledPage {
+    requested: AtomicU64,
+    received: AtomicU64,
+    received_keys: Mutex<Vec<ExclusionKey>>,
+}
+
+/// Max number of pages pulled by one call of `MempoolProxy::pull_all`, so that a mempool
+/// returning the excluded transactions again cannot keep it pulling forever.
+const PULL_ALL_MAX_PAGES: usize = 100;
+
+/// Number of confirmed commits remembered by CommitDedupGuard.
+const COMMIT_DEDUP_WINDOW: usize = 128;
+
+/// Identifies a committed block by its id and its timestamp. Without the block id, e.g. for
+/// commits through the TxnManager trait, the hash of the senders and sequence numbers of its
+/// transactions stands for it.
+type CommitKey = (HashValue, u64);
+
+/// Outcome of reserving a commit in CommitDedupGuard.
+#[derive(Debug, PartialEq)]
+enum CommitReservation {
+    /// Mempool already confirmed the commit, with this summary.
+    Confirmed(CommitSummary),
+    /// The same commit is being sent to mempool by another call.
+    InFlight,
+    /// The commit is reserved for the caller, which must confirm or release it.
+    Reserved,
+}
+
+/// Remembers the commits mempool most recently confirmed, so that a commit repeated after the
+/// first one succeeded, e.g. by a caller retrying after losing the response, is skipped instead
+/// of being processed twice by mempool. Commits are reserved before being sent, so that a
+/// duplicate called while the first one is in flight is not sent either.
+struct CommitDedupGuard {
+    capacity: usize,
+    /// Confirmed commits and their summaries, least recently used first.
+    confirmed: VecDeque<(CommitKey, CommitSummary)>,
+    /// Commits reserved and not yet confirmed or released.
+    in_flight: HashSet<CommitKey>,
+}
+
+impl CommitDedupGuard {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            confirmed: VecDeque::with_capacity(capacity),
+            in_flight: HashSet::new(),
+        }
+    }
+
+    fn key(
+        txns: &[SignedTransaction],
+        timestamp_usecs: u64,
+        context: &TxnManagerContext,
+    ) -> CommitKey {
+        let block_id = context.block_id.unwrap_or_else(|| {
+            let mut bytes = Vec::with_capacity(txns.len() * (ADDRESS_LENGTH + 8));
+            for txn in txns {
+                bytes.extend_from_slice(txn.sender().as_ref());
+                bytes.extend_from_slice(&txn.sequence_number().to_le_bytes());
+            }
+            HashValue::from_sha3_256(&bytes)
+        });
+        (block_id, timestamp_usecs)
+    }
+
+    /// The summary of the commit `key` if it was confirmed, making it the most recently used one.
+    fn get(&mut self, key: &CommitKey) -> Option<CommitSummary> {
+        let index = self
+            .confirmed
+            .iter()
+            .position(|(confirmed, _)| confirmed == key)?;
+        let entry = self.confirmed.remove(index)?;
+        let summary = entry.1;
+        self.confirmed.push_back(entry);
+        Some(summary)
+    }
+
+    /// Reserves the commit `key` unless it was already confirmed or reserved. Nothing is tracked
+    /// when the window is empty.
+    fn reserve(&mut self, key: CommitKey) -> CommitReservation {
+        if self.capacity == 0 {
+            return CommitReservation::Reserved;
+        }
+        if let Some(summary) = self.get(&key) {
+            return CommitReservation::Confirmed(summary);
+        }
+        if self.in_flight.insert(key) {
+            CommitReservation::Reserved
+        } else {
+            CommitReservation::InFlight
+        }
+    }
+
+    /// Releases the reservation of the commit `key`, which mempool did not confirm.
+    fn release(&mut self, key: &CommitKey) {
+        self.in_flight.remove(key);
+    }
+
+    /// Records that mempool confirmed the commit `key`, evicting the least recently used commit
+    /// once the window is full.
+    fn confirm(&mut self, key: CommitKey, summary: CommitSummary) {
+        self.in_flight.remove(&key);
+        if self.capacity == 0 || self.get(&key).is_some() {
+            return;
+        }
+        if self.confirmed.len() == self.capacity {
+            self.confirmed.pop_front();
+        }
+        self.confirmed.push_back((key, summary));
+    }
+}
+
+/// A commit reserved in CommitDedupGuard, released when dropped unless it was confirmed: a commit
+/// failing, or whose future is dropped, can then be sent again.
+struct ReservedCommit<'a> {
+    dedup: &'a Mutex<CommitDedupGuard>,
+    key: CommitKey,
+    confirmed: bool,
+}
+
+impl<'a> ReservedCommit<'a> {
+    fn confirm(mut self, summary: CommitSummary) {
+        self.dedup.lock().unwrap().confirm(self.key, summary);
+        self.confirmed = true;
+    }
+}
+
+impl<'a> Drop for ReservedCommit<'a> {
+    fn drop(&mut self) {
+        if !self.confirmed {
+            self.dedup.lock().unwrap().release(&self.key);
+        }
+    }
+}
+
+/// The futures 0.1 receiver of a grpc call, whose call can be cancelled.
+trait CancellableReceiver: Future01 + Unpin {
+    /// Cancels the call, mempool is told to stop processing it and its response is discarded.
+    fn cancel(&mut self);
+}
+
+impl<T> CancellableReceiver for ClientUnaryReceiver<T> {
+    fn cancel(&mut self) {
+        ClientUnaryReceiver::cancel(self)
+    }
+}
+
+/// Drives the futures 0.1 receiver of a grpc call as a std future: every poll runs the receiver
+/// in a 0.1 task whose wake-ups are forwarded to the waker of the current context.
+/// Dropping it before the call completes, e.g. when the round of a pull is abandoned, cancels the
+/// call rather than leaving it in flight: its response is never processed.
+struct GrpcReceiver<F: CancellableReceiver> {
+    task: executor_01::Spawn<F>,
+    done: bool,
+}
+
+impl<F: CancellableReceiver> GrpcReceiver<F> {
+    fn new(receiver: F) -> Self {
+        Self {
+            task: executor_01::spawn(receiver),
+            done: false,
+        }
+    }
+}
+
+impl<F: CancellableReceiver> Future for GrpcReceiver<F> {
+    type Output = std::result::Result<F::Item, F::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let notify = NotifyHandle::from(Arc::new(WakerNotify(cx.waker().clone())));
+        let result = match self.task.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready(response)) => Ok(response),
+            Ok(Async::NotReady) => return Poll::Pending,
+            Err(e) => Err(e),
+        };
+        self.done = true;
+        Poll::Ready(result)
+    }
+}
+
+impl<F: CancellableReceiver> Drop for GrpcReceiver<F> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.task.get_mut().cancel();
+        }
+    }
+}
+
+/// Forwards the notifications of a futures 0.1 task to a std waker.
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _id: usize) {
+        self.0.wake_by_ref();
+    }
+}
+
+/// Future fulfilled with the response of a mempool rpc.
+pub type MempoolResponseFuture<T> = Pin<Box<dyn Future<Output = grpcio::Result<T>> + Send>>;
+
+/// The mempool rpcs MempoolProxy relies on, abstracted away from the grpc client so that the
+/// proxy can be exercised without a running mempool.
+pub trait MempoolInterface: Send + Sync {
+    /// Fetch ordered block of transactions.
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>>;
+
+    /// Remove committed transactions from mempool.
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>>;
+
+    /// Check the health of mempool.
+    fn health_check_async_opt(
+        &self,
+        req: &HealthCheckRequest,
+        opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>>;
+}
+
+impl MempoolInterface for MempoolClient {
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        Ok(GrpcReceiver::new(MempoolClient::get_block_async(self, req)?).boxed())
+    }
+
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        Ok(GrpcReceiver::new(MempoolClient::commit_transactions_async(self, req)?).boxed())
+    }
+
+    fn health_check_async_opt(
+        &self,
+        req: &HealthCheckRequest,
+        opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        Ok(GrpcReceiver::new(MempoolClient::health_check_async_opt(self, req, opt)?).boxed())
+    }
+}
+
+/// Policy checked against every transaction pulled from mempool, e.g. that it is not expired.
+/// Transactions it rejects are dropped from the pulled block.
+pub type TxnValidator = Arc<dyn Fn(&SignedTransaction) -> bool + Send + Sync>;
 
 /// Proxy interface to mempool
-pub struct MempoolProxy {
-    mempool: Arc<MempoolClient>,
+pub struct MempoolProxy<M = MempoolClient> {
+    mempool: Arc<M>,
+    /// Upper bound on the time pull_txns waits for mempool to respond.
+    pull_timeout: Duration,
+    /// Number of times a transient pull failure is retried before giving up.
+    max_retries: u32,
+    /// Delay before the first retry, doubled on every subsequent attempt.
+    retry_base_delay: Duration,
+    /// Max number of transactions sent to mempool in a single commit request.
+    max_commit_batch: usize,
+    /// Deadline of the health check issued by ping.
+    ping_timeout: Duration,
+    /// Whether pulled transactions are reordered by gas unit price.
+    sort_by_gas_price: bool,
+    /// Number of leading compute statuses not matching any committed transaction.
+    num_prologue_statuses: usize,
+    /// Whether the exclusions of pull requests carry the hash of the excluded transactions.
+    exclude_by_hash: bool,
+    /// Resolves once the last commit is done, the next commit waits for it before being sent
+    /// to mempool.
+    last_commit: Mutex<Option<oneshot::Receiver<()>>>,
+    /// Bounds the number of requests to mempool awaiting a response.
+    in_flight: Semaphore,
+    /// Number of permits of `in_flight`.
+    max_in_flight_requests: usize,
+    /// Upper bound on the size of the blocks pulled from mempool.
+    max_block_size_limit: u64,
+    /// Commits with a block timestamp below it are rejected.
+    min_commit_timestamp_usecs: u64,
+    /// Max number of transactions `pull_all` accumulates.
+    pull_all_max_txns: u64,
+    /// Timestamp of the last block committed to mempool, mempool GCs by it so it must not
+    /// regress.
+    last_commit_timestamp_usecs: AtomicU64,
+    /// Whether commit requests are only built, without being sent to mempool.
+    dry_run: bool,
+    /// Adapts the size of pulls to the latency of mempool, when enabled.
+    pull_size: Option<Arc<Mutex<PullSizeController>>>,
+    /// One committed block out of every this many has its size in bytes observed, 0 disables
+    /// the observation.
+    bytes_per_block_sample_interval: u64,
+    /// Number of blocks committed since startup, selects the blocks whose size is observed.
+    num_committed_blocks: AtomicU64,
+    /// Share of a pulled block, in percent, above which the top senders of the block are logged,
+    /// 0 disables the accounting.
+    sender_share_alert_percent: u64,
+    /// Number of senders logged when a pulled block crosses the alert share.
+    sender_share_top_senders: usize,
+    /// Coalesces the security logs of the invalid and excluded transactions returned by mempool.
+    invalid_txn_log: Arc<SecurityLogRateLimiter>,
+    /// Max serialized size of a pulled transaction, larger ones are dropped undecoded.
+    max_txn_bytes: usize,
+    clock: Arc<dyn Clock>,
+    /// Commits confirmed by mempool, whose duplicates are skipped.
+    commit_dedup: Mutex<CommitDedupGuard>,
+    /// Filters the decoded transactions of every pull, when set.
+    txn_validator: Option<TxnValidator>,
+    /// The last pulled transactions, pulled again ones are dropped, when enabled.
+    recently_pulled: Option<Arc<Mutex<RecentlyPulled>>>,
+    stats: Arc<ProxyStatsRecorder>,
 }
 
-impl MempoolProxy {
-    pub fn new(mempool: Arc<MempoolClient>) -> Self {
+impl MempoolProxy<MempoolClient> {
+    /// Connect to the mempool listening on `address`, with the grpc channel tuned by the
+    /// connection settings of `config`.
+    pub fn connect(address: &str, config: &MempoolProxyConfig) -> Self {
+        let conn_config = &config.connection;
+        let mut env_builder = EnvBuilder::new().name_prefix("grpc-con-mem-");
+        if let Some(concurrency) = conn_config.concurrency {
+            env_builder = env_builder.cq_count(concurrency);
+        }
+        let mut builder = ChannelBuilder::new(Arc::new(env_builder.build()));
+        if let Some(keepalive_time_ms) = conn_config.keepalive_time_ms {
+            builder = builder.keepalive_time(Duration::from_millis(keepalive_time_ms));
+        }
+        if let Some(keepalive_timeout_ms) = conn_config.keepalive_timeout_ms {
+            builder = builder.keepalive_timeout(Duration::from_millis(keepalive_timeout_ms));
+        }
+        if let Some(max_message_len) = conn_config.max_message_len {
+            builder = builder
+                .max_receive_message_len(max_message_len)
+                .max_send_message_len(max_message_len);
+        }
+        let mempool = MempoolClient::new(builder.connect(address));
+        Self::new(Arc::new(mempool), config)
+    }
+}
+
+impl<M: MempoolInterface + 'static> MempoolProxy<M> {
+    /// Proxy sending its requests to `mempool`, tuned by `config`.
+    pub fn new(mempool: Arc<M>, config: &MempoolProxyConfig) -> Self {
+        Self::with_clock(mempool, config, Arc::new(SystemClock))
+    }
+
+    /// Same as `new`, with time measured by `clock` rather than by the clock of the system.
+    pub fn with_clock(mempool: Arc<M>, config: &MempoolProxyConfig, clock: Arc<dyn Clock>) -> Self {
+        let max_in_flight_requests = std::cmp::max(config.max_in_flight_requests, 1);
         Self {
             mempool: Arc::clone(&mempool),
+            pull_timeout: Duration::from_millis(config.pull_timeout_ms),
+            max_retries: config.pull_max_retries,
+            retry_base_delay: Duration::from_millis(config.pull_retry_base_delay_ms),
+            max_commit_batch: config.max_commit_batch,
+            ping_timeout: Duration::from_millis(config.ping_timeout_ms),
+            sort_by_gas_price: config.sort_by_gas_price,
+            num_prologue_statuses: config.num_prologue_statuses,
+            exclude_by_hash: config.exclude_by_hash,
+            last_commit: Mutex::new(None),
+            in_flight: Semaphore::new(max_in_flight_requests),
+            max_in_flight_requests,
+            max_block_size_limit: config.max_block_size_limit,
+            min_commit_timestamp_usecs: config.min_commit_timestamp_usecs,
+            pull_all_max_txns: config.pull_all_max_txns,
+            last_commit_timestamp_usecs: AtomicU64::new(0),
+            dry_run: config.dry_run,
+            pull_size: if config.adaptive_pull.enabled {
+                Some(Arc::new(Mutex::new(PullSizeController::new(
+                    &config.adaptive_pull,
+                ))))
+            } else {
+                None
+            },
+            bytes_per_block_sample_interval: config.bytes_per_block_sample_interval,
+            num_committed_blocks: AtomicU64::new(0),
+            sender_share_alert_percent: config.sender_share_alert_percent,
+            sender_share_top_senders: config.sender_share_top_senders,
+            invalid_txn_log: Arc::new(SecurityLogRateLimiter::new(Duration::from_millis(
+                config.invalid_txn_log_window_ms,
+            ))),
+            max_txn_bytes: config.max_txn_bytes,
+            clock,
+            commit_dedup: Mutex::new(CommitDedupGuard::new(COMMIT_DEDUP_WINDOW)),
+            txn_validator: None,
+            recently_pulled: if config.recently_pulled_window > 0 {
+                Some(Arc::new(Mutex::new(RecentlyPulled::new(
+                    config.recently_pulled_window,
+                ))))
+            } else {
+                None
+            },
+            stats: Arc::new(ProxyStatsRecorder::default()),
+        }
+    }
+
+    /// Current runtime stats of the proxy, e.g. for an admin endpoint. They are read from atomics
+    /// without waiting for the requests in flight, each field is up to date but they are not a
+    /// consistent snapshot of each other.
+    pub fn stats(&self) -> ProxyStats {
+        let stats = &self.stats;
+        ProxyStats {
+            in_flight_requests: self.max_in_flight_requests - self.in_flight.available_permits(),
+            last_pull_latency: Duration::from_micros(
+                stats.last_pull_latency_us.load(Ordering::Relaxed),
+            ),
+            last_commit_latency: Duration::from_micros(
+                stats.last_commit_latency_us.load(Ordering::Relaxed),
+            ),
+            effective_max_size: stats.effective_max_size.load(Ordering::Relaxed),
+            total_pulls: stats.total_pulls.load(Ordering::Relaxed),
+            total_commits: stats.total_commits.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Same proxy, dropping the pulled transactions rejected by `validator`.
+    pub fn with_txn_validator(mut self, validator: TxnValidator) -> Self {
+        self.txn_validator = Some(validator);
+        self
+    }
+
+    /// View of the proxy whose operations log `context` with every line and security event they
+    /// emit. The TxnManager trait carries no context, so callers knowing the round or the block
+    /// an operation is performed for go through this view instead.
+    pub fn with_context(&self, context: TxnManagerContext) -> ContextualMempoolProxy<'_, M> {
+        ContextualMempoolProxy {
+            proxy: self,
+            context,
+        }
+    }
+
+    /// Check that mempool is reachable: the returned future is fulfilled once mempool answers a
+    /// health check within the ping timeout.
+    pub fn ping(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TxnManagerError>> + Send>> {
+        let option = CallOption::default().timeout(self.ping_timeout);
+        let receiver = self
+            .mempool
+            .health_check_async_opt(&HealthCheckRequest::default(), option);
+        async move {
+            let response = match receiver {
+                Ok(receiver) => receiver.await,
+                Err(e) => Err(e),
+            };
+            match response {
+                Ok(response) => {
+                    if !response.is_healthy {
+                        warn!("Mempool is reachable but reports to be unhealthy");
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    counters::MEMPOOL_PING_FAILED_COUNT.inc();
+                    Err(TxnManagerError::from_grpc(e))
+                }
+            }
+        }
+            .boxed()
+    }
+
+    /// Best-effort estimate of the number of transactions held by mempool.
+    /// Mempool has no dedicated rpc for it, so the estimate is the size of a block pulled without
+    /// exclusions, which is inaccurate in several ways:
+    /// - it is capped by `MEMPOOL_SIZE_ESTIMATE_MAX_BLOCK_SIZE`,
+    /// - it only counts the transactions that are ready to be included in a block, transactions
+    ///   waiting for a lower sequence number of the same sender are not counted,
+    /// - it counts the transactions already pending in uncommitted blocks.
+    /// The whole block is transferred for every call, so it should not be issued on a hot path.
+    pub fn mempool_size(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<usize, TxnManagerError>> + Send>> {
+        let mut req = GetBlockRequest::default();
+        req.max_block_size = MEMPOOL_SIZE_ESTIMATE_MAX_BLOCK_SIZE;
+        let get_block = Self::get_block(
+            Arc::clone(&self.mempool),
+            self.in_flight.clone(),
+            req,
+            self.pull_timeout,
+            self.max_retries,
+            self.retry_base_delay,
+            Arc::clone(&self.clock),
+        );
+        let pull_timeout = self.pull_timeout;
+        async move {
+            match get_block.await? {
+                Some(response) => Ok(response.block.map_or(0, |block| block.transactions.len())),
+                None => Err(TxnManagerError::Timeout(format!(
+                    "Mempool did not return a block within {:?}",
+                    pull_timeout
+                ))),
+            }
         }
+            .boxed()
     }
 
     /// Generate mempool commit transactions request given the set of txns and their status
@@ -30,12 +843,36 @@ impl MempoolProxy {
         txns: &[SignedTransaction],
         compute_result: &StateComputeResult,
         timestamp_usecs: u64,
-    ) -> CommitTransactionsRequest {
-        let mut all_updates = Vec::new();
-        // we exclude the prologue txn, we probably need a way to ensure this aligns with state_computer
-        let status = compute_result.compute_status[1..].to_vec();
-        assert_eq!(txns.len(), status.len());
-        for (txn, status) in txns.iter().zip(compute_result.compute_status.iter()) {
+        num_prologue_statuses: usize,
+    ) -> std::result::Result<(CommitTransactionsRequest, CommitSummary), TxnManagerError> {
+        let mut all_updates = Vec::with_capacity(txns.len());
+        let mut summary = CommitSummary::default();
+        // The statuses of the transactions injected by the executor come first and are excluded.
+        if num_prologue_statuses > compute_result.compute_status.len() {
+            return Err(TxnManagerError::InvalidRequest(format!(
+                "Cannot skip {} prologue statuses out of {} compute statuses",
+                num_prologue_statuses,
+                compute_result.compute_status.len()
+            )));
+        }
+        let status = &compute_result.compute_status[num_prologue_statuses..];
+        if txns.len() != status.len() {
+            let e = TxnManagerError::InvalidRequest(format!(
+                "Cannot commit {} transactions with {} compute statuses",
+                txns.len(),
+                status.len()
+            ));
+            // The executor computed the statuses of these txns, a mismatch could hint at it
+            // being tampered with.
+            security_log(SecurityEvent::InvalidComputeStatusConsensus)
+                .error(&e)
+                .data(txns.len())
+                .data(status.len())
+                .data(timestamp_usecs)
+                .log();
+            return Err(e);
+        }
+        for (txn, status) in txns.iter().zip(status) {
             let mut transaction = CommittedTransaction::default();
             transaction.sender = txn.sender().as_ref().to_vec();
             transaction.sequence_number = txn.sequence_number();
@@ -44,12 +881,17 @@ impl MempoolProxy {
                     counters::COMMITTED_TXNS_COUNT
                         .with_label_values(&["success"])
                         .inc();
+                    summary.kept += 1;
                     transaction.is_rejected = false;
                 }
-                TransactionStatus::Discard(_) => {
+                TransactionStatus::Discard(vm_status) => {
                     counters::COMMITTED_TXNS_COUNT
                         .with_label_values(&["failed"])
                         .inc();
+                    counters::DISCARDED_TXNS_BY_REASON
+                        .with_label_values(&[Self::discard_reason(vm_status)])
+                        .inc();
+                    summary.discarded += 1;
                     transaction.is_rejected = true;
                 }
             };
@@ -58,75 +900,951 @@ impl MempoolProxy {
         let mut req = CommitTransactionsRequest::default();
         req.transactions = all_updates;
         req.block_timestamp_usecs = timestamp_usecs;
-        req
+        Ok((req, summary))
     }
 
-    /// Submit the request and return the future, which is fulfilled when the response is received.
-    fn submit_commit_transactions_request(
+    /// Whether the size of the block being committed is observed, one block out of every
+    /// `bytes_per_block_sample_interval` is.
+    fn sample_block_bytes(&self) -> bool {
+        self.bytes_per_block_sample_interval != 0
+            && self.num_committed_blocks.fetch_add(1, Ordering::Relaxed)
+                % self.bytes_per_block_sample_interval
+                == 0
+    }
+
+    /// Serialized size in bytes of the transactions of a block.
+    fn block_bytes(txns: &[SignedTransaction]) -> usize {
+        txns.iter()
+            .map(|txn| {
+                lcs::to_bytes(txn)
+                    .expect("Unable to serialize SignedTransaction")
+                    .len()
+            })
+            .sum()
+    }
+
+    /// Label of a discarded transaction in the discard reason counter. Transactions are discarded
+    /// by the validation of the VM, all the other status codes share a label to bound the
+    /// cardinality of the counter.
+    fn discard_reason(vm_status: &VMStatus) -> &'static str {
+        match vm_status.major_status {
+            StatusCode::INVALID_SIGNATURE => "invalid_signature",
+            StatusCode::INVALID_AUTH_KEY => "invalid_auth_key",
+            StatusCode::SEQUENCE_NUMBER_TOO_OLD => "sequence_number_too_old",
+            StatusCode::SEQUENCE_NUMBER_TOO_NEW => "sequence_number_too_new",
+            StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE => {
+                "insufficient_balance_for_transaction_fee"
+            }
+            StatusCode::TRANSACTION_EXPIRED => "transaction_expired",
+            StatusCode::SENDING_ACCOUNT_DOES_NOT_EXIST => "sending_account_does_not_exist",
+            StatusCode::REJECTED_WRITE_SET => "rejected_write_set",
+            StatusCode::INVALID_WRITE_SET => "invalid_write_set",
+            StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE => "exceeded_max_transaction_size",
+            StatusCode::UNKNOWN_SCRIPT => "unknown_script",
+            StatusCode::UNKNOWN_MODULE => "unknown_module",
+            StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND => {
+                "max_gas_units_exceeds_max_gas_units_bound"
+            }
+            StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS => {
+                "max_gas_units_below_min_transaction_gas_units"
+            }
+            StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND => "gas_unit_price_below_min_bound",
+            StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND => "gas_unit_price_above_max_bound",
+            _ => "other",
+        }
+    }
+
+    /// Same as `commit_txns`, but the returned future is fulfilled with the number of kept and
+    /// discarded transactions of the committed block.
+    pub fn commit_txns_with_summary<'a>(
+        &'a self,
+        txns: &[SignedTransaction],
+        compute_result: &StateComputeResult,
+        timestamp_usecs: u64,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<CommitSummary, TxnManagerError>> + Send + 'a>,
+    > {
+        self.commit_txns_with_context(
+            txns,
+            compute_result,
+            timestamp_usecs,
+            TxnManagerContext::default(),
+        )
+    }
+
+    fn commit_txns_with_context<'a>(
+        &'a self,
+        txns: &[SignedTransaction],
+        compute_result: &StateComputeResult,
+        timestamp_usecs: u64,
+        context: TxnManagerContext,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<CommitSummary, TxnManagerError>> + Send + 'a>,
+    > {
+        let commit_key = CommitDedupGuard::key(txns, timestamp_usecs, &context);
+        let reservation = self.commit_dedup.lock().unwrap().reserve(commit_key);
+        match reservation {
+            CommitReservation::Confirmed(summary) => {
+                counters::COMMIT_TXNS_DEDUP_SKIPPED_COUNT.inc();
+                debug!(
+                    "Skipping a commit already confirmed by mempool";
+                    "round" => context.round,
+                    "block_id" => context.block_id_field()
+                );
+                return future::ok(summary).boxed();
+            }
+            CommitReservation::InFlight => return self.await_in_flight_commit(commit_key, context),
+            CommitReservation::Reserved => (),
+        }
+        let reserved = ReservedCommit {
+            dedup: &self.commit_dedup,
+            key: commit_key,
+            confirmed: false,
+        };
+        let num_txns = txns.len();
+        let block_bytes = if self.sample_block_bytes() {
+            Some(Self::block_bytes(txns))
+        } else {
+            None
+        };
+        let (req, summary) = match Self::gen_commit_transactions_request(
+            txns,
+            compute_result,
+            timestamp_usecs,
+            self.num_prologue_statuses,
+        ) {
+            Ok(res) => res,
+            Err(e) => return future::err(e).boxed(),
+        };
+        let requests = Self::split_commit_transactions_request(req, self.max_commit_batch);
+        let num_requests = requests.len();
+        // Mempool GCs by block timestamp, so commits must reach it in the order commit_txns was
+        // called. Every commit waits for the previous one, which signals by dropping its sender
+        // once it is done (or once its future is dropped).
+        let (done_sender, done_receiver) = oneshot::channel::<()>();
+        let previous_commit = self.last_commit.lock().unwrap().replace(done_receiver);
+        async move {
+            let _done_sender = done_sender;
+            if let Some(previous_commit) = previous_commit {
+                let _ = previous_commit.await;
+            }
+            // The previous commits are done, the timestamp is checked against the last one that
+            // reached mempool.
+            self.check_commit_timestamp(timestamp_usecs, &context)?;
+            counters::COMMITTED_BLOCKS_COUNT.inc();
+            counters::NUM_TXNS_PER_BLOCK.observe(num_txns as f64);
+            if let Some(block_bytes) = block_bytes {
+                counters::BYTES_PER_BLOCK.observe(block_bytes as f64);
+            }
+            for (committed, req) in requests.into_iter().enumerate() {
+                if let Err(e) = self.submit_commit_transactions_request(req).await {
+                    if committed > 0 {
+                        error!(
+                            "Partial mempool commit: {} of {} batches committed before failure",
+                            committed, num_requests;
+                            "round" => context.round,
+                            "block_id" => context.block_id_field()
+                        );
+                    }
+                    return Err(e);
+                }
+            }
+            debug!(
+                "Committed block to mempool: {} kept, {} discarded txns",
+                summary.kept, summary.discarded;
+                "round" => context.round,
+                "block_id" => context.block_id_field()
+            );
+            // Commits are sent one at a time, nothing else updates the timestamp meanwhile.
+            self.last_commit_timestamp_usecs
+                .store(timestamp_usecs, Ordering::SeqCst);
+            reserved.confirm(summary);
+            self.stats.total_commits.fetch_add(1, Ordering::Relaxed);
+            Ok(summary)
+        }
+            .boxed()
+    }
+
+    /// Waits for the in flight commit `commit_key` sent by another call, which is done once every
+    /// commit called before this one is, and returns its summary if mempool confirmed it.
+    fn await_in_flight_commit<'a>(
+        &'a self,
+        commit_key: CommitKey,
+        context: TxnManagerContext,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<CommitSummary, TxnManagerError>> + Send + 'a>,
+    > {
+        let (done_sender, done_receiver) = oneshot::channel::<()>();
+        let previous_commit = self.last_commit.lock().unwrap().replace(done_receiver);
+        async move {
+            let _done_sender = done_sender;
+            if let Some(previous_commit) = previous_commit {
+                let _ = previous_commit.await;
+            }
+            match self.commit_dedup.lock().unwrap().get(&commit_key) {
+                Some(summary) => {
+                    counters::COMMIT_TXNS_DEDUP_SKIPPED_COUNT.inc();
+                    debug!(
+                        "Skipping a commit confirmed by mempool while it was repeated";
+                        "round" => context.round,
+                        "block_id" => context.block_id_field()
+                    );
+                    Ok(summary)
+                }
+                None => Err(TxnManagerError::InvalidRequest(
+                    "A concurrent commit of the same block failed".to_string(),
+                )),
+            }
+        }
+            .boxed()
+    }
+
+    /// Rejects the timestamp of a block being committed if it is older than the timestamp of the
+    /// last block committed to mempool. The timestamp is only recorded once the block reached
+    /// mempool, so that a commit failing does not reject the next ones.
+    fn check_commit_timestamp(
         &self,
+        timestamp_usecs: u64,
+        context: &TxnManagerContext,
+    ) -> std::result::Result<(), TxnManagerError> {
+        // Mempool GCs the transactions expiring before the timestamp, an implausibly small one
+        // would wipe it out.
+        if timestamp_usecs < self.min_commit_timestamp_usecs {
+            error!(
+                "Rejecting a commit with timestamp {} below the floor {}",
+                timestamp_usecs, self.min_commit_timestamp_usecs;
+                "round" => context.round,
+                "block_id" => context.block_id_field()
+            );
+            return Err(TxnManagerError::InvalidRequest(format!(
+                "Commit timestamp {} is below the floor {}",
+                timestamp_usecs, self.min_commit_timestamp_usecs
+            )));
+        }
+        let last_timestamp_usecs = self.last_commit_timestamp_usecs.load(Ordering::SeqCst);
+        if timestamp_usecs < last_timestamp_usecs {
+            error!(
+                "Rejecting a commit with timestamp {} older than the last one {}",
+                timestamp_usecs, last_timestamp_usecs;
+                "round" => context.round,
+                "block_id" => context.block_id_field()
+            );
+            return Err(TxnManagerError::InvalidRequest(format!(
+                "Commit timestamp {} regresses from {}",
+                timestamp_usecs, last_timestamp_usecs
+            )));
+        }
+        Ok(())
+    }
+
+    /// Split the request into batches of at most `max_commit_batch` transactions, so that the
+    /// commit of a large block does not exceed the grpc message size limit.
+    /// An empty block still results in a single request, which carries the block timestamp.
+    fn split_commit_transactions_request(
         req: CommitTransactionsRequest,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
-        match self.mempool.commit_transactions_async(&req) {
-            Ok(receiver) => async move {
-                match receiver.compat().await {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(e.into()),
+        max_commit_batch: usize,
+    ) -> Vec<CommitTransactionsRequest> {
+        if req.transactions.len() <= max_commit_batch {
+            return vec![req];
+        }
+        // The transactions are moved into the batches rather than cloned, large blocks would
+        // otherwise copy the sender of every transaction once more.
+        let max_commit_batch = std::cmp::max(max_commit_batch, 1);
+        let num_batches = (req.transactions.len() + max_commit_batch - 1) / max_commit_batch;
+        let block_timestamp_usecs = req.block_timestamp_usecs;
+        let mut transactions = req.transactions.into_iter();
+        (0..num_batches)
+            .map(|_| {
+                let mut batch_req = CommitTransactionsRequest::default();
+                batch_req.transactions = transactions.by_ref().take(max_commit_batch).collect();
+                batch_req.block_timestamp_usecs = block_timestamp_usecs;
+                batch_req
+            })
+            .collect()
+    }
+
+    /// Fetch a block from mempool, retrying transient failures with exponential backoff.
+    /// Resolves to `None` if mempool did not respond within `pull_timeout`.
+    async fn get_block(
+        mempool: Arc<M>,
+        in_flight: Semaphore,
+        req: GetBlockRequest,
+        pull_timeout: Duration,
+        max_retries: u32,
+        retry_base_delay: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> std::result::Result<Option<GetBlockResponse>, TxnManagerError> {
+        let mut attempt = 0;
+        loop {
+            let permit = Self::acquire_permit(&in_flight).await;
+            let result = match mempool.get_block_async(&req) {
+                // On timeout the receiver is dropped together with the timeout future, which
+                // cancels the underlying grpc call if it is still in flight, see GrpcReceiver.
+                Ok(receiver) => match timeout(pull_timeout, receiver).await {
+                    Ok(response) => response.map(Some),
+                    Err(_) => Ok(None),
+                },
+                Err(e) => Err(e),
+            };
+            // The permit is not held while backing off.
+            drop(permit);
+            match result {
+                Err(e) if attempt < max_retries && Self::is_retryable(&e) => {
+                    let backoff = retry_base_delay * 2u32.saturating_pow(attempt);
+                    counters::PULL_TXNS_RETRY_COUNT.inc();
+                    warn!(
+                        "Failed to pull a block from mempool: {:?}, retrying in {:?}",
+                        e, backoff
+                    );
+                    clock.sleep(backoff).await;
+                    attempt += 1;
                 }
+                result => return result.map_err(TxnManagerError::from_grpc),
             }
-                .boxed(),
-            Err(e) => future::err(e.into()).boxed(),
         }
     }
-}
 
-impl TxnManager for MempoolProxy {
-    type Payload = Vec<SignedTransaction>;
+    /// Wait until the number of requests in flight allows issuing one more.
+    async fn acquire_permit(in_flight: &Semaphore) -> Permit {
+        match in_flight.try_acquire() {
+            Some(permit) => permit,
+            None => {
+                counters::MEMPOOL_REQUESTS_THROTTLED_COUNT.inc();
+                in_flight.acquire().await
+            }
+        }
+    }
 
-    /// The returned future is fulfilled with the vector of SignedTransactions
-    fn pull_txns(
+    /// Only retry when mempool is unreachable, any other failure is returned right away.
+    fn is_retryable(error: &grpcio::Error) -> bool {
+        if let grpcio::Error::RpcFailure(status) = error {
+            status.status == grpcio::RpcStatusCode::UNAVAILABLE
+        } else {
+            false
+        }
+    }
+
+    /// Same as `pull_txns`, but the transactions are yielded as soon as they are decoded instead
+    /// of once the whole block is, in the order mempool returned them (`sort_by_gas_price` only
+    /// applies to `pull_txns`). A failed pull yields a single error.
+    pub fn pull_txns_stream(
         &self,
         max_size: u64,
-        exclude_payloads: Vec<&Self::Payload>,
-    ) -> Pin<Box<dyn Future<Output = Result<Self::Payload>> + Send>> {
+        exclude_payloads: Vec<&Vec<SignedTransaction>>,
+    ) -> Pin<Box<dyn Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> + Send>>
+    {
+        let exclusions = Self::flatten_exclusions(exclude_payloads, self.exclude_by_hash);
+        self.pull_txns_stream_excluding(
+            max_size,
+            exclusions,
+            self.exclude_by_hash,
+            TxnManagerContext::default(),
+        )
+    }
+
+    /// Same as `pull_txns`, but excluding the transactions of the given senders and sequence
+    /// numbers, for callers maintaining their own exclusion index rather than the payloads
+    /// pending in the branch. As the exclusions carry no hash, transactions are excluded by
+    /// sender and sequence number even if `exclude_by_hash` is set.
+    pub fn pull_txns_with_exclusions(
+        &self,
+        max_size: u64,
+        exclusions: &[(AccountAddress, u64)],
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Vec<SignedTransaction>, TxnManagerError>>
+                + Send,
+        >,
+    > {
+        let exclusions = exclusions
+            .iter()
+            .map(|(sender, sequence_number)| (*sender, *sequence_number, None))
+            .collect();
+        let context = TxnManagerContext::default();
+        self.collect_block(
+            self.pull_txns_stream_excluding(max_size, exclusions, false, context),
+            context,
+        )
+    }
+
+    /// Pulls every remaining transaction of mempool not in `exclude_payloads`, regardless of the
+    /// size of a block, e.g. to finalize the last block of an epoch. Blocks of the max allowed
+    /// size are pulled, each one excluding the transactions of the previous ones, until mempool
+    /// returns a block smaller than requested, `pull_all_max_txns` are pulled or
+    /// `PULL_ALL_MAX_PAGES` blocks are pulled.
+    pub fn pull_all<'a>(
+        &'a self,
+        exclude_payloads: Vec<&Vec<SignedTransaction>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Vec<SignedTransaction>, TxnManagerError>>
+                + Send
+                + 'a,
+        >,
+    > {
+        let mut exclusions = Self::flatten_exclusions(exclude_payloads, self.exclude_by_hash);
+        let context = TxnManagerContext::default();
+        async move {
+            let mut txns = vec![];
+            for pages in 0.. {
+                if pages == PULL_ALL_MAX_PAGES {
+                    warn!(
+                        "Stopped pulling every txn of mempool after {} pages of {} txns",
+                        pages,
+                        txns.len()
+                    );
+                    break;
+                }
+                let remaining = self.pull_all_max_txns.saturating_sub(txns.len() as u64);
+                if remaining == 0 {
+                    warn!(
+                        "Stopped pulling every txn of mempool after {} txns",
+                        txns.len()
+                    );
+                    break;
+                }
+                let page = Arc::new(PulledPage::default());
+                let pulled: Vec<_> = self
+                    .pull_page(
+                        std::cmp::min(self.max_block_size_limit, remaining),
+                        exclusions.clone(),
+                        self.exclude_by_hash,
+                        context,
+                        Some(Arc::clone(&page)),
+                    )
+                    .try_collect()
+                    .await?;
+                // The dropped txns are excluded too, or mempool would return them again.
+                exclusions.extend(page.received_keys.lock().unwrap().drain(..));
+                txns.extend(pulled);
+                if page.received.load(Ordering::Relaxed) < page.requested.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+            if self.sort_by_gas_price {
+                Self::sort_by_gas_price(&mut txns);
+            }
+            debug!("Drained {} txns from mempool", txns.len());
+            Ok(txns)
+        }
+            .boxed()
+    }
+
+    /// The exclusion keys of the transactions of `exclude_payloads`, in order.
+    fn flatten_exclusions(
+        exclude_payloads: Vec<&Vec<SignedTransaction>>,
+        exclude_by_hash: bool,
+    ) -> Vec<ExclusionKey> {
+        exclude_payloads
+            .into_iter()
+            .flatten()
+            .map(|transaction| Self::exclusion_key(transaction, exclude_by_hash))
+            .collect()
+    }
+
+    fn pull_txns_stream_excluding(
+        &self,
+        max_size: u64,
+        exclusions: Vec<ExclusionKey>,
+        exclude_by_hash: bool,
+        context: TxnManagerContext,
+    ) -> Pin<Box<dyn Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> + Send>>
+    {
+        self.pull_page(max_size, exclusions, exclude_by_hash, context, None)
+    }
+
+    /// Same as `pull_txns_stream_excluding`, recording the size of the pulled block in `page`
+    /// when set.
+    fn pull_page(
+        &self,
+        max_size: u64,
+        exclusions: Vec<ExclusionKey>,
+        exclude_by_hash: bool,
+        context: TxnManagerContext,
+        page: Option<Arc<PulledPage>>,
+    ) -> Pin<Box<dyn Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> + Send>>
+    {
+        if max_size == 0 {
+            return stream::once(future::err(TxnManagerError::InvalidRequest(
+                "Cannot pull a block of at most 0 transactions from mempool".to_string(),
+            )))
+            .boxed();
+        }
+        let max_size = if max_size > self.max_block_size_limit {
+            warn!(
+                "Clamping the size of the block pulled from mempool from {} to {} txns",
+                max_size, self.max_block_size_limit;
+                "round" => context.round,
+                "block_id" => context.block_id_field()
+            );
+            self.max_block_size_limit
+        } else {
+            max_size
+        };
+        let max_size = match &self.pull_size {
+            Some(pull_size) => {
+                std::cmp::min(max_size, pull_size.lock().unwrap().effective_max_size())
+            }
+            None => max_size,
+        };
         let mut exclude_txns = vec![];
-        for payload in exclude_payloads {
-            for transaction in payload {
-                let mut txn_meta = TransactionExclusion::default();
-                txn_meta.sender = transaction.sender().into();
-                txn_meta.sequence_number = transaction.sequence_number();
-                exclude_txns.push(txn_meta);
+        let mut seen = HashSet::new();
+        for key in exclusions {
+            // The same txn may be pending in several blocks of the branch, exclude it once.
+            // First-seen order is kept so that the request is deterministic.
+            if !seen.insert(key) {
+                continue;
+            }
+            let (sender, sequence_number, hash) = key;
+            let mut txn_meta = TransactionExclusion::default();
+            txn_meta.sender = sender.into();
+            txn_meta.sequence_number = sequence_number;
+            if let Some(hash) = hash {
+                txn_meta.hash = hash.to_vec();
             }
+            exclude_txns.push(txn_meta);
         }
+        counters::PULL_TXNS_EXCLUDED_COUNT.observe(exclude_txns.len() as f64);
+        counters::PULL_TXNS_EXCLUDE_SET_SIZE.set(exclude_txns.len() as i64);
         let mut get_block_request = GetBlockRequest::default();
         get_block_request.max_block_size = max_size;
         get_block_request.transactions = exclude_txns;
-        match self.mempool.get_block_async(&get_block_request) {
-            Ok(receiver) => async move {
-                match receiver.compat().await {
-                    Ok(response) => Ok(response
-                        .block
-                        .unwrap_or_else(Default::default)
-                        .transactions
-                        .into_iter()
-                        .filter_map(|proto_txn| {
-                            match SignedTransaction::try_from(proto_txn.clone()) {
-                                Ok(t) => Some(t),
-                                Err(e) => {
-                                    security_log(SecurityEvent::InvalidTransactionConsensus)
-                                        .error(&e)
-                                        .data(&proto_txn)
-                                        .log();
-                                    None
-                                }
-                            }
-                        })
-                        .collect()),
-                    Err(e) => Err(e.into()),
+        debug!(
+            "Pulling at most {} txns from mempool, request hash {}",
+            max_size,
+            Self::get_block_request_hash(&get_block_request);
+            "round" => context.round,
+            "block_id" => context.block_id_field()
+        );
+        let get_block = Self::get_block(
+            Arc::clone(&self.mempool),
+            self.in_flight.clone(),
+            get_block_request,
+            self.pull_timeout,
+            self.max_retries,
+            self.retry_base_delay,
+            Arc::clone(&self.clock),
+        );
+        let pull_timeout = self.pull_timeout;
+        let pull_size = self.pull_size.clone();
+        let invalid_txn_log = Arc::clone(&self.invalid_txn_log);
+        let max_txn_bytes = self.max_txn_bytes;
+        let clock = Arc::clone(&self.clock);
+        let pull_clock = Arc::clone(&self.clock);
+        let txn_validator = self.txn_validator.clone();
+        let recently_pulled = self.recently_pulled.clone();
+        self.stats
+            .effective_max_size
+            .store(max_size, Ordering::Relaxed);
+        if let Some(page) = &page {
+            page.requested.store(max_size, Ordering::Relaxed);
+        }
+        let stats = Arc::clone(&self.stats);
+        async move {
+            let start = pull_clock.now();
+            let response = get_block.await;
+            let latency = pull_clock.now().duration_since(start);
+            counters::PULL_TXNS_LATENCY.observe_duration(latency);
+            stats.record_pull(latency);
+            if let Some(pull_size) = pull_size {
+                pull_size.lock().unwrap().record(latency);
+            }
+            match response {
+                Ok(Some(response)) => {
+                    // A mempool without transactions still returns an empty block, a missing
+                    // block rather hints at a protocol mismatch between consensus and mempool.
+                    let block = response.block.unwrap_or_else(|| {
+                        counters::PULL_TXNS_MISSING_BLOCK_COUNT.inc();
+                        warn!(
+                            "Mempool response to a pull of at most {} txns has no block",
+                            max_size;
+                            "round" => context.round,
+                            "block_id" => context.block_id_field()
+                        );
+                        Default::default()
+                    });
+                    Ok(block.transactions)
+                }
+                Ok(None) => {
+                    counters::PULL_TXNS_TIMEOUT_COUNT.inc();
+                    warn!(
+                        "Mempool did not return a block within {:?}, proposing an empty block",
+                        pull_timeout;
+                        "round" => context.round,
+                        "block_id" => context.block_id_field()
+                    );
+                    Ok(vec![])
                 }
+                Err(e) => Err(e),
             }
-                .boxed(),
-            Err(e) => future::err(e.into()).boxed(),
         }
+            .map(move |result| match result {
+                Ok(proto_txns) => {
+                    if let Some(page) = &page {
+                        page.received
+                            .store(proto_txns.len() as u64, Ordering::Relaxed);
+                    }
+                    let violation_log = Arc::clone(&invalid_txn_log);
+                    let violation_clock = Arc::clone(&clock);
+                    let txns = Self::decode_transactions_stream(
+                        proto_txns,
+                        invalid_txn_log,
+                        clock,
+                        max_txn_bytes,
+                        context,
+                    );
+                    txns.try_filter(move |txn| {
+                        if let Some(page) = &page {
+                            page.received_keys
+                                .lock()
+                                .unwrap()
+                                .push(Self::exclusion_key(txn, exclude_by_hash));
+                        }
+                        future::ready(
+                            !Self::is_exclusion_violation(
+                                &seen,
+                                txn,
+                                exclude_by_hash,
+                                &violation_log,
+                                &*violation_clock,
+                                &context,
+                            ) && Self::is_accepted(txn_validator.as_ref(), txn, &context)
+                                && !Self::is_recently_pulled(recently_pulled.as_ref(), txn),
+                        )
+                    })
+                    .left_stream()
+                }
+                Err(e) => stream::once(future::err(e)).right_stream(),
+            })
+            .flatten_stream()
+            .boxed()
+    }
+
+    /// Collects the pulled block, sorting it by gas price if configured.
+    fn collect_block(
+        &self,
+        txns: Pin<
+            Box<dyn Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> + Send>,
+        >,
+        context: TxnManagerContext,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Vec<SignedTransaction>, TxnManagerError>>
+                + Send,
+        >,
+    > {
+        let sort_by_gas_price = self.sort_by_gas_price;
+        let sender_share_alert_percent = self.sender_share_alert_percent;
+        let sender_share_top_senders = self.sender_share_top_senders;
+        async move {
+            let mut txns: Vec<_> = txns.try_collect().await?;
+            if sort_by_gas_price {
+                Self::sort_by_gas_price(&mut txns);
+            }
+            Self::check_sender_shares(
+                &txns,
+                sender_share_alert_percent,
+                sender_share_top_senders,
+                &context,
+            );
+            counters::PULL_TXNS_SIZE.observe(txns.len() as f64);
+            if txns.is_empty() {
+                counters::PULL_TXNS_EMPTY_COUNT.inc();
+            }
+            Ok(txns)
+        }
+            .boxed()
+    }
+
+    /// Hash of the canonical form of the request, in which the exclusions are sorted, so that
+    /// requests excluding the same transactions in a different order hash the same. Identifies a
+    /// pull in the logs to correlate it with the response of mempool.
+    fn get_block_request_hash(request: &GetBlockRequest) -> HashValue {
+        let mut exclusions: Vec<_> = request
+            .transactions
+            .iter()
+            .map(|txn| (&txn.sender, txn.sequence_number, &txn.hash))
+            .collect();
+        exclusions.sort();
+        let mut bytes = request.max_block_size.to_le_bytes().to_vec();
+        for (sender, sequence_number, hash) in exclusions {
+            // Fields are length prefixed so that distinct exclusions cannot serialize the same.
+            bytes.extend_from_slice(&(sender.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(sender);
+            bytes.extend_from_slice(&sequence_number.to_le_bytes());
+            bytes.extend_from_slice(&(hash.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(hash);
+        }
+        HashValue::from_sha3_256(&bytes)
+    }
+
+    /// Decode the transactions returned by mempool, invalid transactions are logged and dropped.
+    /// Invalid transactions within the window of `invalid_txn_log` are coalesced into a single
+    /// log line carrying their number. Transactions of more than `max_txn_bytes` are dropped
+    /// without being decoded, decoding could allocate as much as their size claims.
+    /// Blocks of at least `PARALLEL_DECODE_THRESHOLD` transactions are decoded in parallel, the
+    /// order of the transactions is preserved either way.
+    fn decode_transactions(
+        proto_txns: Vec<SignedTransactionProto>,
+        invalid_txn_log: &SecurityLogRateLimiter,
+        clock: &dyn Clock,
+        max_txn_bytes: usize,
+        context: &TxnManagerContext,
+    ) -> Vec<SignedTransaction> {
+        counters::PULL_TXNS_RECEIVED_COUNT.inc_by(proto_txns.len() as i64);
+        let decode = |proto_txn: SignedTransactionProto| {
+            if proto_txn.txn_bytes.len() > max_txn_bytes {
+                counters::PULL_TXNS_OVERSIZED_COUNT.inc();
+                if let Some(count) = invalid_txn_log.record(clock.now()) {
+                    // The txn itself is left out, it may be arbitrarily large.
+                    security_log(SecurityEvent::InvalidTransactionConsensus)
+                        .error(format!(
+                            "Transaction of {} bytes exceeds the max of {} bytes",
+                            proto_txn.txn_bytes.len(),
+                            max_txn_bytes
+                        ))
+                        .data(format!("{} invalid txns since the last log", count))
+                        .data(context)
+                        .log();
+                }
+                return None;
+            }
+            match SignedTransaction::try_from(proto_txn.clone()) {
+                Ok(t) => Some(t),
+                Err(e) => {
+                    counters::PULL_TXNS_DECODE_FAILURES.inc();
+                    if let Some(count) = invalid_txn_log.record(clock.now()) {
+                        security_log(SecurityEvent::InvalidTransactionConsensus)
+                            .error(&e)
+                            .data(&proto_txn)
+                            .data(format!("{} invalid txns since the last log", count))
+                            .data(context)
+                            .log();
+                    }
+                    None
+                }
+            }
+        };
+        if proto_txns.len() < PARALLEL_DECODE_THRESHOLD {
+            proto_txns.into_iter().filter_map(decode).collect()
+        } else {
+            proto_txns.into_par_iter().filter_map(decode).collect()
+        }
+    }
+
+    /// Key identifying a txn of the exclusion list. Replacements of a txn share its sender and
+    /// sequence number, they are only told apart by their hash.
+    fn exclusion_key(txn: &SignedTransaction, exclude_by_hash: bool) -> ExclusionKey {
+        let hash = if exclude_by_hash {
+            Some(Transaction::UserTransaction(txn.clone()).hash())
+        } else {
+            None
+        };
+        (txn.sender(), txn.sequence_number(), hash)
+    }
+
+    /// Whether mempool returned a txn it was asked to exclude. Such a txn is already pending in
+    /// the branch, it is logged and must not be proposed again. Its log line is coalesced by
+    /// `invalid_txn_log` with the ones of the invalid transactions.
+    fn is_exclusion_violation(
+        excluded: &HashSet<ExclusionKey>,
+        txn: &SignedTransaction,
+        exclude_by_hash: bool,
+        invalid_txn_log: &SecurityLogRateLimiter,
+        clock: &dyn Clock,
+        context: &TxnManagerContext,
+    ) -> bool {
+        if !excluded.contains(&Self::exclusion_key(txn, exclude_by_hash)) {
+            return false;
+        }
+        counters::PULL_TXNS_EXCLUSION_VIOLATION_COUNT.inc();
+        if let Some(count) = invalid_txn_log.record(clock.now()) {
+            security_log(SecurityEvent::ExcludedTransactionConsensus)
+                .data(txn.sender())
+                .data(txn.sequence_number())
+                .data(format!("{} invalid txns since the last log", count))
+                .data(context)
+                .log();
+        }
+        true
+    }
+
+    /// Whether `txn` passes `validator`, if any. Rejected txns are counted and dropped from the
+    /// pulled block.
+    fn is_accepted(
+        validator: Option<&TxnValidator>,
+        txn: &SignedTransaction,
+        context: &TxnManagerContext,
+    ) -> bool {
+        match validator {
+            Some(validator) if !validator(txn) => {
+                counters::PULL_TXNS_VALIDATION_REJECTED_COUNT.inc();
+                debug!(
+                    "Dropping pulled txn {}:{} rejected by the txn validator",
+                    txn.sender(),
+                    txn.sequence_number();
+                    "round" => context.round,
+                    "block_id" => context.block_id_field()
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether `txn` was pulled within the window of `recently_pulled`, if enabled, in which case
+    /// it is counted and dropped from the pulled block.
+    fn is_recently_pulled(
+        recently_pulled: Option<&Arc<Mutex<RecentlyPulled>>>,
+        txn: &SignedTransaction,
+    ) -> bool {
+        match recently_pulled {
+            Some(recently_pulled) if recently_pulled.lock().unwrap().record(txn) => {
+                counters::PULL_TXNS_RECENTLY_PULLED_COUNT.inc();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Decode the transactions chunk by chunk, yielding the transactions of every chunk once it is
+    /// decoded.
+    fn decode_transactions_stream(
+        proto_txns: Vec<SignedTransactionProto>,
+        invalid_txn_log: Arc<SecurityLogRateLimiter>,
+        clock: Arc<dyn Clock>,
+        max_txn_bytes: usize,
+        context: TxnManagerContext,
+    ) -> impl Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> {
+        let mut proto_txns = proto_txns.into_iter();
+        let chunks = std::iter::from_fn(move || {
+            let chunk: Vec<_> = proto_txns.by_ref().take(STREAM_DECODE_CHUNK_SIZE).collect();
+            if chunk.is_empty() {
+                None
+            } else {
+                Some(chunk)
+            }
+        });
+        stream::iter(chunks)
+            .map(move |chunk| {
+                let txns = Self::decode_transactions(
+                    chunk,
+                    &invalid_txn_log,
+                    clock.as_ref(),
+                    max_txn_bytes,
+                    &context,
+                );
+                stream::iter(txns.into_iter().map(Ok))
+            })
+            .flatten()
+    }
+
+    /// Order the transactions by gas unit price, highest first. Transactions with the same gas
+    /// unit price are ordered by sender and sequence number, so that the result does not depend
+    /// on the order mempool returned them in.
+    fn sort_by_gas_price(txns: &mut [SignedTransaction]) {
+        txns.sort_by(|a, b| {
+            b.gas_unit_price()
+                .cmp(&a.gas_unit_price())
+                .then_with(|| a.sender().cmp(&b.sender()))
+                .then_with(|| a.sequence_number().cmp(&b.sequence_number()))
+        });
+    }
+
+    /// Number of transactions each sender contributed to the block, the `n` largest contributors
+    /// first. Senders with the same number of transactions are ordered by address, so that the
+    /// result does not depend on the order of the block.
+    fn top_senders(txns: &[SignedTransaction], n: usize) -> Vec<(AccountAddress, usize)> {
+        let mut counts = HashMap::new();
+        for txn in txns {
+            *counts.entry(txn.sender()).or_insert(0) += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Log the top senders of a pulled block when a single sender contributed more than
+    /// `alert_percent` of its transactions, e.g. an account spamming the chain.
+    fn check_sender_shares(
+        txns: &[SignedTransaction],
+        alert_percent: u64,
+        top_senders: usize,
+        context: &TxnManagerContext,
+    ) {
+        if alert_percent == 0 || txns.is_empty() {
+            return;
+        }
+        let senders = Self::top_senders(txns, std::cmp::max(top_senders, 1));
+        let (_, max_count) = senders[0];
+        if max_count as u64 * 100 <= alert_percent * txns.len() as u64 {
+            return;
+        }
+        counters::PULL_TXNS_DOMINANT_SENDER_COUNT.inc();
+        warn!(
+            "A single sender contributed {} of the {} pulled txns, top senders: {:?}",
+            max_count,
+            txns.len(),
+            senders;
+            "round" => context.round,
+            "block_id" => context.block_id_field()
+        );
+    }
+
+    /// Submit the request and return the future, which is fulfilled when the response is received.
+    fn submit_commit_transactions_request(
+        &self,
+        req: CommitTransactionsRequest,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TxnManagerError>> + Send>> {
+        if self.dry_run {
+            debug!(
+                "Dry run: not committing {} txns to mempool",
+                req.transactions.len()
+            );
+            return future::ok(()).boxed();
+        }
+        let mempool = Arc::clone(&self.mempool);
+        let in_flight = self.in_flight.clone();
+        let clock = Arc::clone(&self.clock);
+        let stats = Arc::clone(&self.stats);
+        async move {
+            let _permit = Self::acquire_permit(&in_flight).await;
+            let start = clock.now();
+            match mempool.commit_transactions_async(&req) {
+                Ok(receiver) => {
+                    let response = receiver.await;
+                    let latency = clock.now().duration_since(start);
+                    counters::COMMIT_TXNS_LATENCY.observe_duration(latency);
+                    stats.record_commit_request(latency);
+                    match response {
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(TxnManagerError::from_grpc(e)),
+                    }
+                }
+                Err(e) => Err(TxnManagerError::from_grpc(e)),
+            }
+        }
+            .boxed()
+    }
+}
+
+impl<M: MempoolInterface + 'static> TxnManager for MempoolProxy<M> {
+    type Payload = Vec<SignedTransaction>;
+    type Error = TxnManagerFailure;
+
+    /// The returned future is fulfilled with the vector of SignedTransactions
+    fn pull_txns(
+        &self,
+        max_size: u64,
+        exclude_payloads: Vec<&Self::Payload>,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<Self::Payload, Self::Error>> + Send>> {
+        self.collect_block(
+            self.pull_txns_stream(max_size, exclude_payloads),
+            TxnManagerContext::default(),
+        )
+        .map(|result| result.map_err(TxnManagerError::into_failure))
+        .boxed()
     }
 
     fn commit_txns<'a>(
@@ -135,11 +1853,88 @@ impl TxnManager for MempoolProxy {
         compute_result: &StateComputeResult,
         // Monotonic timestamp_usecs of committed blocks is used to GC expired transactions.
         timestamp_usecs: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
-        counters::COMMITTED_BLOCKS_COUNT.inc();
-        counters::NUM_TXNS_PER_BLOCK.observe(txns.len() as f64);
-        let req =
-            Self::gen_commit_transactions_request(txns.as_slice(), compute_result, timestamp_usecs);
-        self.submit_commit_transactions_request(req)
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        self.commit_txns_with_summary(txns, compute_result, timestamp_usecs)
+            .map(|result| result.map(|_| ()).map_err(TxnManagerError::into_failure))
+            .boxed()
+    }
+
+    fn mempool_size(
+        &self,
+    ) -> Option<Pin<Box<dyn Future<Output = std::result::Result<usize, Self::Error>> + Send>>> {
+        Some(
+            MempoolProxy::mempool_size(self)
+                .map(|result| result.map_err(TxnManagerError::into_failure))
+                .boxed(),
+        )
+    }
+
+    fn flush<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = std::result::Result<(), Self::Error>> + Send + 'a>> {
+        // Queue up behind the last commit like a commit does, so that the commits notified after
+        // the flush still wait for the ones notified before.
+        let (done_sender, done_receiver) = oneshot::channel::<()>();
+        let previous_commit = self.last_commit.lock().unwrap().replace(done_receiver);
+        async move {
+            let _done_sender = done_sender;
+            if let Some(previous_commit) = previous_commit {
+                let _ = previous_commit.await;
+            }
+            Ok(())
+        }
+            .boxed()
+    }
+}
+
+/// MempoolProxy whose operations log the consensus event they are performed for, see
+/// `MempoolProxy::with_context`.
+pub struct ContextualMempoolProxy<'a, M> {
+    proxy: &'a MempoolProxy<M>,
+    context: TxnManagerContext,
+}
+
+impl<'a, M: MempoolInterface + 'static> ContextualMempoolProxy<'a, M> {
+    /// Same as `MempoolProxy::pull_txns`.
+    pub fn pull_txns(
+        &self,
+        max_size: u64,
+        exclude_payloads: Vec<&Vec<SignedTransaction>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = std::result::Result<Vec<SignedTransaction>, TxnManagerError>>
+                + Send,
+        >,
+    > {
+        self.proxy.collect_block(
+            self.pull_txns_stream(max_size, exclude_payloads),
+            self.context,
+        )
+    }
+
+    /// Same as `MempoolProxy::pull_txns_stream`.
+    pub fn pull_txns_stream(
+        &self,
+        max_size: u64,
+        exclude_payloads: Vec<&Vec<SignedTransaction>>,
+    ) -> Pin<Box<dyn Stream<Item = std::result::Result<SignedTransaction, TxnManagerError>> + Send>>
+    {
+        let exclude_by_hash = self.proxy.exclude_by_hash;
+        let exclusions = MempoolProxy::<M>::flatten_exclusions(exclude_payloads, exclude_by_hash);
+        self.proxy
+            .pull_txns_stream_excluding(max_size, exclusions, exclude_by_hash, self.context)
+    }
+
+    /// Same as `MempoolProxy::commit_txns_with_summary`.
+    pub fn commit_txns_with_summary(
+        &self,
+        txns: &[SignedTransaction],
+        compute_result: &StateComputeResult,
+        timestamp_usecs: u64,
+    ) -> Pin<
+        Box<dyn Future<Output = std::result::Result<CommitSummary, TxnManagerError>> + Send + 'a>,
+    > {
+        self.proxy
+            .commit_txns_with_context(txns, compute_result, timestamp_usecs, self.context)
     }
 }
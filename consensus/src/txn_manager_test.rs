@@ -0,0 +1,1802 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    CancellableReceiver, Clock, CommitDedupGuard, CommitReservation, CommitSummary, GrpcReceiver,
+    MempoolInterface, MempoolProxy, MempoolResponseFuture, ProxyStats, PullSizeController,
+    SecurityLogRateLimiter, SystemClock, TxnManagerContext, TxnManagerError, TxnValidator,
+    PULL_ALL_MAX_PAGES,
+};
+use crate::{counters, state_replication::TxnManager};
+use executor::StateComputeResult;
+use futures::{channel::oneshot, executor::block_on, future, Future, FutureExt, StreamExt};
+use futures_01::{Async, Future as Future01, Poll as Poll01};
+use grpcio::CallOption;
+use libra_config::config::{AdaptivePullConfig, MempoolProxyConfig};
+use libra_crypto::{ed25519::compat, hash::CryptoHash, HashValue};
+use libra_mempool::proto::mempool::{
+    CommitTransactionsRequest, CommitTransactionsResponse, GetBlockRequest, GetBlockResponse,
+    HealthCheckRequest, HealthCheckResponse, TransactionExclusion,
+};
+use libra_types::{
+    account_address::AccountAddress,
+    proto::types::{SignedTransaction as SignedTransactionProto, SignedTransactionsBlock},
+    transaction::{RawTransaction, Script, SignedTransaction, Transaction, TransactionStatus},
+    vm_error::{StatusCode, VMStatus},
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+type TestMempoolProxy = MempoolProxy<MockMempool>;
+
+/// Mempool answering every pull with the same block, until it is replaced, and recording the
+/// requests it receives.
+#[derive(Default)]
+struct MockMempool {
+    block: Mutex<Vec<SignedTransaction>>,
+    get_block_requests: Mutex<Vec<GetBlockRequest>>,
+    commit_requests: Mutex<Vec<CommitTransactionsRequest>>,
+}
+
+impl MockMempool {
+    fn new(block: Vec<SignedTransaction>) -> Self {
+        Self {
+            block: Mutex::new(block),
+            ..Self::default()
+        }
+    }
+
+    fn set_block(&self, block: Vec<SignedTransaction>) {
+        *self.block.lock().unwrap() = block;
+    }
+}
+
+impl MempoolInterface for MockMempool {
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        self.get_block_requests.lock().unwrap().push(req.clone());
+        let mut block = SignedTransactionsBlock::default();
+        block.transactions = self
+            .block
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect();
+        let mut response = GetBlockResponse::default();
+        response.block = Some(block);
+        Ok(future::ok(response).boxed())
+    }
+
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        self.commit_requests.lock().unwrap().push(req.clone());
+        Ok(future::ok(CommitTransactionsResponse::default()).boxed())
+    }
+
+    fn health_check_async_opt(
+        &self,
+        _req: &HealthCheckRequest,
+        _opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        let mut response = HealthCheckResponse::default();
+        response.is_healthy = true;
+        Ok(future::ok(response).boxed())
+    }
+}
+
+/// Mempool holding the responses to its pull and commit requests until they are released.
+#[derive(Default)]
+struct GatedMempool {
+    inner: MockMempool,
+    gates: Mutex<Vec<oneshot::Sender<()>>>,
+}
+
+impl GatedMempool {
+    fn gate<T: Send + 'static>(
+        &self,
+        response: MempoolResponseFuture<T>,
+    ) -> grpcio::Result<MempoolResponseFuture<T>> {
+        let (sender, receiver) = oneshot::channel();
+        self.gates.lock().unwrap().push(sender);
+        Ok(async move {
+            let _ = receiver.await;
+            response.await
+        }
+            .boxed())
+    }
+
+    fn release(&self) {
+        for gate in self.gates.lock().unwrap().drain(..) {
+            let _ = gate.send(());
+        }
+    }
+}
+
+impl MempoolInterface for GatedMempool {
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        self.gate(self.inner.get_block_async(req)?)
+    }
+
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        self.gate(self.inner.commit_transactions_async(req)?)
+    }
+
+    fn health_check_async_opt(
+        &self,
+        req: &HealthCheckRequest,
+        opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        self.inner.health_check_async_opt(req, opt)
+    }
+}
+
+/// Mempool returning its pages in order to successive pulls, truncated to their max size, then
+/// empty blocks once they are all pulled.
+#[derive(Default)]
+struct PagedMempool {
+    inner: MockMempool,
+    pages: Mutex<VecDeque<Vec<SignedTransaction>>>,
+}
+
+impl MempoolInterface for PagedMempool {
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        let mut page = self.pages.lock().unwrap().pop_front().unwrap_or_default();
+        page.truncate(req.max_block_size as usize);
+        self.inner.set_block(page);
+        self.inner.get_block_async(req)
+    }
+
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        self.inner.commit_transactions_async(req)
+    }
+
+    fn health_check_async_opt(
+        &self,
+        req: &HealthCheckRequest,
+        opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        self.inner.health_check_async_opt(req, opt)
+    }
+}
+
+/// Clock only advanced by hand and by the sleeps of the proxy, which complete right away.
+struct MockClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+    sleeps: Mutex<Vec<Duration>>,
+}
+
+impl MockClock {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::from_secs(0)),
+            sleeps: Mutex::new(vec![]),
+        }
+    }
+
+    fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.advance(duration);
+        self.sleeps.lock().unwrap().push(duration);
+        future::ready(()).boxed()
+    }
+}
+
+/// Mempool unavailable for its first pulls, then answering every pull after `latency` has
+/// elapsed on `clock`.
+struct SlowMempool {
+    inner: MockMempool,
+    clock: Arc<MockClock>,
+    latency: Duration,
+    failures: Mutex<u32>,
+}
+
+impl MempoolInterface for SlowMempool {
+    fn get_block_async(
+        &self,
+        req: &GetBlockRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<GetBlockResponse>> {
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            return Err(grpcio::Error::RpcFailure(grpcio::RpcStatus::new(
+                grpcio::RpcStatusCode::UNAVAILABLE,
+                None,
+            )));
+        }
+        self.clock.advance(self.latency);
+        self.inner.get_block_async(req)
+    }
+
+    fn commit_transactions_async(
+        &self,
+        req: &CommitTransactionsRequest,
+    ) -> grpcio::Result<MempoolResponseFuture<CommitTransactionsResponse>> {
+        self.inner.commit_transactions_async(req)
+    }
+
+    fn health_check_async_opt(
+        &self,
+        req: &HealthCheckRequest,
+        opt: CallOption,
+    ) -> grpcio::Result<MempoolResponseFuture<HealthCheckResponse>> {
+        self.inner.health_check_async_opt(req, opt)
+    }
+}
+
+fn create_signed_txn(sender: AccountAddress, sequence_number: u64) -> SignedTransaction {
+    create_signed_txn_with_gas_price(sender, sequence_number, 0)
+}
+
+fn create_signed_txn_with_gas_price(
+    sender: AccountAddress,
+    sequence_number: u64,
+    gas_unit_price: u64,
+) -> SignedTransaction {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    RawTransaction::new_script(
+        sender,
+        sequence_number,
+        Script::new(vec![], vec![]),
+        0,
+        gas_unit_price,
+        Duration::from_secs(0),
+    )
+    .sign(&private_key, public_key)
+    .expect("Failed to sign txn")
+    .into_inner()
+}
+
+fn keep() -> TransactionStatus {
+    TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED))
+}
+
+fn discard() -> TransactionStatus {
+    TransactionStatus::Discard(VMStatus::new(StatusCode::SEQUENCE_NUMBER_TOO_OLD))
+}
+
+fn compute_result(compute_status: Vec<TransactionStatus>) -> StateComputeResult {
+    StateComputeResult {
+        compute_status,
+        ..StateComputeResult::default()
+    }
+}
+
+#[test]
+fn test_commit_request_length_mismatch() {
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+    // A prologue status followed by a status for the first txn only.
+    let result = compute_result(vec![keep(), keep()]);
+
+    let error = TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 0, 1)
+        .expect_err("Mismatched txns and statuses must be rejected");
+    assert_eq!(
+        error.to_string(),
+        "Cannot commit 2 transactions with 1 compute statuses"
+    );
+}
+
+#[test]
+fn test_commit_request_without_prologue() {
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+
+    let result = compute_result(vec![keep(), keep()]);
+    let (req, summary) = TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 0, 0)
+        .expect("Every txn has a compute status");
+    assert_eq!(req.transactions.len(), 2);
+    assert_eq!(summary.kept, 2);
+
+    let result = compute_result(vec![keep(), keep(), keep()]);
+    TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 0, 0)
+        .expect_err("Extra statuses must be rejected when there is no prologue");
+}
+
+#[test]
+fn test_commit_request_with_prologue() {
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+
+    let result = compute_result(vec![keep(), keep(), keep()]);
+    let (req, summary) = TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 0, 1)
+        .expect("Every txn has a compute status after the prologue");
+    assert_eq!(req.transactions.len(), 2);
+    assert_eq!(summary.kept, 2);
+}
+
+#[test]
+fn test_commit_request_status_alignment() {
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+    // The prologue is kept, the first txn is discarded and the second one is kept.
+    let result = compute_result(vec![keep(), discard(), keep()]);
+
+    let (req, summary) = TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 0, 1)
+        .expect("Every txn has a compute status after the prologue");
+    let rejected: Vec<_> = req
+        .transactions
+        .iter()
+        .map(|txn| (txn.sequence_number, txn.is_rejected))
+        .collect();
+    assert_eq!(rejected, vec![(0, true), (1, false)]);
+    assert_eq!(summary.kept, 1);
+    assert_eq!(summary.discarded, 1);
+}
+
+#[test]
+fn test_commit_request_empty_compute_status() {
+    let error =
+        TestMempoolProxy::gen_commit_transactions_request(&[], &compute_result(vec![]), 0, 1)
+            .expect_err("Missing prologue status must be rejected");
+    assert_eq!(
+        error.to_string(),
+        "Cannot skip 1 prologue statuses out of 0 compute statuses"
+    );
+}
+
+#[test]
+fn test_pull_txns_request() {
+    let sender = AccountAddress::random();
+    let block = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let mempool = Arc::new(MockMempool::new(block.clone()));
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+
+    // The second txn is pending in both payloads and must be excluded once.
+    let first = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+    let second = vec![create_signed_txn(sender, 1), create_signed_txn(sender, 2)];
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(10, vec![&first, &second]))
+        .expect("Failed to pull txns");
+    assert_eq!(pulled, block);
+
+    let requests = mempool.get_block_requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].max_block_size, 10);
+    let excluded: Vec<_> = requests[0]
+        .transactions
+        .iter()
+        .map(|txn| (txn.sender.clone(), txn.sequence_number))
+        .collect();
+    assert!(requests[0]
+        .transactions
+        .iter()
+        .all(|txn| txn.hash.is_empty()));
+    let sender = sender.as_ref().to_vec();
+    assert_eq!(
+        excluded,
+        vec![(sender.clone(), 0), (sender.clone(), 1), (sender, 2)]
+    );
+}
+
+#[test]
+fn test_pull_txns_clamp_max_size() {
+    let mempool = Arc::new(MockMempool::default());
+    let mut config = MempoolProxyConfig::default();
+    config.max_block_size_limit = 5;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(proxy.pull_txns(5, vec![]))
+        .expect("Failed to pull txns");
+    runtime
+        .block_on(proxy.pull_txns(1000, vec![]))
+        .expect("Failed to pull txns");
+
+    let requests = mempool.get_block_requests.lock().unwrap();
+    let sizes: Vec<_> = requests.iter().map(|req| req.max_block_size).collect();
+    assert_eq!(sizes, vec![5, 5]);
+}
+
+#[test]
+fn test_pull_txns_zero_max_size() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+
+    let mut runtime = Runtime::new().unwrap();
+    let error = runtime
+        .block_on(proxy.pull_txns(0, vec![]))
+        .expect_err("An empty pull must be rejected");
+    assert_eq!(
+        error.to_string(),
+        "Cannot pull a block of at most 0 transactions from mempool"
+    );
+    assert!(mempool.get_block_requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_commit_txns_request() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+
+    let sender = AccountAddress::random();
+    let txns = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+    let result = compute_result(vec![keep(), keep(), keep()]);
+    block_on(proxy.commit_txns(&txns, &result, 42)).expect("Failed to commit txns");
+
+    let requests = mempool.commit_requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].block_timestamp_usecs, 42);
+    let committed: Vec<_> = requests[0]
+        .transactions
+        .iter()
+        .map(|txn| (txn.sender.clone(), txn.sequence_number, txn.is_rejected))
+        .collect();
+    let sender = sender.as_ref().to_vec();
+    assert_eq!(
+        committed,
+        vec![(sender.clone(), 0, false), (sender, 1, false)]
+    );
+}
+
+#[test]
+fn test_pull_txns_sorted_by_gas_price() {
+    let sender_a = AccountAddress::new([1; 32]);
+    let sender_b = AccountAddress::new([2; 32]);
+    let block = vec![
+        create_signed_txn_with_gas_price(sender_b, 0, 1),
+        create_signed_txn_with_gas_price(sender_a, 1, 1),
+        create_signed_txn_with_gas_price(sender_b, 1, 5),
+        create_signed_txn_with_gas_price(sender_a, 0, 1),
+    ];
+    let mempool = Arc::new(MockMempool::new(block.clone()));
+    let mut config = MempoolProxyConfig::default();
+    config.sort_by_gas_price = true;
+    let proxy = MempoolProxy::new(mempool, &config);
+
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(10, vec![]))
+        .expect("Failed to pull txns");
+    // Equal gas prices are ordered by sender and sequence number, not by mempool order.
+    assert_eq!(
+        pulled,
+        vec![
+            block[2].clone(),
+            block[3].clone(),
+            block[1].clone(),
+            block[0].clone()
+        ]
+    );
+}
+
+#[test]
+fn test_mempool_size() {
+    let block = vec![
+        create_signed_txn(AccountAddress::random(), 0),
+        create_signed_txn(AccountAddress::random(), 0),
+    ];
+    let mempool = Arc::new(MockMempool::new(block));
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+
+    let mut runtime = Runtime::new().unwrap();
+    let size = runtime
+        .block_on(proxy.mempool_size())
+        .expect("Failed to estimate mempool size");
+    assert_eq!(size, 2);
+    let requests = mempool.get_block_requests.lock().unwrap();
+    assert!(requests[0].transactions.is_empty());
+}
+
+#[test]
+fn test_pull_txns_exclude_by_hash() {
+    let sender = AccountAddress::random();
+    let mempool = Arc::new(MockMempool::default());
+    let mut config = MempoolProxyConfig::default();
+    config.exclude_by_hash = true;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+
+    // Two replacements of the same txn are both excluded, each with its own hash.
+    let first = vec![create_signed_txn_with_gas_price(sender, 0, 1)];
+    let second = vec![create_signed_txn_with_gas_price(sender, 0, 2)];
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(proxy.pull_txns(10, vec![&first, &second, &first]))
+        .expect("Failed to pull txns");
+
+    let requests = mempool.get_block_requests.lock().unwrap();
+    let hashes: Vec<_> = requests[0]
+        .transactions
+        .iter()
+        .map(|txn| txn.hash.clone())
+        .collect();
+    let expected: Vec<_> = vec![&first[0], &second[0]]
+        .into_iter()
+        .map(|txn| Transaction::UserTransaction(txn.clone()).hash().to_vec())
+        .collect();
+    assert_eq!(hashes, expected);
+}
+
+#[test]
+fn test_commit_txns_ordering() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+
+    let sender = AccountAddress::random();
+    let first_txns = vec![create_signed_txn(sender, 0)];
+    let second_txns = vec![create_signed_txn(sender, 1)];
+    let result = compute_result(vec![keep(), keep()]);
+    let first = proxy.commit_txns(&first_txns, &result, 1);
+    let second = proxy.commit_txns(&second_txns, &result, 2);
+    // The second commit is polled first, but must only be sent after the first one.
+    let (second, first) = block_on(future::join(second, first));
+    first.expect("Failed to commit first block");
+    second.expect("Failed to commit second block");
+
+    let timestamps: Vec<_> = mempool
+        .commit_requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|req| req.block_timestamp_usecs)
+        .collect();
+    assert_eq!(timestamps, vec![1, 2]);
+}
+
+#[test]
+fn test_pull_txns_stream() {
+    let block: Vec<_> = (0..3)
+        .map(|i| create_signed_txn(AccountAddress::random(), i))
+        .collect();
+    let mempool = Arc::new(MockMempool::new(block.clone()));
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+
+    let mut runtime = Runtime::new().unwrap();
+    let pulled: Vec<_> = runtime.block_on(proxy.pull_txns_stream(10, vec![]).collect::<Vec<_>>());
+    let pulled: Vec<_> = pulled
+        .into_iter()
+        .map(|txn| txn.expect("Failed to pull txn"))
+        .collect();
+    assert_eq!(pulled, block);
+
+    let pulled: Vec<_> = runtime.block_on(proxy.pull_txns_stream(0, vec![]).collect::<Vec<_>>());
+    assert_eq!(pulled.len(), 1);
+    assert!(pulled[0].is_err());
+}
+
+#[test]
+fn test_discard_reason() {
+    assert_eq!(
+        TestMempoolProxy::discard_reason(&VMStatus::new(StatusCode::SEQUENCE_NUMBER_TOO_OLD)),
+        "sequence_number_too_old"
+    );
+    assert_eq!(
+        TestMempoolProxy::discard_reason(&VMStatus::new(StatusCode::OUT_OF_GAS)),
+        "other"
+    );
+}
+
+#[test]
+fn test_commit_txns_timestamp_regression() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let txns = vec![];
+    let result = compute_result(vec![keep()]);
+
+    block_on(proxy.commit_txns(&txns, &result, 2)).expect("Failed to commit block");
+    let other_txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let other_result = compute_result(vec![keep(), keep()]);
+    block_on(proxy.commit_txns(&other_txns, &other_result, 2))
+        .expect("Equal timestamps are allowed");
+    let error = block_on(proxy.commit_txns(&txns, &result, 1))
+        .expect_err("A regressing timestamp must be rejected");
+    assert_eq!(error.to_string(), "Commit timestamp 1 regresses from 2");
+    match error.into_inner().downcast::<TxnManagerError>() {
+        Ok(TxnManagerError::InvalidRequest(_)) => (),
+        e => panic!("Expected an invalid request error, got {:?}", e),
+    }
+    assert_eq!(mempool.commit_requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_commit_txns_timestamp_failed_commit() {
+    let mempool = Arc::new(GatedMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+
+    // The commit is dropped before mempool answers, its timestamp is not the last one.
+    let mut failed = proxy.commit_txns(&txns, &result, 5);
+    assert!((&mut failed).now_or_never().is_none());
+    drop(failed);
+
+    let other_txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let mut commit = proxy.commit_txns(&other_txns, &result, 3);
+    assert!((&mut commit).now_or_never().is_none());
+    mempool.release();
+    block_on(commit).expect("A failed commit must not advance the timestamp");
+    let error = block_on(proxy.commit_txns(&txns, &result, 2))
+        .expect_err("A regressing timestamp must be rejected");
+    assert_eq!(error.to_string(), "Commit timestamp 2 regresses from 3");
+}
+
+#[test]
+fn test_commit_txns_timestamp_floor() {
+    let mempool = Arc::new(MockMempool::default());
+    let mut config = MempoolProxyConfig::default();
+    config.min_commit_timestamp_usecs = 1_546_300_800_000_000;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+
+    let error = block_on(proxy.commit_txns(&txns, &result, 1))
+        .expect_err("A timestamp below the floor must be rejected");
+    assert_eq!(
+        error.to_string(),
+        "Commit timestamp 1 is below the floor 1546300800000000"
+    );
+    match error.into_inner().downcast::<TxnManagerError>() {
+        Ok(TxnManagerError::InvalidRequest(_)) => (),
+        e => panic!("Expected an invalid request error, got {:?}", e),
+    }
+    assert!(mempool.commit_requests.lock().unwrap().is_empty());
+
+    // The rejected timestamp is not the last one, later commits are checked against the floor.
+    block_on(proxy.commit_txns(&txns, &result, config.min_commit_timestamp_usecs))
+        .expect("Failed to commit block");
+    assert_eq!(mempool.commit_requests.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_commit_txns_dry_run() {
+    let mempool = Arc::new(MockMempool::default());
+    let mut config = MempoolProxyConfig::default();
+    config.dry_run = true;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+    let summary =
+        block_on(proxy.commit_txns_with_summary(&txns, &result, 1)).expect("Failed to commit txns");
+    assert_eq!(summary.kept, 1);
+    assert!(mempool.commit_requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_pull_txns_exclusion_violation() {
+    let pending = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let new_txn = create_signed_txn(AccountAddress::random(), 0);
+    // Mempool ignores the exclusion and returns the pending txn again.
+    let mempool = Arc::new(MockMempool::new(vec![pending[0].clone(), new_txn.clone()]));
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(10, vec![&pending]))
+        .expect("Failed to pull txns");
+    assert_eq!(pulled, vec![new_txn]);
+}
+
+#[test]
+fn test_txn_manager_counters() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+    let txns = vec![
+        create_signed_txn(AccountAddress::random(), 0),
+        create_signed_txn(AccountAddress::random(), 0),
+    ];
+    // No other test discards transactions for this reason.
+    let rejected = TransactionStatus::Discard(VMStatus::new(StatusCode::REJECTED_WRITE_SET));
+    let result = compute_result(vec![keep(), keep(), rejected]);
+
+    let baseline = counters::snapshot();
+    block_on(proxy.commit_txns(&txns, &result, 1)).expect("Failed to commit txns");
+    let delta = counters::snapshot().delta_since(&baseline);
+    // Other tests may commit concurrently, the increments of the shared labels are lower bounds.
+    assert!(delta.committed_blocks >= 1);
+    assert!(delta.committed_txns_success >= 1);
+    assert!(delta.committed_txns_failed >= 1);
+    assert_eq!(delta.discarded_txns_by_reason["rejected_write_set"], 1);
+
+    // A snapshot taken after the commit has no increments since itself.
+    let after = counters::snapshot();
+    let delta = after.delta_since(&after);
+    assert_eq!(delta.committed_blocks, 0);
+    assert!(delta.discarded_txns_by_reason.is_empty());
+}
+
+#[test]
+fn test_render_counters() {
+    const COMMITTED_BLOCKS: &str = "libra_consensus_committed_blocks_count ";
+    let committed_blocks = |rendered: &str| -> f64 {
+        rendered
+            .lines()
+            .find(|line| line.starts_with(COMMITTED_BLOCKS))
+            .expect("The committed blocks counter is not rendered")[COMMITTED_BLOCKS.len()..]
+            .parse()
+            .unwrap()
+    };
+    let baseline = committed_blocks(&counters::render());
+
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    block_on(proxy.commit_txns(&txns, &compute_result(vec![keep(), keep()]), 1))
+        .expect("Failed to commit txns");
+
+    let rendered = counters::render();
+    assert!(rendered.contains("# TYPE libra_consensus_committed_blocks_count counter"));
+    assert!(committed_blocks(&rendered) > baseline);
+    assert!(rendered
+        .lines()
+        .all(|line| line.is_empty() || line.contains("libra_consensus_")));
+}
+
+#[test]
+fn test_bytes_per_block() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+    let txns = vec![
+        create_signed_txn(AccountAddress::random(), 0),
+        create_signed_txn(AccountAddress::random(), 1),
+    ];
+    let expected: usize = txns
+        .iter()
+        .map(|txn| lcs::to_bytes(txn).unwrap().len())
+        .sum();
+    assert_eq!(TestMempoolProxy::block_bytes(&txns), expected);
+
+    let baseline = counters::BYTES_PER_BLOCK.get_sample_sum();
+    block_on(proxy.commit_txns(&txns, &compute_result(vec![keep(), keep(), keep()]), 1))
+        .expect("Failed to commit txns");
+    // Other tests may commit concurrently, the increment is a lower bound.
+    assert!(counters::BYTES_PER_BLOCK.get_sample_sum() - baseline >= expected as f64);
+}
+
+#[test]
+fn test_bytes_per_block_sampling() {
+    let mut config = MempoolProxyConfig::default();
+    config.bytes_per_block_sample_interval = 3;
+    let proxy = MempoolProxy::new(Arc::new(MockMempool::default()), &config);
+    let sampled: Vec<_> = (0..6).map(|_| proxy.sample_block_bytes()).collect();
+    assert_eq!(sampled, vec![true, false, false, true, false, false]);
+
+    config.bytes_per_block_sample_interval = 0;
+    let proxy = MempoolProxy::new(Arc::new(MockMempool::default()), &config);
+    assert!(!proxy.sample_block_bytes());
+}
+
+#[test]
+fn test_flush_waits_for_commits() {
+    let mempool = Arc::new(GatedMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+
+    let mut first = proxy.commit_txns(&txns, &result, 1);
+    let mut second = proxy.commit_txns(&txns, &result, 2);
+    let mut flush = TxnManager::flush(&proxy);
+    assert!((&mut first).now_or_never().is_none());
+    assert!((&mut second).now_or_never().is_none());
+    assert!((&mut flush).now_or_never().is_none());
+
+    mempool.release();
+    block_on(first).expect("Failed to commit txns");
+    // The second commit is only sent to mempool once the first one is done.
+    assert!((&mut flush).now_or_never().is_none());
+    assert!((&mut second).now_or_never().is_none());
+    mempool.release();
+    block_on(second).expect("Failed to commit txns");
+    block_on(flush).expect("Failed to flush");
+    assert_eq!(mempool.inner.commit_requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_pull_size_controller() {
+    let mut controller = PullSizeController::new(&AdaptivePullConfig {
+        enabled: true,
+        slow_latency_ms: 100,
+        min_size: 100,
+        max_size: 1000,
+    });
+    assert_eq!(controller.effective_max_size(), 1000);
+
+    let slow = Duration::from_millis(500);
+    let fast = Duration::from_millis(10);
+    controller.record(slow);
+    assert_eq!(controller.effective_max_size(), 500);
+    for _ in 0..10 {
+        controller.record(slow);
+    }
+    assert_eq!(controller.effective_max_size(), 100);
+
+    // The average latency goes back under the threshold after a few fast pulls.
+    controller.record(fast);
+    controller.record(fast);
+    assert_eq!(controller.effective_max_size(), 100);
+    controller.record(fast);
+    assert_eq!(controller.effective_max_size(), 110);
+    for _ in 0..100 {
+        controller.record(fast);
+    }
+    assert_eq!(controller.effective_max_size(), 1000);
+}
+
+#[test]
+fn test_pull_txns_adaptive_size() {
+    let mempool = Arc::new(MockMempool::default());
+    let mut config = MempoolProxyConfig::default();
+    config.adaptive_pull = AdaptivePullConfig {
+        enabled: true,
+        slow_latency_ms: 1000,
+        min_size: 10,
+        max_size: 50,
+    };
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(proxy.pull_txns(100, vec![]))
+        .expect("Failed to pull txns");
+    runtime
+        .block_on(proxy.pull_txns(20, vec![]))
+        .expect("Failed to pull txns");
+    let sizes: Vec<_> = mempool
+        .get_block_requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|req| req.max_block_size)
+        .collect();
+    assert_eq!(sizes, vec![50, 20]);
+}
+
+#[test]
+fn test_txn_manager_error_from_grpc() {
+    let rpc_failure =
+        |code| grpcio::Error::RpcFailure(grpcio::RpcStatus::new(code, Some("failed".to_string())));
+
+    match TxnManagerError::from_grpc(rpc_failure(grpcio::RpcStatusCode::DEADLINE_EXCEEDED)) {
+        TxnManagerError::Timeout(_) => (),
+        e => panic!("Expected a timeout, got {:?}", e),
+    }
+    match TxnManagerError::from_grpc(rpc_failure(grpcio::RpcStatusCode::UNIMPLEMENTED)) {
+        TxnManagerError::ProtocolMismatch(_) => (),
+        e => panic!("Expected a protocol mismatch, got {:?}", e),
+    }
+    match TxnManagerError::from_grpc(rpc_failure(grpcio::RpcStatusCode::UNAVAILABLE)) {
+        TxnManagerError::Connection(_) => (),
+        e => panic!("Expected a connection error, got {:?}", e),
+    }
+    match TxnManagerError::from_grpc(rpc_failure(grpcio::RpcStatusCode::INTERNAL)) {
+        TxnManagerError::MempoolRejected(_) => (),
+        e => panic!("Expected a rejection, got {:?}", e),
+    }
+    match TxnManagerError::from_grpc(grpcio::Error::Codec("bad message".to_string().into())) {
+        TxnManagerError::Decode(_) => (),
+        e => panic!("Expected a decode error, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_top_senders() {
+    let sender_a = AccountAddress::new([1; 32]);
+    let sender_b = AccountAddress::new([2; 32]);
+    let sender_c = AccountAddress::new([3; 32]);
+    let txns = vec![
+        create_signed_txn(sender_c, 0),
+        create_signed_txn(sender_b, 0),
+        create_signed_txn(sender_c, 1),
+        create_signed_txn(sender_a, 0),
+    ];
+
+    // Equal counts are ordered by sender, not by block order.
+    assert_eq!(
+        TestMempoolProxy::top_senders(&txns, 2),
+        vec![(sender_c, 2), (sender_a, 1)]
+    );
+    assert_eq!(TestMempoolProxy::top_senders(&txns, 10).len(), 3);
+    assert!(TestMempoolProxy::top_senders(&[], 10).is_empty());
+}
+
+#[test]
+fn test_pull_txns_dominant_sender() {
+    let sender_a = AccountAddress::new([1; 32]);
+    let sender_b = AccountAddress::new([2; 32]);
+    let block = vec![
+        create_signed_txn(sender_a, 0),
+        create_signed_txn(sender_a, 1),
+        create_signed_txn(sender_b, 0),
+    ];
+    let mempool = Arc::new(MockMempool::new(block.clone()));
+    let mut config = MempoolProxyConfig::default();
+    config.sender_share_alert_percent = 50;
+    let proxy = MempoolProxy::new(mempool, &config);
+
+    let before = counters::PULL_TXNS_DOMINANT_SENDER_COUNT.get();
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(10, vec![]))
+        .expect("Failed to pull txns");
+    // The accounting only observes the block, it is returned untouched.
+    assert_eq!(pulled, block);
+    assert!(counters::PULL_TXNS_DOMINANT_SENDER_COUNT.get() > before);
+}
+
+#[test]
+fn test_get_block_request_hash() {
+    let exclusion = |sender: u8, sequence_number| {
+        let mut exclusion = TransactionExclusion::default();
+        exclusion.sender = vec![sender; 32];
+        exclusion.sequence_number = sequence_number;
+        exclusion
+    };
+    let request = |max_block_size, transactions| {
+        let mut request = GetBlockRequest::default();
+        request.max_block_size = max_block_size;
+        request.transactions = transactions;
+        request
+    };
+
+    let hash = TestMempoolProxy::get_block_request_hash(&request(
+        10,
+        vec![exclusion(1, 0), exclusion(2, 0), exclusion(1, 1)],
+    ));
+    // The order of the exclusions does not matter.
+    assert_eq!(
+        TestMempoolProxy::get_block_request_hash(&request(
+            10,
+            vec![exclusion(1, 1), exclusion(1, 0), exclusion(2, 0)],
+        )),
+        hash
+    );
+    assert_ne!(
+        TestMempoolProxy::get_block_request_hash(&request(
+            11,
+            vec![exclusion(1, 0), exclusion(2, 0), exclusion(1, 1)],
+        )),
+        hash
+    );
+    assert_ne!(
+        TestMempoolProxy::get_block_request_hash(&request(
+            10,
+            vec![exclusion(1, 0), exclusion(2, 0)]
+        )),
+        hash
+    );
+}
+
+#[test]
+fn test_split_commit_transactions_request() {
+    let sender = AccountAddress::random();
+    let txns: Vec<_> = (0..5).map(|i| create_signed_txn(sender, i)).collect();
+    let result = compute_result(vec![keep(); 6]);
+    let (req, _) = TestMempoolProxy::gen_commit_transactions_request(&txns, &result, 7, 1)
+        .expect("Failed to generate commit request");
+
+    let batches = TestMempoolProxy::split_commit_transactions_request(req.clone(), 2);
+    let sizes: Vec<_> = batches
+        .iter()
+        .map(|batch| batch.transactions.len())
+        .collect();
+    assert_eq!(sizes, vec![2, 2, 1]);
+    assert!(batches.iter().all(|batch| batch.block_timestamp_usecs == 7));
+    let sequence_numbers: Vec<_> = batches
+        .iter()
+        .flat_map(|batch| batch.transactions.iter().map(|txn| txn.sequence_number))
+        .collect();
+    assert_eq!(sequence_numbers, vec![0, 1, 2, 3, 4]);
+
+    assert_eq!(
+        TestMempoolProxy::split_commit_transactions_request(req.clone(), 5),
+        vec![req.clone()]
+    );
+    // A batch size of 0 is treated as 1.
+    assert_eq!(
+        TestMempoolProxy::split_commit_transactions_request(req, 0).len(),
+        5
+    );
+}
+
+#[test]
+fn test_invalid_txn_log_rate_limited() {
+    let window = Duration::from_secs(3600);
+    let invalid_txn_log = SecurityLogRateLimiter::new(window);
+    let proto_txns = vec![SignedTransactionProto::default(); 1000];
+
+    assert!(TestMempoolProxy::decode_transactions(
+        proto_txns,
+        &invalid_txn_log,
+        &SystemClock,
+        1 << 20,
+        &TxnManagerContext::default()
+    )
+    .is_empty());
+    // A single line was logged, the other invalid txns are coalesced into the next one.
+    let (window_start, coalesced) = *invalid_txn_log.state.lock().unwrap();
+    assert_eq!(coalesced, 999);
+    let window_start = window_start.expect("An invalid txn was logged");
+    assert_eq!(invalid_txn_log.record(window_start + window / 2), None);
+    assert_eq!(invalid_txn_log.record(window_start + window), Some(1001));
+    assert_eq!(invalid_txn_log.record(window_start + window), None);
+
+    // Without a window every invalid txn is logged.
+    let unlimited = SecurityLogRateLimiter::new(Duration::from_secs(0));
+    let now = Instant::now();
+    assert!((0..10).all(|_| unlimited.record(now) == Some(1)));
+}
+
+#[test]
+fn test_exclusion_violation_log_rate_limited() {
+    let invalid_txn_log = SecurityLogRateLimiter::new(Duration::from_secs(3600));
+    let txns: Vec<_> = (0..100)
+        .map(|_| create_signed_txn(AccountAddress::random(), 0))
+        .collect();
+    let excluded: HashSet<_> = txns
+        .iter()
+        .map(|txn| TestMempoolProxy::exclusion_key(txn, false))
+        .collect();
+
+    assert!(txns
+        .iter()
+        .all(|txn| TestMempoolProxy::is_exclusion_violation(
+            &excluded,
+            txn,
+            false,
+            &invalid_txn_log,
+            &SystemClock,
+            &TxnManagerContext::default()
+        )));
+    // A single line was logged, the other violations are coalesced into the next one.
+    assert_eq!(invalid_txn_log.state.lock().unwrap().1, 99);
+}
+
+#[test]
+fn test_oversized_txn_rejected() {
+    let txn = create_signed_txn(AccountAddress::random(), 0);
+    let proto_txn = SignedTransactionProto::from(txn.clone());
+    let txn_bytes = proto_txn.txn_bytes.len();
+    let invalid_txn_log = SecurityLogRateLimiter::new(Duration::from_secs(0));
+
+    let before = counters::PULL_TXNS_OVERSIZED_COUNT.get();
+    // The txn is valid, it is only dropped for its size.
+    assert!(TestMempoolProxy::decode_transactions(
+        vec![proto_txn.clone()],
+        &invalid_txn_log,
+        &SystemClock,
+        txn_bytes - 1,
+        &TxnManagerContext::default()
+    )
+    .is_empty());
+    assert!(counters::PULL_TXNS_OVERSIZED_COUNT.get() > before);
+    assert_eq!(
+        TestMempoolProxy::decode_transactions(
+            vec![proto_txn],
+            &invalid_txn_log,
+            &SystemClock,
+            txn_bytes,
+            &TxnManagerContext::default()
+        ),
+        vec![txn]
+    );
+}
+
+#[test]
+fn test_decode_failure_counters() {
+    let txns = vec![
+        create_signed_txn(AccountAddress::random(), 0),
+        create_signed_txn(AccountAddress::random(), 0),
+    ];
+    let proto_txns = vec![
+        SignedTransactionProto::from(txns[0].clone()),
+        SignedTransactionProto::default(),
+        SignedTransactionProto::from(txns[1].clone()),
+        SignedTransactionProto::default(),
+        SignedTransactionProto::default(),
+    ];
+    let invalid_txn_log = SecurityLogRateLimiter::new(Duration::from_secs(0));
+
+    let received = counters::PULL_TXNS_RECEIVED_COUNT.get();
+    let decode_failures = counters::PULL_TXNS_DECODE_FAILURES.get();
+    assert_eq!(
+        TestMempoolProxy::decode_transactions(
+            proto_txns,
+            &invalid_txn_log,
+            &SystemClock,
+            1 << 20,
+            &TxnManagerContext::default()
+        ),
+        txns
+    );
+    // Other tests may decode concurrently, the increments are lower bounds.
+    assert!(counters::PULL_TXNS_RECEIVED_COUNT.get() - received >= 5);
+    assert!(counters::PULL_TXNS_DECODE_FAILURES.get() - decode_failures >= 3);
+}
+
+#[test]
+fn test_flatten_exclusions() {
+    let sender = AccountAddress::random();
+    let first = vec![create_signed_txn(sender, 0), create_signed_txn(sender, 1)];
+    let second = vec![create_signed_txn(sender, 1)];
+    // Duplicates are kept, they are only dropped when building the request.
+    assert_eq!(
+        TestMempoolProxy::flatten_exclusions(vec![&first, &second], false),
+        vec![(sender, 0, None), (sender, 1, None), (sender, 1, None)]
+    );
+    let with_hash = TestMempoolProxy::flatten_exclusions(vec![&second], true);
+    assert_eq!(
+        with_hash,
+        vec![(
+            sender,
+            1,
+            Some(Transaction::UserTransaction(second[0].clone()).hash())
+        )]
+    );
+}
+
+#[test]
+fn test_pull_txns_with_exclusions() {
+    let sender = AccountAddress::random();
+    // Mempool ignores the exclusion, the excluded txn is dropped from the block.
+    let mempool = Arc::new(MockMempool::new(vec![
+        create_signed_txn(sender, 0),
+        create_signed_txn(sender, 1),
+    ]));
+    let mut config = MempoolProxyConfig::default();
+    config.exclude_by_hash = true;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns_with_exclusions(10, &[(sender, 0), (sender, 0)]))
+        .expect("Failed to pull txns");
+    assert_eq!(pulled.len(), 1);
+    assert_eq!(pulled[0].sequence_number(), 1);
+
+    let requests = mempool.get_block_requests.lock().unwrap();
+    let excluded: Vec<_> = requests[0]
+        .transactions
+        .iter()
+        .map(|txn| (txn.sender.clone(), txn.sequence_number, txn.hash.clone()))
+        .collect();
+    assert_eq!(excluded, vec![(sender.as_ref().to_vec(), 0, vec![])]);
+}
+
+/// Drain recording the message and the key-value pairs of every record logged through it.
+#[derive(Clone, Default)]
+struct CapturingDrain(Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>);
+
+impl slog::Drain for CapturingDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        _values: &slog::OwnedKVList,
+    ) -> std::result::Result<(), slog::Never> {
+        let mut fields = FieldCollector::default();
+        slog::KV::serialize(record.kv(), record, &mut fields).expect("Fields are collected");
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.msg().to_string(), fields.0));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct FieldCollector(Vec<(String, String)>);
+
+impl slog::Serializer for FieldCollector {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        self.0.push((key.to_string(), val.to_string()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_log_context() {
+    let sender = AccountAddress::random();
+    let mempool = Arc::new(MockMempool::new(vec![create_signed_txn(sender, 0)]));
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let block_id = HashValue::random();
+    let context = TxnManagerContext {
+        round: Some(7),
+        block_id: Some(block_id),
+    };
+
+    let drain = CapturingDrain::default();
+    let logger = slog::Logger::root(drain.clone(), slog::o!());
+    let mut runtime = Runtime::new().unwrap();
+    slog_scope::scope(&logger, || {
+        let proxy = proxy.with_context(context);
+        let pulled = runtime
+            .block_on(proxy.pull_txns(10, vec![]))
+            .expect("Failed to pull txns");
+        runtime
+            .block_on(proxy.commit_txns_with_summary(
+                &pulled,
+                &compute_result(vec![keep(), keep()]),
+                1,
+            ))
+            .expect("Failed to commit txns");
+    });
+
+    let records = drain.0.lock().unwrap();
+    for message in &["Pulling at most 10 txns", "Committed block to mempool"] {
+        let (_, fields) = records
+            .iter()
+            .find(|(msg, _)| msg.starts_with(message))
+            .unwrap_or_else(|| panic!("No log line starting with {:?}", message));
+        assert!(fields.contains(&("round".to_string(), "7".to_string())));
+        assert!(fields.contains(&("block_id".to_string(), format!("{:x}", block_id))));
+    }
+}
+
+#[test]
+fn test_pull_txns_retry_backoff() {
+    let clock = Arc::new(MockClock::new());
+    let mempool = Arc::new(SlowMempool {
+        inner: MockMempool::default(),
+        clock: Arc::clone(&clock),
+        latency: Duration::from_millis(0),
+        failures: Mutex::new(3),
+    });
+    let mut config = MempoolProxyConfig::default();
+    config.pull_max_retries = 3;
+    config.pull_retry_base_delay_ms = 10;
+    let proxy = MempoolProxy::with_clock(Arc::clone(&mempool), &config, clock.clone());
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(proxy.pull_txns(10, vec![]))
+        .expect("Failed to pull txns");
+    // The backoff doubles on every retry, without waiting for it to actually elapse.
+    assert_eq!(
+        *clock.sleeps.lock().unwrap(),
+        vec![
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            Duration::from_millis(40),
+        ]
+    );
+
+    // Past the max number of retries, the pull fails.
+    *mempool.failures.lock().unwrap() = 4;
+    match runtime.block_on(proxy.pull_txns_stream(10, vec![]).collect::<Vec<_>>())[..] {
+        [Err(TxnManagerError::Connection(_))] => (),
+        ref pulled => panic!("Unexpected pull: {:?}", pulled),
+    }
+}
+
+#[test]
+fn test_pull_txns_adaptive_size_latency() {
+    let clock = Arc::new(MockClock::new());
+    let mempool = Arc::new(SlowMempool {
+        inner: MockMempool::default(),
+        clock: Arc::clone(&clock),
+        latency: Duration::from_millis(2000),
+        failures: Mutex::new(0),
+    });
+    let mut config = MempoolProxyConfig::default();
+    config.adaptive_pull = AdaptivePullConfig {
+        enabled: true,
+        slow_latency_ms: 1000,
+        min_size: 10,
+        max_size: 50,
+    };
+    let proxy = MempoolProxy::with_clock(Arc::clone(&mempool), &config, clock);
+
+    let mut runtime = Runtime::new().unwrap();
+    for _ in 0..3 {
+        runtime
+            .block_on(proxy.pull_txns(100, vec![]))
+            .expect("Failed to pull txns");
+    }
+    // Every pull takes 2s on the clock, which halves the size of the next one.
+    let sizes: Vec<_> = mempool
+        .inner
+        .get_block_requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|req| req.max_block_size)
+        .collect();
+    assert_eq!(sizes, vec![50, 25, 12]);
+}
+
+#[test]
+fn test_commit_txns_dedup() {
+    let mempool = Arc::new(MockMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+    let skipped = counters::COMMIT_TXNS_DEDUP_SKIPPED_COUNT.get();
+
+    // Repeating a confirmed commit reports the same summary without reaching mempool.
+    let summary =
+        block_on(proxy.commit_txns_with_summary(&txns, &result, 1)).expect("Failed to commit");
+    let repeated =
+        block_on(proxy.commit_txns_with_summary(&txns, &result, 1)).expect("Failed to commit");
+    assert_eq!(repeated, summary);
+    assert_eq!(mempool.commit_requests.lock().unwrap().len(), 1);
+    assert!(counters::COMMIT_TXNS_DEDUP_SKIPPED_COUNT.get() >= skipped + 1);
+
+    // The same transactions in a block with another id or timestamp are committed again.
+    let context = TxnManagerContext {
+        round: Some(2),
+        block_id: Some(HashValue::random()),
+    };
+    block_on(
+        proxy
+            .with_context(context)
+            .commit_txns_with_summary(&txns, &result, 1),
+    )
+    .expect("Failed to commit");
+    block_on(proxy.commit_txns_with_summary(&txns, &result, 2)).expect("Failed to commit");
+    assert_eq!(mempool.commit_requests.lock().unwrap().len(), 3);
+    block_on(
+        proxy
+            .with_context(context)
+            .commit_txns_with_summary(&txns, &result, 1),
+    )
+    .expect("Failed to commit");
+    assert_eq!(mempool.commit_requests.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_commit_txns_dedup_in_flight() {
+    let mempool = Arc::new(GatedMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+
+    // A duplicate called while the first commit is in flight waits for it instead of being sent.
+    let mut first = proxy.commit_txns_with_summary(&txns, &result, 1);
+    assert!((&mut first).now_or_never().is_none());
+    let mut second = proxy.commit_txns_with_summary(&txns, &result, 1);
+    assert!((&mut second).now_or_never().is_none());
+    mempool.release();
+    let summary = block_on(first).expect("Failed to commit");
+    assert_eq!(block_on(second).expect("Failed to commit"), summary);
+    assert_eq!(mempool.inner.commit_requests.lock().unwrap().len(), 1);
+
+    // A duplicate of a commit which does not complete fails, and the commit can be sent again.
+    let mut third = proxy.commit_txns_with_summary(&txns, &result, 2);
+    assert!((&mut third).now_or_never().is_none());
+    let fourth = proxy.commit_txns_with_summary(&txns, &result, 2);
+    drop(third);
+    match block_on(fourth) {
+        Err(TxnManagerError::InvalidRequest(_)) => (),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    let mut retried = proxy.commit_txns_with_summary(&txns, &result, 2);
+    assert!((&mut retried).now_or_never().is_none());
+    mempool.release();
+    block_on(retried).expect("Failed to commit");
+    assert_eq!(mempool.inner.commit_requests.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_commit_dedup_guard_reservation() {
+    let summary = CommitSummary {
+        kept: 1,
+        discarded: 0,
+    };
+    let key = (HashValue::zero(), 1);
+    let mut guard = CommitDedupGuard::new(2);
+    assert_eq!(guard.reserve(key), CommitReservation::Reserved);
+    assert_eq!(guard.reserve(key), CommitReservation::InFlight);
+    guard.release(&key);
+    assert_eq!(guard.reserve(key), CommitReservation::Reserved);
+    guard.confirm(key, summary);
+    assert_eq!(guard.reserve(key), CommitReservation::Confirmed(summary));
+
+    let mut disabled = CommitDedupGuard::new(0);
+    assert_eq!(disabled.reserve(key), CommitReservation::Reserved);
+    assert_eq!(disabled.reserve(key), CommitReservation::Reserved);
+}
+
+#[test]
+fn test_commit_dedup_guard_window() {
+    let summary = CommitSummary {
+        kept: 1,
+        discarded: 0,
+    };
+    let key = |timestamp_usecs| (HashValue::zero(), timestamp_usecs);
+    let mut guard = CommitDedupGuard::new(2);
+    guard.confirm(key(1), summary);
+    guard.confirm(key(2), summary);
+    // Looking up the first commit makes the second one the least recently used.
+    assert_eq!(guard.get(&key(1)), Some(summary));
+    guard.confirm(key(3), summary);
+    assert_eq!(guard.get(&key(2)), None);
+    assert_eq!(guard.get(&key(1)), Some(summary));
+    assert_eq!(guard.get(&key(3)), Some(summary));
+
+    let mut disabled = CommitDedupGuard::new(0);
+    disabled.confirm(key(1), summary);
+    assert_eq!(disabled.get(&key(1)), None);
+}
+
+#[test]
+fn test_pull_txns_validator() {
+    let (private_key, public_key) = compat::generate_keypair(None);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    let create_txn = |expiration_time| {
+        RawTransaction::new_script(
+            AccountAddress::random(),
+            0,
+            Script::new(vec![], vec![]),
+            0,
+            0,
+            expiration_time,
+        )
+        .sign(&private_key, public_key.clone())
+        .expect("Failed to sign txn")
+        .into_inner()
+    };
+    let expired = create_txn(now - Duration::from_secs(60));
+    let live = create_txn(now + Duration::from_secs(60));
+    let mempool = Arc::new(MockMempool::new(vec![expired, live.clone()]));
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default()).with_txn_validator(
+        Arc::new(move |txn: &SignedTransaction| txn.expiration_time() > now),
+    );
+
+    let rejected = counters::PULL_TXNS_VALIDATION_REJECTED_COUNT.get();
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(10, vec![]))
+        .expect("Failed to pull txns");
+    assert_eq!(pulled, vec![live]);
+    assert!(counters::PULL_TXNS_VALIDATION_REJECTED_COUNT.get() > rejected);
+}
+
+/// Receiver of a grpc call resolving once `ready` is set, recording whether it was cancelled.
+#[derive(Default)]
+struct StubReceiver {
+    ready: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Future01 for StubReceiver {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll01<(), ()> {
+        if self.ready.load(Ordering::SeqCst) {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl CancellableReceiver for StubReceiver {
+    fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_grpc_receiver_cancelled_on_drop() {
+    let pending = StubReceiver::default();
+    let cancelled = Arc::clone(&pending.cancelled);
+    let mut receiver = GrpcReceiver::new(pending);
+    assert!((&mut receiver).now_or_never().is_none());
+    drop(receiver);
+    assert!(cancelled.load(Ordering::SeqCst));
+
+    // A completed call is left alone.
+    let completed = StubReceiver::default();
+    completed.ready.store(true, Ordering::SeqCst);
+    let cancelled = Arc::clone(&completed.cancelled);
+    let mut receiver = GrpcReceiver::new(completed);
+    assert_eq!((&mut receiver).now_or_never(), Some(Ok(())));
+    drop(receiver);
+    assert!(!cancelled.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_dropped_futures_have_no_side_effects() {
+    // The pulled txn is oversized, processing the response would log it as invalid.
+    let mempool = Arc::new(GatedMempool {
+        inner: MockMempool::new(vec![create_signed_txn(AccountAddress::random(), 0)]),
+        ..GatedMempool::default()
+    });
+    let mut config = MempoolProxyConfig::default();
+    config.max_txn_bytes = 1;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let result = compute_result(vec![keep(), keep()]);
+
+    let drain = CapturingDrain::default();
+    let logger = slog::Logger::root(drain.clone(), slog::o!());
+    let mut runtime = Runtime::new().unwrap();
+    slog_scope::scope(&logger, || {
+        let mut pull = proxy.pull_txns(10, vec![]);
+        let mut commit = proxy.commit_txns(&txns, &result, 1);
+        runtime.block_on(future::lazy(|_| {
+            assert!((&mut pull).now_or_never().is_none());
+            assert!((&mut commit).now_or_never().is_none());
+        }));
+        assert_eq!(mempool.gates.lock().unwrap().len(), 2);
+
+        // The round is abandoned before mempool responds.
+        drop(pull);
+        drop(commit);
+        assert!(mempool
+            .gates
+            .lock()
+            .unwrap()
+            .iter()
+            .all(oneshot::Sender::is_canceled));
+        mempool.release();
+    });
+
+    let records = drain.0.lock().unwrap();
+    assert!(!records
+        .iter()
+        .any(|(msg, _)| msg.starts_with("[security]")
+            || msg.starts_with("Committed block to mempool")));
+    // No invalid txn was accounted for by the rate limiter of the security log either.
+    assert_eq!(*proxy.invalid_txn_log.state.lock().unwrap(), (None, 0));
+    // The dropped commit was not confirmed, committing the block again reaches mempool.
+    let mut commit = proxy.commit_txns(&txns, &result, 1);
+    assert!((&mut commit).now_or_never().is_none());
+    mempool.release();
+    block_on(commit).expect("Failed to commit txns");
+    assert_eq!(mempool.inner.commit_requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_pull_txns_recently_pulled() {
+    let txns: Vec<_> = (0..4)
+        .map(|_| create_signed_txn(AccountAddress::random(), 0))
+        .collect();
+    let mempool = Arc::new(MockMempool::new(txns[..2].to_vec()));
+    let mut config = MempoolProxyConfig::default();
+    config.recently_pulled_window = 3;
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config);
+    let mut runtime = Runtime::new().unwrap();
+    let mut pull = || {
+        runtime
+            .block_on(proxy.pull_txns(10, vec![]))
+            .expect("Failed to pull txns")
+    };
+
+    let dropped = counters::PULL_TXNS_RECENTLY_PULLED_COUNT.get();
+    assert_eq!(pull(), txns[..2].to_vec());
+    // The second txn raced with the exclusions of the pull and is returned again.
+    mempool.set_block(txns[1..3].to_vec());
+    assert_eq!(pull(), vec![txns[2].clone()]);
+    assert!(counters::PULL_TXNS_RECENTLY_PULLED_COUNT.get() > dropped);
+    // Pulling the last txn evicts the first one, the least recently pulled, from the window.
+    mempool.set_block(txns[3..].to_vec());
+    assert_eq!(pull(), vec![txns[3].clone()]);
+    mempool.set_block(vec![txns[0].clone(), txns[2].clone()]);
+    assert_eq!(pull(), vec![txns[0].clone()]);
+
+    // A txn returned by every pull is not kept in the window by being pulled again, it is pulled
+    // once the window moved past it.
+    let stuck = create_signed_txn(AccountAddress::random(), 0);
+    mempool.set_block(vec![stuck.clone()]);
+    assert_eq!(pull(), vec![stuck.clone()]);
+    for _ in 0..3 {
+        let fresh = create_signed_txn(AccountAddress::random(), 0);
+        mempool.set_block(vec![stuck.clone(), fresh.clone()]);
+        assert_eq!(pull(), vec![fresh]);
+    }
+    mempool.set_block(vec![stuck.clone()]);
+    assert_eq!(pull(), vec![stuck]);
+
+    // The window is disabled by default.
+    let mempool = Arc::new(MockMempool::new(txns.clone()));
+    let proxy = MempoolProxy::new(mempool, &MempoolProxyConfig::default());
+    for _ in 0..2 {
+        let pulled = runtime
+            .block_on(proxy.pull_txns(10, vec![]))
+            .expect("Failed to pull txns");
+        assert_eq!(pulled, txns);
+    }
+}
+
+#[test]
+fn test_proxy_stats() {
+    let clock = Arc::new(MockClock::new());
+    let txns = vec![create_signed_txn(AccountAddress::random(), 0)];
+    let mempool = Arc::new(SlowMempool {
+        inner: MockMempool::new(txns.clone()),
+        clock: Arc::clone(&clock),
+        latency: Duration::from_millis(20),
+        failures: Mutex::new(0),
+    });
+    let mut config = MempoolProxyConfig::default();
+    config.max_block_size_limit = 5;
+    let proxy = MempoolProxy::with_clock(mempool, &config, clock);
+    assert_eq!(proxy.stats(), ProxyStats::default());
+
+    let mut runtime = Runtime::new().unwrap();
+    let pulled = runtime
+        .block_on(proxy.pull_txns(1000, vec![]))
+        .expect("Failed to pull txns");
+    runtime
+        .block_on(proxy.commit_txns(&pulled, &compute_result(vec![keep(), keep()]), 1))
+        .expect("Failed to commit txns");
+    assert_eq!(
+        proxy.stats(),
+        ProxyStats {
+            in_flight_requests: 0,
+            last_pull_latency: Duration::from_millis(20),
+            last_commit_latency: Duration::from_millis(0),
+            effective_max_size: 5,
+            total_pulls: 1,
+            total_commits: 1,
+        }
+    );
+
+    // The stats are read while requests are in flight.
+    let mempool = Arc::new(GatedMempool::default());
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &MempoolProxyConfig::default());
+    let mut pull = proxy.pull_txns(10, vec![]);
+    let mut commit = proxy.commit_txns(&txns, &compute_result(vec![keep(), keep()]), 1);
+    runtime.block_on(future::lazy(|_| {
+        assert!((&mut pull).now_or_never().is_none());
+        assert!((&mut commit).now_or_never().is_none());
+    }));
+    let stats = proxy.stats();
+    assert_eq!(stats.in_flight_requests, 2);
+    assert_eq!((stats.total_pulls, stats.total_commits), (0, 0));
+    mempool.release();
+    runtime.block_on(pull).expect("Failed to pull txns");
+    runtime.block_on(commit).expect("Failed to commit txns");
+    let stats = proxy.stats();
+    assert_eq!(stats.in_flight_requests, 0);
+    assert_eq!((stats.total_pulls, stats.total_commits), (1, 1));
+}
+
+#[test]
+fn test_pull_all() {
+    let txns: Vec<_> = (0..5)
+        .map(|_| create_signed_txn(AccountAddress::random(), 0))
+        .collect();
+    let pending = vec![txns[4].clone()];
+    let mut config = MempoolProxyConfig::default();
+    config.max_block_size_limit = 2;
+    let mut runtime = Runtime::new().unwrap();
+    let pull_all =
+        |runtime: &mut Runtime, config: &MempoolProxyConfig, pages: &[&[SignedTransaction]]| {
+            let mempool = Arc::new(PagedMempool {
+                pages: Mutex::new(pages.iter().map(|page| page.to_vec()).collect()),
+                ..PagedMempool::default()
+            });
+            let proxy = MempoolProxy::new(Arc::clone(&mempool), config);
+            let pulled = runtime
+                .block_on(proxy.pull_all(vec![&pending]))
+                .expect("Failed to pull txns");
+            let requests = mempool.inner.get_block_requests.lock().unwrap().clone();
+            (pulled, requests)
+        };
+    let excluded = |request: &GetBlockRequest| -> Vec<_> {
+        request
+            .transactions
+            .iter()
+            .map(|txn| txn.sender.clone())
+            .collect()
+    };
+    let senders = |txns: &[SignedTransaction]| -> Vec<_> {
+        txns.iter()
+            .map(|txn| txn.sender().as_ref().to_vec())
+            .collect()
+    };
+
+    // Full pages are pulled until mempool is empty, excluding the txns of the previous ones.
+    let (pulled, requests) = pull_all(&mut runtime, &config, &[&txns[..2], &txns[2..4]]);
+    assert_eq!(pulled, txns[..4].to_vec());
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|request| request.max_block_size == 2));
+    assert_eq!(excluded(&requests[0]), senders(&txns[4..]));
+    assert_eq!(
+        excluded(&requests[1]),
+        senders(&[&txns[4..], &txns[..2]].concat())
+    );
+    assert_eq!(
+        excluded(&requests[2]),
+        senders(&[&txns[4..], &txns[..4]].concat())
+    );
+
+    // A page smaller than requested is the last one.
+    let (pulled, requests) = pull_all(&mut runtime, &config, &[&txns[..2], &txns[2..3]]);
+    assert_eq!(pulled, txns[..3].to_vec());
+    assert_eq!(requests.len(), 2);
+
+    // Pulls stop at the safety cap.
+    config.pull_all_max_txns = 3;
+    let (pulled, requests) = pull_all(&mut runtime, &config, &[&txns[..2], &txns[2..4]]);
+    assert_eq!(pulled, txns[..3].to_vec());
+    let sizes: Vec<_> = requests.iter().map(|req| req.max_block_size).collect();
+    assert_eq!(sizes, vec![2, 1]);
+}
+
+#[test]
+fn test_pull_all_dropped_txns() {
+    let rejected: Vec<_> = (0..2)
+        .map(|_| create_signed_txn(AccountAddress::random(), 0))
+        .collect();
+    let kept = create_signed_txn(AccountAddress::random(), 0);
+    let rejected_senders: HashSet<_> = rejected.iter().map(SignedTransaction::sender).collect();
+    let validator: TxnValidator =
+        Arc::new(move |txn: &SignedTransaction| !rejected_senders.contains(&txn.sender()));
+    let mut config = MempoolProxyConfig::default();
+    config.max_block_size_limit = 2;
+    let mut runtime = Runtime::new().unwrap();
+
+    // A full page of dropped txns is excluded from the next pages.
+    let mempool = Arc::new(PagedMempool {
+        pages: Mutex::new(vec![rejected.clone(), vec![kept.clone()]].into()),
+        ..PagedMempool::default()
+    });
+    let proxy =
+        MempoolProxy::new(Arc::clone(&mempool), &config).with_txn_validator(validator.clone());
+    let pulled = runtime
+        .block_on(proxy.pull_all(vec![]))
+        .expect("Failed to pull txns");
+    assert_eq!(pulled, vec![kept]);
+    let requests = mempool.inner.get_block_requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 2);
+    let excluded: Vec<_> = requests[1]
+        .transactions
+        .iter()
+        .map(|txn| txn.sender.clone())
+        .collect();
+    let expected: Vec<_> = rejected
+        .iter()
+        .map(|txn| txn.sender().as_ref().to_vec())
+        .collect();
+    assert_eq!(excluded, expected);
+
+    // A mempool returning the same full page of dropped txns over and over is pulled a bounded
+    // number of times.
+    let mempool = Arc::new(MockMempool::new(rejected));
+    let proxy = MempoolProxy::new(Arc::clone(&mempool), &config).with_txn_validator(validator);
+    let pulled = runtime
+        .block_on(proxy.pull_all(vec![]))
+        .expect("Failed to pull txns");
+    assert!(pulled.is_empty());
+    assert_eq!(
+        mempool.get_block_requests.lock().unwrap().len(),
+        PULL_ALL_MAX_PAGES
+    );
+}
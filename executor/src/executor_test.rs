@@ -43,7 +43,8 @@ fn get_config() -> NodeConfig {
             config
                 .consensus
                 .consensus_peers
-                .get_validator_set(&config.networks[0].network_peers),
+                .get_validator_set(&config.networks[0].network_peers)
+                .expect("Invalid validator set"),
         )
         .into();
     let mut file = File::create(config.get_genesis_transaction_file()).unwrap();
@@ -117,7 +117,7 @@ impl Config {
         // generate a validator set with |validator_accounts| validators
         let (validator_keys, consensus_config, network_config) =
             ConfigHelpers::gen_validator_nodes(validator_accounts, None);
-        let validator_set = consensus_config.get_validator_set(&network_config);
+        let validator_set = consensus_config.get_validator_set(&network_config)?;
 
         // initialize the keys of validator entries with the validator set
         // enhance type of config to contain a validator set, use it to initialize genesis
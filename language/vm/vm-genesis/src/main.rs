@@ -19,7 +19,9 @@ fn generate_genesis_blob() -> Vec<u8> {
         encode_genesis_transaction_with_validator(
             &GENESIS_KEYPAIR.0,
             GENESIS_KEYPAIR.1.clone(),
-            consensus_config.get_validator_set(&network_config),
+            consensus_config
+                .get_validator_set(&network_config)
+                .expect("Generated validators have ed25519 network signing keys"),
         )
         .into();
 
@@ -5,9 +5,13 @@ use admission_control_service::runtime::AdmissionControlRuntime;
 use consensus::consensus_provider::{make_consensus_provider, ConsensusProvider};
 use debug_interface::{node_debug_service::NodeDebugService, proto::create_node_debug_interface};
 use executor::Executor;
+use failure::prelude::*;
 use grpc_helpers::ServerHandle;
 use grpcio::EnvBuilder;
-use libra_config::config::{NetworkConfig, NodeConfig, RoleType};
+use libra_config::{
+    config::{NetworkConfig, NodeConfig, RoleType, SeedPeersResolution},
+    trusted_peers::{NetworkPeersConfig, TrustedPeersSource},
+};
 use libra_crypto::{ed25519::*, ValidKey};
 use libra_logger::prelude::*;
 use libra_mempool::MempoolRuntime;
@@ -94,10 +98,13 @@ fn setup_debug_interface(config: &NodeConfig) -> ::grpcio::Server {
 }
 
 // TODO(abhayb): Move to network crate (similar to consensus).
+/// The trusted peers of a permissioned network are fetched from `trusted_peers` when set, and
+/// are the network peers of `config` otherwise.
 pub fn setup_network(
     peer_id: PeerId,
     config: &mut NetworkConfig,
-) -> (Runtime, Box<dyn LibraNetworkProvider>) {
+    trusted_peers: Option<&dyn TrustedPeersSource<NetworkPeersConfig>>,
+) -> Result<(Runtime, Box<dyn LibraNetworkProvider>)> {
     let runtime = Builder::new()
         .thread_name("network-")
         .threaded_scheduler()
@@ -129,24 +136,42 @@ pub fn setup_network(
             config.enable_encryption_and_authentication,
             "Permissioned network end-points should use authentication"
         );
-        let trusted_peers = config
-            .network_peers
+        let network_peers = match trusted_peers {
+            Some(source) => source.fetch().map_err(|e| {
+                format_err!("Failed to fetch the trusted peers of the network: {}", e)
+            })?,
+            None => config.network_peers.clone(),
+        };
+        let trusted_peers = network_peers
             .peers
             .iter()
-            .map(|(peer_id, keys)| {
-                (
+            .filter_map(|(peer_id, keys)| {
+                // The network only authenticates peers by ed25519 signing keys.
+                let signing_public_key = match keys.network_signing_pubkey.to_ed25519() {
+                    Some(signing_public_key) => signing_public_key,
+                    None => {
+                        error!(
+                            "Skipping trusted peer {} with an unsupported {} network signing key",
+                            peer_id, keys.network_signing_pubkey.algorithm
+                        );
+                        return None;
+                    }
+                };
+                Some((
                     PeerId::from_str(peer_id).unwrap(),
                     NetworkPublicKeys {
-                        signing_public_key: keys.network_signing_pubkey.clone(),
+                        signing_public_key,
                         identity_public_key: keys.network_identity_pubkey.clone(),
                     },
-                )
+                ))
             })
             .collect();
-        let seed_peers = config
-            .seed_peers
+        let mut seed_peers_config = config.seed_peers.clone();
+        if config.seed_peers_resolution == SeedPeersResolution::Lazy {
+            seed_peers_config.resolve();
+        }
+        let seed_peers = seed_peers_config
             .seed_peers
-            .clone()
             .into_iter()
             .map(|(peer_id, addrs)| (peer_id.try_into().expect("Invalid PeerId"), addrs))
             .collect();
@@ -172,10 +197,20 @@ pub fn setup_network(
         network_builder.transport(TransportType::Tcp);
     }
     let (_listen_addr, network_provider) = network_builder.build();
-    (runtime, network_provider)
+    Ok((runtime, network_provider))
 }
 
 pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
+    setup_environment_with_trusted_peers(node_config, None)
+        .expect("The network peers of the config are always available")
+}
+
+/// Same as `setup_environment`, with the trusted peers of the validator network fetched from
+/// `network_peers` rather than taken from the config. Fails if they cannot be fetched.
+pub fn setup_environment_with_trusted_peers(
+    node_config: &mut NodeConfig,
+    network_peers: Option<Arc<dyn TrustedPeersSource<NetworkPeersConfig>>>,
+) -> Result<LibraHandle> {
     crash_handler::setup_panic_handler();
 
     // Some of our code uses the rayon global thread pool. Name the rayon threads so it doesn't
@@ -204,7 +239,12 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
     for i in 0..node_config.networks.len() {
         let peer_id =
             PeerId::try_from(node_config.networks[i].peer_id.clone()).expect("Invalid PeerId");
-        let (runtime, mut network_provider) = setup_network(peer_id, &mut node_config.networks[i]);
+        let trusted_peers = match node_config.networks[i].role {
+            RoleType::Validator => network_peers.as_ref().map(|source| &**source),
+            RoleType::FullNode => None,
+        };
+        let (runtime, mut network_provider) =
+            setup_network(peer_id, &mut node_config.networks[i], trusted_peers)?;
         state_sync_network_handles.push(network_provider.add_state_synchronizer(vec![
             ProtocolId::from_static(STATE_SYNCHRONIZER_DIRECT_SEND_PROTOCOL),
         ]));
@@ -312,7 +352,7 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         debug!("Consensus started in {} ms", instant.elapsed().as_millis());
     }
 
-    LibraHandle {
+    Ok(LibraHandle {
         _network_runtimes: network_runtimes,
         _ac: admission_control,
         _mempool: mempool,
@@ -320,5 +360,5 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         consensus,
         _storage: storage,
         _debug: debug_if,
-    }
+    })
 }
@@ -15,11 +15,15 @@ use crate::{
 };
 use chrono::Utc;
 use libra_config::config::NodeConfig;
+use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_logger::prelude::*;
 use libra_mempool_shared_proto::{
     proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
 };
-use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{SignedTransaction, Transaction},
+};
 use lru_cache::LruCache;
 use std::{cmp::max, collections::HashSet, convert::TryFrom};
 use ttl_cache::TtlCache;
@@ -163,6 +167,17 @@ impl Mempool {
         status
     }
 
+    /// Hash of the transaction held for given account address + sequence number
+    pub(crate) fn get_transaction_hash(
+        &self,
+        address: &AccountAddress,
+        sequence_number: u64,
+    ) -> Option<HashValue> {
+        self.transactions
+            .get(address, sequence_number)
+            .map(|txn| Transaction::UserTransaction(txn).hash())
+    }
+
     /// Fetches next block of transactions for consensus
     /// `batch_size` - size of requested block
     /// `seen_txns` - transactions that were sent to Consensus but were not committed yet
@@ -82,19 +82,27 @@ impl Mempool for MempoolService {
 
         let block_size = cmp::max(req.max_block_size, 1);
         OP_COUNTERS.inc_by("get_block.requested", block_size as usize);
+        let mut pool = self
+            .core_mempool
+            .lock()
+            .expect("[get_block] acquire mempool lock");
         let exclude_transactions: HashSet<TxnPointer> = req
             .transactions
             .iter()
-            .map(|t| (AccountAddress::try_from(&t.sender[..]), t.sequence_number))
+            .map(|t| (AccountAddress::try_from(&t.sender[..]), t))
             .filter(|(address, _)| address.is_ok())
-            .map(|(address, seq)| (address.unwrap(), seq))
+            .map(|(address, t)| (address.unwrap(), t))
+            // an exclusion carrying a hash does not apply to a replacement of the excluded txn
+            .filter(|(address, t)| {
+                t.hash.is_empty()
+                    || pool
+                        .get_transaction_hash(address, t.sequence_number)
+                        .map_or(true, |hash| hash.to_vec() == t.hash)
+            })
+            .map(|(address, t)| (address, t.sequence_number))
             .collect();
 
-        let mut txns = self
-            .core_mempool
-            .lock()
-            .expect("[get_block] acquire mempool lock")
-            .get_block(block_size, exclude_transactions);
+        let mut txns = pool.get_block(block_size, exclude_transactions);
 
         let transactions = txns.drain(..).map(SignedTransaction::into).collect();
 
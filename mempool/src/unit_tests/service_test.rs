@@ -5,12 +5,12 @@ use crate::{core_mempool::CoreMempool, mempool_service::MempoolService, proto::m
 use grpc_helpers::ServerHandle;
 use grpcio::{ChannelBuilder, EnvBuilder};
 use libra_config::config::NodeConfigHelpers;
-use libra_crypto::ed25519::compat::generate_keypair;
+use libra_crypto::{ed25519::compat::generate_keypair, hash::CryptoHash};
 use libra_mempool_shared_proto::proto::mempool_status::*;
 use libra_types::{
     account_address::AccountAddress,
     test_helpers::transaction_test_helpers::get_test_signed_transaction,
-    transaction::SignedTransaction,
+    transaction::{SignedTransaction, Transaction},
 };
 use std::{
     convert::TryFrom,
@@ -147,3 +147,30 @@ fn test_gc_by_expiration_time() {
     let response = client.get_block(&GetBlockRequest::default()).unwrap();
     assert_eq!(response.block.unwrap().transactions.len(), 1);
 }
+
+#[test]
+fn test_get_block_exclude_by_hash() {
+    let (server, client) = setup_mempool();
+    let _handle = ServerHandle::setup(server);
+
+    let add_req = create_add_transaction_request(0);
+    client.add_transaction_with_validation(&add_req).unwrap();
+    let signed_txn = SignedTransaction::try_from(add_req.transaction.unwrap()).unwrap();
+
+    let mut exclusion = TransactionExclusion::default();
+    exclusion.sender = signed_txn.sender().as_ref().to_vec();
+    exclusion.sequence_number = 0;
+    let mut req = GetBlockRequest::default();
+    req.max_block_size = 10;
+
+    // the exclusion of a replaced transaction does not apply
+    exclusion.hash = vec![0; 32];
+    req.transactions = vec![exclusion.clone()];
+    let response = client.get_block(&req).unwrap();
+    assert_eq!(response.block.unwrap().transactions.len(), 1);
+
+    exclusion.hash = Transaction::UserTransaction(signed_txn).hash().to_vec();
+    req.transactions = vec![exclusion];
+    let response = client.get_block(&req).unwrap();
+    assert!(response.block.unwrap().transactions.is_empty());
+}
@@ -5,6 +5,7 @@ use crate::{
     common::NetworkPublicKeys,
     protocols::identity::{exchange_identity, Identity},
 };
+use libra_config::keys::constant_time_eq_bytes;
 use libra_crypto::{
     x25519::{X25519StaticPrivateKey, X25519StaticPublicKey},
     ValidKey,
@@ -42,7 +43,12 @@ fn identity_key_to_peer_id(
     remote_static_key: &[u8],
 ) -> Option<PeerId> {
     for (peer_id, public_keys) in trusted_peers.read().unwrap().iter() {
-        if public_keys.identity_public_key.to_bytes() == remote_static_key {
+        // Compared in constant time, so that the time to reject a key does not leak the trusted
+        // keys.
+        if constant_time_eq_bytes(
+            &public_keys.identity_public_key.to_bytes(),
+            remote_static_key,
+        ) {
             return Some(*peer_id);
         }
     }